@@ -3,6 +3,7 @@ use simple_logger::SimpleLogger;
 use rand::Rng;
 use measure::Measure;
 use engine::MatchEngine;
+use match_base::Symbols;
 #[allow(unused_imports)]
 use log::{error, info, warn, LevelFilter};
 
@@ -20,6 +21,10 @@ struct Args {
     /// Number of times to greet
     #[clap(short, long, default_value_t = 2_000_000)]
     count: u32,
+
+    /// Instrument master csv
+    #[clap(short, long)]
+    symbols: Option<String>,
 }
 
 fn main() {
@@ -28,7 +33,14 @@ fn main() {
     log::set_max_level(LevelFilter::Info);
     info!("{} start!", args.name);
     let sym_name = "cu1906";
-    let mut me = MatchEngine::new();
+    let mut me = if let Some(filen) = &args.symbols {
+        let syms = Symbols::load_csv(filen).unwrap_or_else(|e| {
+            panic!("load symbols {} failed: {}", filen, e)
+        });
+        MatchEngine::with_symbols(syms)
+    } else {
+        MatchEngine::new()
+    };
     let instr: u32;
     if let Some(idx) = me.symbol_idx(&sym_name) {
         instr = idx;
@@ -36,33 +48,46 @@ fn main() {
         warn!("symbols {} not FOUND", &sym_name);
         instr = 0;
     }
-    assert!(me.begin_market());
-    assert!(me.start_market());
-    me.load_orders(instr, &args.file1);
-    me.load_orders(instr, &args.file2);
+    me.begin_market().unwrap();
+    me.start_market().unwrap();
+    me.set_ref_price(instr, 50000).unwrap();
+    for filen in [&args.file1, &args.file2] {
+        if let Err(e) = me.load_orders(instr, filen) {
+            warn!("load orders from {} failed: {}", filen, e);
+        }
+    }
     assert!(args.count >= 10000);
-    let mut measure = Measure::start("cross bench");
-    let mc_ret = me.match_cross(instr, 50000);
+    // opening auction, uncross all symbols
+    let mut measure = Measure::start("call auction bench");
+    me.call_auction().unwrap();
     measure.stop();
-    let (last, qty, rem_qty) = mc_ret.unwrap();
-    println!("MatchCross last: {}, volume: {}, remain: {}",
-             last, qty, rem_qty);
-    println!("MatchCross cost {}us", measure.as_us());
-    assert!(me.uncross(instr, last, qty), "uncross failed");
+    for res in me.auction_results() {
+        println!("CallAuction symbol({}) last: {}, volume: {}, remain: {}",
+                 res.symbol(), res.price(), res.qty(), res.remain());
+    }
+    println!("CallAuction cost {}us", measure.as_us());
     // now benchmark trading continue
-    assert!(me.call_auction()); // do nothing currently
-    assert!(me.start_trading());
+    me.start_trading().unwrap();
     let mut rng = rand::thread_rng();
     let cnt = args.count;
+    let (tick, lot) = me.symbol(instr).map_or((1, 1), |s| {
+        (s.price_step() as i32, s.lot_size())
+    });
+    let mut rejects = 0;
     let mut measure = Measure::start("TC bench");
     for _i in 0 .. cnt {
-        // send order
-        let price = (rng.gen::<i32>() % 10000) + 40000;
-        let qty: u32 = (rng.gen::<u32>() % 200) + 1;
+        // send order, price/qty on tick/lot grid
+        let price = ((rng.gen::<i32>() % 10000) + 40000) / tick * tick;
+        let qty: u32 = ((rng.gen::<u32>() % 200) + 1) * lot;
         let buy: bool = (rng.gen::<u32>() & 1) != 0;
-        me.send_order(instr, buy, price, qty);
+        if me.send_order(instr, buy, price, qty).is_err() {
+            rejects += 1;
+        }
     }
     measure.stop();
+    if rejects > 0 {
+        warn!("{} orders rejected", rejects);
+    }
     let ns_ops = measure.as_ns() / (cnt as u64);
     println!("TradingContinue cost {}ms, {} ns per op",
              measure.as_ms(), ns_ops);
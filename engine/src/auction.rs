@@ -0,0 +1,345 @@
+// auction price determination on crossed price levels.
+// candidate prices are limit prices inside [best ask, best bid], price with
+// max executable volume always wins, tie-break rules applied in order then.
+// prices of RefPrice/Midpoint rounded to nearest tick, ties to lower
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum TieBreak {
+    MinSurplus,     // least unmatched qty
+    MarketPressure, // surplus all buy side highest price, all sell lowest
+    RefPrice,       // reference price if inside range, else closest
+    Midpoint,       // middle of remaining price range
+}
+
+#[derive(PartialEq, Eq, Clone, Debug, Default)]
+pub enum PriceRules {
+    #[default]
+    Classic,        // walk book, reference price on equal volume levels
+    Chain(Vec<TieBreak>),
+}
+
+impl PriceRules {
+    // volume, surplus, market pressure, reference price
+    pub fn standard() -> PriceRules {
+        PriceRules::Chain(vec![TieBreak::MinSurplus, TieBreak::MarketPressure,
+                               TieBreak::RefPrice])
+    }
+    // u32 code for journal and snapshot, Classic as 0. chain as nibble
+    // of rule + 1 first rule lowest, ended by 0xf. None over 7 rules
+    pub fn pack(&self) -> Option<u32> {
+        let rules = match self {
+            PriceRules::Classic => return Some(0),
+            PriceRules::Chain(rules) => rules,
+        };
+        if rules.len() > 7 { return None }
+        let mut code = 0xf << (4 * rules.len());
+        for (i, rule) in rules.iter().enumerate() {
+            code |= (*rule as u32 + 1) << (4 * i);
+        }
+        Some(code)
+    }
+    pub fn unpack(code: u32) -> Option<PriceRules> {
+        if code == 0 { return Some(PriceRules::Classic) }
+        let mut rules = Vec::<TieBreak>::new();
+        let mut code = code;
+        while code & 0xf != 0xf {
+            rules.push(match code & 0xf {
+                1 => TieBreak::MinSurplus,
+                2 => TieBreak::MarketPressure,
+                3 => TieBreak::RefPrice,
+                4 => TieBreak::Midpoint,
+                _ => return None,
+            });
+            code >>= 4;
+        }
+        if code != 0xf { return None }
+        Some(PriceRules::Chain(rules))
+    }
+}
+
+// cumulated qty executable at price
+#[derive(Clone, Copy)]
+struct Candidate {
+    price:  i32,
+    bid:    u32,    // bids price >= price
+    ask:    u32,    // asks price <= price
+}
+
+impl Candidate {
+    fn qty(&self) -> u32 {
+        self.bid.min(self.ask)
+    }
+    fn surplus(&self) -> u32 {
+        self.bid.max(self.ask) - self.qty()
+    }
+}
+
+// nearest price on tick grid, ties to lower price
+fn round_tick(price: i32, tick: i32) -> i32 {
+    if tick <= 1 { return price }
+    let rem = price.rem_euclid(tick);
+    if rem * 2 > tick { price - rem + tick } else { price - rem }
+}
+
+fn candidate(bids: &[(i32, u32)], asks: &[(i32, u32)], price: i32)
+-> Candidate {
+    let bid = bids.iter().filter(|(p, _)| *p >= price).map(|(_, v)| v).sum();
+    let ask = asks.iter().filter(|(p, _)| *p <= price).map(|(_, v)| v).sum();
+    Candidate { price, bid, ask }
+}
+
+// (price, qty) levels best price first
+// return Option<(price, max_qty, surplus, surplus is buy)>
+pub fn determine<B, A>(mut bnext: B, mut anext: A, pclose: i32, tick: i32,
+                       rules: &[TieBreak]) -> Option<(i32, u32, u32, bool)>
+where B: FnMut() -> Option<(i32, u32)>, A: FnMut() -> Option<(i32, u32)>
+{
+    let best_bid = bnext()?;
+    let best_ask = anext()?;
+    if best_bid.0 < best_ask.0 { return None }
+    // crossed levels only
+    let mut bids = vec![best_bid];
+    while let Some(lv) = bnext() {
+        if lv.0 < best_ask.0 { break }
+        bids.push(lv);
+    }
+    let mut asks = vec![best_ask];
+    while let Some(lv) = anext() {
+        if lv.0 > best_bid.0 { break }
+        asks.push(lv);
+    }
+    let mut prices: Vec<i32> = bids.iter().chain(asks.iter())
+                                .map(|(p, _)| *p).collect();
+    prices.sort_unstable();
+    prices.dedup();
+    let mut cands: Vec<Candidate> = prices.iter()
+                        .map(|p| candidate(&bids, &asks, *p)).collect();
+    let max_qty = cands.iter().map(|c| c.qty()).max()?;
+    cands.retain(|c| c.qty() == max_qty);
+    for rule in rules {
+        if cands.len() == 1 { break }
+        let (lo, hi) = (cands[0].price, cands[cands.len()-1].price);
+        match rule {
+            TieBreak::MinSurplus => {
+                let min_surplus = cands.iter().map(|c| c.surplus()).min()?;
+                cands.retain(|c| c.surplus() == min_surplus);
+            },
+            TieBreak::MarketPressure => {
+                if cands.iter().all(|c| c.bid > c.ask) {
+                    cands.drain(..cands.len()-1);
+                } else if cands.iter().all(|c| c.bid < c.ask) {
+                    cands.truncate(1);
+                }
+            },
+            TieBreak::RefPrice => {
+                if pclose >= hi {
+                    cands.drain(..cands.len()-1);
+                } else if pclose <= lo {
+                    cands.truncate(1);
+                } else {
+                    let price = round_tick(pclose, tick).clamp(lo, hi);
+                    cands = vec![candidate(&bids, &asks, price)];
+                }
+            },
+            TieBreak::Midpoint => {
+                let price = round_tick(lo + (hi - lo) / 2, tick).clamp(lo, hi);
+                cands = vec![candidate(&bids, &asks, price)];
+            },
+        }
+    }
+    // still tie, lowest price
+    let c = cands[0];
+    Some((c.price, c.qty(), c.surplus(), c.bid > c.ask))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{determine, round_tick, TieBreak, PriceRules};
+    use TieBreak::*;
+
+    // bids/asks as (price, qty) levels best first
+    fn run(bids: &[(i32, u32)], asks: &[(i32, u32)], pclose: i32,
+           rules: &[TieBreak]) -> Option<(i32, u32, u32, bool)> {
+        let mut bit = bids.iter().copied();
+        let mut ait = asks.iter().copied();
+        determine(|| bit.next(), || ait.next(), pclose, 1, rules)
+    }
+
+    // prices in cents, tick 5
+    fn run_tick(bids: &[(i32, u32)], asks: &[(i32, u32)], pclose: i32,
+                rules: &[TieBreak]) -> Option<(i32, u32, u32, bool)> {
+        let mut bit = bids.iter().copied();
+        let mut ait = asks.iter().copied();
+        determine(|| bit.next(), || ait.next(), pclose, 5, rules)
+    }
+
+    #[test]
+    fn test_pack() {
+        for rules in [PriceRules::Classic, PriceRules::standard(),
+                      PriceRules::Chain(vec![]),
+                      PriceRules::Chain(vec![Midpoint; 7])] {
+            assert_eq!(PriceRules::unpack(rules.pack().unwrap()), Some(rules));
+        }
+        assert_eq!(PriceRules::standard().pack(), Some(0xf321));
+        assert_eq!(PriceRules::Chain(vec![RefPrice; 8]).pack(), None);
+        assert_eq!(PriceRules::unpack(0xf5), None);
+        assert_eq!(PriceRules::unpack(0x1f1), None);
+        assert_eq!(PriceRules::unpack(0x1111), None);
+    }
+
+    #[test]
+    fn test_no_cross() {
+        assert_eq!(run(&[(199, 10)], &[(200, 10)], 200, &[]), None);
+        assert_eq!(run(&[], &[(200, 10)], 200, &[]), None);
+    }
+
+    // single price of highest executable volume
+    #[test]
+    fn test_max_volume() {
+        // cum bid 202:100 201:300 200:500, cum ask 200:200 201:400 202:500
+        let bids = [(202, 100), (201, 200), (200, 200)];
+        let asks = [(200, 200), (201, 200), (202, 100)];
+        assert_eq!(run(&bids, &asks, 0, &[]), Some((201, 300, 100, false)));
+        let rules = PriceRules::standard();
+        if let PriceRules::Chain(rules) = rules {
+            assert_eq!(run(&bids, &asks, 0, &rules),
+                       Some((201, 300, 100, false)));
+        }
+    }
+
+    // 199..201 all 300 executable, surplus 200/200/0
+    #[test]
+    fn test_min_surplus() {
+        let bids = [(202, 100), (201, 200), (200, 200)];
+        let asks = [(199, 300), (202, 200)];
+        assert_eq!(run(&bids, &asks, 0, &[MinSurplus]),
+                   Some((201, 300, 0, false)));
+        assert_eq!(run(&bids, &asks, 0, &[]), Some((199, 300, 200, true)));
+        // 200: bid 400 ask 300, 201: bid 300 ask 400, min surplus tie
+        let bids = [(201, 300), (200, 100)];
+        let asks = [(199, 100), (200, 200), (201, 100)];
+        assert_eq!(run(&bids, &asks, 0, &[MinSurplus]),
+                   Some((200, 300, 100, true)));
+    }
+
+    // equal volume and surplus, surplus side decide
+    #[test]
+    fn test_market_pressure() {
+        // buy surplus 100 at 200 and 202, highest price
+        let (bids, asks) = ([(202, 400)], [(200, 300)]);
+        assert_eq!(run(&bids, &asks, 0, &[MinSurplus, MarketPressure]),
+                   Some((202, 300, 100, true)));
+        assert_eq!(run(&bids, &asks, 0, &[MinSurplus]),
+                   Some((200, 300, 100, true)));
+        // sell surplus, lowest price
+        let (bids, asks) = ([(202, 300)], [(200, 400)]);
+        assert_eq!(run(&bids, &asks, 250, &[MarketPressure, RefPrice]),
+                   Some((200, 300, 100, false)));
+        // buy surplus at 200, sell at 201/202, reference price decide
+        let bids = [(202, 300), (200, 100)];
+        let asks = [(200, 300), (201, 100)];
+        assert_eq!(run(&bids, &asks, 201,
+                       &[MinSurplus, MarketPressure, RefPrice]),
+                   Some((201, 300, 100, false)));
+        assert_eq!(run(&bids, &asks, 201, &[MinSurplus, MarketPressure]),
+                   Some((200, 300, 100, true)));
+    }
+
+    // equal volume, no surplus
+    #[test]
+    fn test_ref_price() {
+        let bids = [(205, 100)];
+        let asks = [(200, 100)];
+        let rules = [MinSurplus, MarketPressure, RefPrice];
+        assert_eq!(run(&bids, &asks, 203, &rules), Some((203, 100, 0, false)));
+        assert_eq!(run(&bids, &asks, 210, &rules), Some((205, 100, 0, false)));
+        assert_eq!(run(&bids, &asks, 190, &rules), Some((200, 100, 0, false)));
+        assert_eq!(run(&bids, &asks, 190, &[]), Some((200, 100, 0, false)));
+    }
+
+    #[test]
+    fn test_midpoint() {
+        let bids = [(206, 100)];
+        let asks = [(200, 100)];
+        assert_eq!(run(&bids, &asks, 190, &[Midpoint]),
+                   Some((203, 100, 0, false)));
+        assert_eq!(run(&[(205, 100)], &asks, 190, &[Midpoint, RefPrice]),
+                   Some((202, 100, 0, false)));
+        // surplus side decided before midpoint
+        let (bids, asks) = ([(202, 400)], [(200, 300)]);
+        assert_eq!(run(&bids, &asks, 0, &[MarketPressure, Midpoint]),
+                   Some((202, 300, 100, true)));
+        assert_eq!(run(&bids, &asks, 0, &[Midpoint]),
+                   Some((201, 300, 100, true)));
+    }
+
+    #[test]
+    fn test_round_tick() {
+        assert_eq!(round_tick(10003, 5), 10005);
+        assert_eq!(round_tick(10002, 5), 10000);
+        assert_eq!(round_tick(10012, 10), 10010);
+        assert_eq!(round_tick(10015, 10), 10010);
+        assert_eq!(round_tick(-7, 5), -5);
+        assert_eq!(round_tick(10003, 1), 10003);
+    }
+
+    // auction situations of the Xetra market model chain: highest
+    // volume, lowest surplus, market pressure, reference price.
+    // (price, qty, surplus, surplus is buy)
+    #[test]
+    fn test_exchange_min_surplus() {
+        // max volume 400 at 100.00 and 100.05, surplus 200/100
+        let bids = [(10010, 300), (10005, 200), (10000, 100)];
+        let asks = [(9995, 200), (10000, 200), (10010, 300)];
+        if let PriceRules::Chain(rules) = PriceRules::standard() {
+            assert_eq!(run_tick(&bids, &asks, 10000, &rules),
+                       Some((10005, 400, 100, true)));
+        }
+    }
+
+    #[test]
+    fn test_exchange_market_pressure() {
+        let rules = [MinSurplus, MarketPressure, RefPrice];
+        // surplus on bid side at all prices, highest limit
+        assert_eq!(run_tick(&[(10010, 500)], &[(10000, 300)], 10000, &rules),
+                   Some((10010, 300, 200, true)));
+        // surplus on ask side at all prices, lowest limit
+        assert_eq!(run_tick(&[(10010, 300)], &[(10000, 500)], 10010, &rules),
+                   Some((10000, 300, 200, false)));
+    }
+
+    #[test]
+    fn test_exchange_ref_price() {
+        let rules = [MinSurplus, MarketPressure, RefPrice];
+        // no surplus, reference price inside range
+        let (bids, asks) = ([(10010, 300)], [(10000, 300)]);
+        assert_eq!(run_tick(&bids, &asks, 10005, &rules),
+                   Some((10005, 300, 0, false)));
+        // reference price above/below range, closest limit
+        assert_eq!(run_tick(&bids, &asks, 10020, &rules),
+                   Some((10010, 300, 0, false)));
+        assert_eq!(run_tick(&bids, &asks, 9990, &rules),
+                   Some((10000, 300, 0, false)));
+        // reference price off tick, nearest tick
+        assert_eq!(run_tick(&bids, &asks, 10003, &rules),
+                   Some((10005, 300, 0, false)));
+        assert_eq!(run_tick(&bids, &asks, 10002, &rules),
+                   Some((10000, 300, 0, false)));
+        // surplus on both sides, reference price decide
+        let bids = [(10010, 300), (10000, 100)];
+        let asks = [(10000, 300), (10005, 100)];
+        assert_eq!(run_tick(&bids, &asks, 10005, &rules),
+                   Some((10005, 300, 100, false)));
+    }
+
+    #[test]
+    fn test_exchange_midpoint() {
+        let asks = [(10000, 300)];
+        assert_eq!(run_tick(&[(10020, 300)], &asks, 0, &[Midpoint]),
+                   Some((10010, 300, 0, false)));
+        // midpoint 100.075 between ticks, lower tick
+        assert_eq!(run_tick(&[(10015, 300)], &asks, 0, &[Midpoint]),
+                   Some((10005, 300, 0, false)));
+        assert_eq!(run_tick(&[(10025, 300)], &asks, 0, &[Midpoint]),
+                   Some((10010, 300, 0, false)));
+    }
+}
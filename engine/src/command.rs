@@ -0,0 +1,52 @@
+use match_base::{OrderType, TimeInForce, EngineError};
+use crate::state::State;
+use crate::engine::{AuctionResult, TradePrice};
+
+// state changing request to MatchEngine
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Command {
+    NewOrder {
+        sym:    u32,
+        buy:    bool,
+        price:  i32,
+        qty:    u32,
+        ord_type:   OrderType,
+        tif:    TimeInForce,
+    },
+    Cancel { oid: u64 },
+    Amend { oid: u64, price: i32, qty: u32 },
+    ChangeState(State),
+    RefPrice { sym: u32, price: i32 },
+    Reopen { sym: u32 },
+    SymbolState { sym: u32, state: State },
+    PriceLimit { sym: u32, limit_down: i32, limit_up: i32 },
+    TradePrice(TradePrice),
+    // PriceRules::pack code, symbol rules None fall back to market rules
+    AuctionRules { rules: u32 },
+    SymbolRules { sym: u32, rules: Option<u32> },
+    VolBand { sym: u32, bps: Option<u32> },
+}
+
+// result of Command executed
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Reply {
+    NewOrder(Result<u64, EngineError>),
+    Cancel(Result<u32, EngineError>),
+    Amend(Result<u64, EngineError>),
+    ChangeState(Result<(), EngineError>),
+    RefPrice(Result<(), EngineError>),
+    Reopen(Result<Option<AuctionResult>, EngineError>),
+    SymbolState(Result<(), EngineError>),
+    PriceLimit(Result<(), EngineError>),
+    TradePrice(Result<(), EngineError>),
+    AuctionRules(Result<(), EngineError>),
+    SymbolRules(Result<(), EngineError>),
+    VolBand(Result<(), EngineError>),
+}
+
+impl Command {
+    pub fn new_order(sym: u32, buy: bool, price: i32, qty: u32) -> Command {
+        Command::NewOrder { sym, buy, price, qty, ord_type: OrderType::Limit,
+                            tif: TimeInForce::Day }
+    }
+}
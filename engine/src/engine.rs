@@ -1,11 +1,18 @@
 use std::collections::HashMap;
+use std::io::{self, Read, Write};
 use log::{error, info, warn};
 use measure::Measure;
-#[cfg(not(feature = "btree_maple"))]
-use match_base::{Order, OrderKey, OrderPool, DealPool, Symbols};
-#[cfg(feature = "btree_maple")]
-use match_base::{Order, OrderPool, DealPool, Symbols};
-use crate::{state::State, order_book::OrderBook};
+use match_base::{Order, OrderKey, OrderPool, DealPool, Symbols, Symbol};
+use match_base::{OrderType, TimeInForce, Liquidity, Reject, EngineError};
+use crate::state::State;
+use crate::order_book::{OrderBook, Indicative, equilibrium};
+use crate::auction::{PriceRules, determine};
+use crate::command::{Command, Reply};
+use crate::journal::Journal;
+use crate::snapshot::{SnapWriter, SnapReader, SNAP_MAGIC, invalid_data};
+use crate::report::{ExecReport, ExecType, ReportSink};
+use crate::market_data::{MarketData, MdSink, DepthSnapshot, Level1, Trade};
+use crate::market_data::{OrderUpdate, OrderAction, BookSnapshot};
 
 pub struct MatchEngine {
     state:  State,
@@ -13,6 +20,69 @@ pub struct MatchEngine {
     pool:   OrderPool,
     book:   HashMap<u32, OrderBook>,
     deals:  DealPool,
+    price_rule: TradePrice,
+    journal: Option<Journal>,
+    ref_price: HashMap<u32, i32>,
+    auctions: Vec<AuctionResult>,
+    on_indicative: Option<IndicativeFn>,
+    auction_rules: PriceRules,
+    sym_rules: HashMap<u32, PriceRules>,
+    vol_band: HashMap<u32, u32>,
+    reports: Option<Box<dyn ReportSink>>,
+    md:     Option<Box<dyn MdSink>>,
+    md_interval: u64,   // full depth snapshot every n updates
+    md_trades: Vec<Trade>,  // trades not yet published
+    l3:     Option<Box<dyn MdSink>>,
+    l3_pending: Vec<OrderUpdate>,   // sequenced once published
+}
+
+// called with symbol and new indicative uncross while preAuction
+pub type IndicativeFn = Box<dyn FnMut(u32, Option<Indicative>) + Send>;
+
+// equilibrium of call auction uncross for symbol
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct AuctionResult {
+    sym:    u32,
+    price:  i32,
+    qty:    u32,
+    remain: u32,
+}
+
+// deal price for trading continue
+#[derive(PartialEq, Clone, Copy, Debug, Default)]
+pub enum TradePrice {
+    #[default]
+    Maker,      // resting order price
+    Aggressor,  // incoming order limit price
+}
+
+impl TradePrice {
+    pub const fn from_u8(v: u8) -> Option<TradePrice> {
+        match v {
+            0 => Some(TradePrice::Maker),
+            1 => Some(TradePrice::Aggressor),
+            _ => None,
+        }
+    }
+}
+
+impl AuctionResult {
+    pub const fn new(sym: u32, price: i32, qty: u32, remain: u32)
+    -> AuctionResult {
+        AuctionResult { sym, price, qty, remain }
+    }
+    pub fn symbol(&self) -> u32 {
+        self.sym
+    }
+    pub fn price(&self) -> i32 {
+        self.price
+    }
+    pub fn qty(&self) -> u32 {
+        self.qty
+    }
+    pub fn remain(&self) -> u32 {
+        self.remain
+    }
 }
 
 #[inline(always)]
@@ -47,18 +117,28 @@ fn get_mid_price(hi: i32, lo: i32, clast: i32) -> i32 {
 }
 
 
-#[cfg(not(feature = "btree_maple"))]
-#[allow(dead_code)]
+// limit price for match, market order take any price
+#[inline(always)]
+fn match_price(buy: bool, price: i32, ord_type: OrderType) -> i32 {
+    if ord_type != OrderType::Market {
+        price
+    } else if buy {
+        i32::MAX
+    } else {
+        i32::MIN
+    }
+}
+
 #[inline(always)]
-fn get_match_qty(orb: &OrderBook, buy: bool, prc: i32, qty: u32) -> u32 {
+fn get_match_qty(orb: &OrderBook, pool: &OrderPool, buy: bool, prc: i32,
+                 qty: u32) -> u32 {
     let ob = orb.book(buy);
-    if ob.len() == 0 {
+    if ob.is_empty() {
         return 0
     }
-    let mut it = ob.iter();
     let mut fill_qty = 0;
-    while let Some((_, okey)) = it.next() {
-        if let Some(ord) = okey.get() {
+    for okey in ob.values() {
+        if let Some(ord) = pool.get(okey) {
             if ord.is_canceled() { continue }
             if ord.is_invalid() {
                 error!("order({}) is invalid", ord.oid());
@@ -78,11 +158,37 @@ fn get_match_qty(orb: &OrderBook, buy: bool, prc: i32, qty: u32) -> u32 {
     fill_qty
 }
 
+// fill pairs of uncross qty at last, None if book can't fill qty
+fn uncross_pairs(orb: &OrderBook, pool: &OrderPool, last: i32, qty: u32)
+-> Option<usize> {
+    let remains = |buy: bool| orb.book(buy).values()
+                    .filter_map(|okey| pool.get(okey))
+                    .take_while(move |ord| may_match(buy, ord.price(), last))
+                    .map(|ord| ord.remain_qty());
+    let (mut bit, mut ait) = (remains(true), remains(false));
+    let (mut bvol, mut avol) = (0, 0);
+    let (mut sum, mut pairs) = (qty, 0);
+    while sum > 0 {
+        if bvol == 0 { bvol = bit.next()? }
+        if avol == 0 { avol = ait.next()? }
+        let fill_qty = sum.min(bvol).min(avol);
+        bvol -= fill_qty;
+        avol -= fill_qty;
+        sum -= fill_qty;
+        pairs += 1;
+    }
+    Some(pairs)
+}
+
+// deal pushed before fill, no fill w/o deal
 #[inline(always)]
-fn set_fill(deals: &DealPool, ord: &mut Order, vol: u32, price: i32) {
+fn set_fill(deals: &mut DealPool, ord: &mut Order, contra: u64,
+            liquidity: Liquidity, vol: u32, price: i32)
+-> Result<(), EngineError> {
+        deals.push_deal(ord, contra as u32, liquidity, price, vol)?;
         ord.fill(vol, price);
-        deals.push_deal(ord.oid() as u32, price, vol);
         // should pushDeal to mdCache as well
+        Ok(())
 }
 
 #[inline(always)]
@@ -102,410 +208,1425 @@ fn parse_orderfile(aline: &str) -> Option<(bool, i32, u32)> {
 
 impl MatchEngine {
     pub fn new() -> MatchEngine {
+        let mut symbols = Symbols::new();
+        for name in ["cu1906", "cu1908", "cu1909", "cu1912"] {
+            symbols.add_symbol(name).unwrap();
+        }
+        MatchEngine::with_symbols(symbols)
+    }
+    // symbols of instrument master, e.g. Symbols::load_csv
+    pub fn with_symbols(symbols: Symbols) -> MatchEngine {
         let pool = OrderPool::new();
-        let mut me = MatchEngine { pool, state: Default::default(),
-                    symbols: Symbols::new(), deals: DealPool::new(),
-                    book: HashMap::<u32, OrderBook>::new() };
-        me.symbols.add_symbol("cu1906");
-        me.symbols.add_symbol("cu1908");
-        me.symbols.add_symbol("cu1909");
-        me.symbols.add_symbol("cu1912");
-        me
-    }
-    pub fn change_state(&mut self, new_state: State) -> bool {
-        let rev = self.state.review(&new_state);
-        if rev {
+        MatchEngine { pool, state: Default::default(),
+                    symbols, deals: DealPool::new(),
+                    book: HashMap::<u32, OrderBook>::new(),
+                    price_rule: Default::default(), journal: None,
+                    ref_price: HashMap::<u32, i32>::new(),
+                    auctions: Vec::<AuctionResult>::new(),
+                    on_indicative: None, auction_rules: Default::default(),
+                    sym_rules: HashMap::<u32, PriceRules>::new(),
+                    vol_band: HashMap::<u32, u32>::new(), reports: None,
+                    md: None, md_interval: 0,
+                    md_trades: Vec::<Trade>::new(), l3: None,
+                    l3_pending: Vec::<OrderUpdate>::new() }
+    }
+    pub fn change_state(&mut self, new_state: State)
+    -> Result<(), EngineError> {
+        if !self.journal(&Command::ChangeState(new_state)) {
+            return Err(EngineError::JournalFail)
+        }
+        if self.state.review(&new_state) {
             // do somethine
             info!("do change state {}", new_state);
+            match new_state {
+                // volatility auction kept while trading paused
+                State::StateTrading | State::StatePause | State::StateBreak => {
+                },
+                _ => {
+                    for orb in self.book.values_mut() {
+                        orb.set_state(None);
+                    }
+                },
+            }
+            for orb in self.book.values_mut() {
+                if orb.state().unwrap_or(new_state) != State::StatePreAuction {
+                    orb.disable_indicative();
+                }
+            }
             match new_state {
                 State::StateIdle => {
-                    // poll.clear collision w/ order_book bench
-                    //let pool = OrderPool::new();
-                    //pool.clear();        // clear orders
+                    self.pool.clear();        // clear orders
                     self.deals.clear();
                     // clear orderBooks
                     let mut it = self.book.iter_mut();
                     while let Some((_, ob)) = it.next() {
                         ob.clear();
                     }
+                    self.publish_md_all();
+                    self.publish_books();
+                },
+                State::StatePreAuction => {
+                    for (sym, orb) in self.book.iter_mut() {
+                        let pclose = self.ref_price.get(sym).copied()
+                                        .unwrap_or(0);
+                        let rules = self.sym_rules.get(sym)
+                                        .unwrap_or(&self.auction_rules);
+                        orb.enable_indicative(&self.pool, pclose,
+                                              rules.clone());
+                    }
                 },
                 State::StateCallAuction => {
-                    // TODO: FIXME
-                    // do call auction
                     // uncross all list symbols
+                    self.call_auction_all();
+                },
+                State::StateStop => {
+                    // expire Day orders, GTC orders keep in orderBook
+                    let mut expired = Vec::<OrderKey>::new();
+                    for ob in self.book.values_mut() {
+                        let keys = ob.expire_day(&mut self.pool);
+                        info!("{} expired {} day orders", ob.symbol(),
+                              keys.len());
+                        expired.extend(keys);
+                    }
+                    for okey in expired {
+                        let ord = self.pool.get(&okey).unwrap();
+                        let rep = ExecReport::order(ord, ExecType::Expired);
+                        self.emit(rep);
+                        self.l3_order(okey, OrderAction::Delete);
+                    }
+                    self.publish_md_all();
                 },
                 _ => { },
             }
             self.state = new_state;
+            Ok(())
         } else {
             warn!("can't change to: {}", new_state);
+            Err(EngineError::InvalidState)
         }
-        rev
     }
     // init symbols/orders/deals
-    pub fn init_market(&mut self) -> bool {
+    pub fn init_market(&mut self) -> Result<(), EngineError> {
         self.change_state(State::StateIdle)
     }
     // open market
-    pub fn begin_market(&mut self) -> bool {
+    pub fn begin_market(&mut self) -> Result<(), EngineError> {
         self.change_state(State::StateStart)
     }
     // goto preAuction
-    pub fn start_market(&mut self) -> bool {
+    pub fn start_market(&mut self) -> Result<(), EngineError> {
+        self.change_state(State::StatePreAuction)
+    }
+    // closing call, collect orders w/o matching, then call_auction and stop
+    pub fn closing_call(&mut self) -> Result<(), EngineError> {
+        self.change_state(State::StateBreak)?;
         self.change_state(State::StatePreAuction)
     }
     // uncross
-    pub fn call_auction(&mut self) -> bool {
+    pub fn call_auction(&mut self) -> Result<(), EngineError> {
         self.change_state(State::StateCallAuction)
     }
     // start trading
-    pub fn start_trading(&mut self) -> bool {
+    pub fn start_trading(&mut self) -> Result<(), EngineError> {
         self.change_state(State::StateTrading)
     }
     // pause trading
-    pub fn pause_trading(&mut self) -> bool {
+    pub fn pause_trading(&mut self) -> Result<(), EngineError> {
         self.change_state(State::StatePause)
     }
     // stop trading
-    pub fn stop_trading(&mut self) -> bool {
+    pub fn stop_trading(&mut self) -> Result<(), EngineError> {
         self.change_state(State::StateStop)
     }
     // end market
-    pub fn end_market(&mut self) -> bool {
+    pub fn end_market(&mut self) -> Result<(), EngineError> {
         self.change_state(State::StateEnd)
     }
+    pub fn set_trade_price(&mut self, rule: TradePrice)
+    -> Result<(), EngineError> {
+        if !self.journal(&Command::TradePrice(rule)) {
+            return Err(EngineError::JournalFail)
+        }
+        self.price_rule = rule;
+        Ok(())
+    }
+    // reference close price for call auction, updated by auction price
+    pub fn set_ref_price(&mut self, sym: u32, price: i32)
+    -> Result<(), EngineError> {
+        if !self.journal(&Command::RefPrice { sym, price }) {
+            return Err(EngineError::JournalFail)
+        }
+        self.ref_price.insert(sym, price);
+        if let Some(orb) = self.book.get_mut(&sym) {
+            orb.set_ref_price(price);
+        }
+        self.notify_indicative(sym);
+        Ok(())
+    }
+    pub fn ref_price(&self, sym: u32) -> Option<i32> {
+        self.ref_price.get(&sym).copied()
+    }
+    // update limit down/up intraday, new orders checked against new limits,
+    // resting orders kept
+    pub fn set_price_limits(&mut self, sym: u32, limit_down: i32,
+                            limit_up: i32) -> Result<(), EngineError> {
+        if !self.journal(&Command::PriceLimit { sym, limit_down, limit_up }) {
+            return Err(EngineError::JournalFail)
+        }
+        self.symbols.set_limits(sym, limit_down, limit_up)?;
+        if let Some(orb) = self.book.get_mut(&sym) {
+            orb.set_limits((limit_down, limit_up));
+        }
+        self.notify_indicative(sym);
+        Ok(())
+    }
+    // auction price rules of market, symbol rules override
+    pub fn set_auction_rules(&mut self, rules: PriceRules)
+    -> Result<(), EngineError> {
+        let code = rules.pack().ok_or(EngineError::InvalidRules)?;
+        if !self.journal(&Command::AuctionRules { rules: code }) {
+            return Err(EngineError::JournalFail)
+        }
+        self.auction_rules = rules;
+        for orb in self.book.values_mut() {
+            if !self.sym_rules.contains_key(&orb.sym_idx()) {
+                orb.set_price_rules(&self.auction_rules);
+            }
+        }
+        let syms: Vec<u32> = self.book.keys().copied().collect();
+        for sym in syms {
+            self.notify_indicative(sym);
+        }
+        Ok(())
+    }
+    pub fn set_symbol_auction_rules(&mut self, sym: u32,
+                                    rules: Option<PriceRules>)
+    -> Result<(), EngineError> {
+        let code = match rules.as_ref() {
+            Some(rules) => Some(rules.pack().ok_or(EngineError::InvalidRules)?),
+            None => None,
+        };
+        if !self.journal(&Command::SymbolRules { sym, rules: code }) {
+            return Err(EngineError::JournalFail)
+        }
+        if let Some(rules) = rules {
+            self.sym_rules.insert(sym, rules);
+        } else {
+            self.sym_rules.remove(&sym);
+        }
+        let rules = self.price_rules(sym).clone();
+        if let Some(orb) = self.book.get_mut(&sym) {
+            orb.set_price_rules(&rules);
+        }
+        self.notify_indicative(sym);
+        Ok(())
+    }
+    // trade price band in basis points around reference price, trade out
+    // of band moves symbol to volatility auction until reopen_symbol
+    pub fn set_volatility_band(&mut self, sym: u32, bps: Option<u32>)
+    -> Result<(), EngineError> {
+        if !self.journal(&Command::VolBand { sym, bps }) {
+            return Err(EngineError::JournalFail)
+        }
+        if let Some(bps) = bps {
+            self.vol_band.insert(sym, bps);
+        } else {
+            self.vol_band.remove(&sym);
+        }
+        Ok(())
+    }
+    // symbol state, market state as upper bound
+    pub fn symbol_state(&self, sym: u32) -> State {
+        self.state.bound(self.book.get(&sym).and_then(|orb| orb.state()))
+    }
+    // symbol state change while market trading, follow State::review
+    pub fn change_symbol_state(&mut self, sym: u32, new_state: State)
+    -> Result<(), EngineError> {
+        if !self.journal(&Command::SymbolState { sym, state: new_state }) {
+            return Err(EngineError::JournalFail)
+        }
+        self.set_symbol_state(sym, new_state)
+    }
+    // halt trading of symbol, orders can be canceled only
+    pub fn halt_symbol(&mut self, sym: u32) -> Result<(), EngineError> {
+        self.change_symbol_state(sym, State::StatePause)
+    }
+    pub fn resume_symbol(&mut self, sym: u32) -> Result<(), EngineError> {
+        self.change_symbol_state(sym, State::StateTrading)
+    }
+    // call auction for symbol, collect orders w/o matching until reopen
+    pub fn auction_symbol(&mut self, sym: u32) -> Result<(), EngineError> {
+        self.change_symbol_state(sym, State::StateBreak)?;
+        self.change_symbol_state(sym, State::StatePreAuction)
+    }
+    // uncross symbol in auction call, back to trading continue
+    pub fn reopen_symbol(&mut self, sym: u32)
+    -> Result<Option<AuctionResult>, EngineError> {
+        if !self.journal(&Command::Reopen { sym }) {
+            return Err(EngineError::JournalFail)
+        }
+        let n_auctions = self.auctions.len();
+        if self.symbol_state(sym) != State::StatePreAuction {
+            return Err(EngineError::InvalidState)
+        }
+        self.set_symbol_state(sym, State::StateCallAuction)?;
+        let res = self.auctions.get(n_auctions).copied();
+        self.set_symbol_state(sym, State::StateTrading)?;
+        info!("symbol({}) reopen", sym);
+        Ok(res)
+    }
+    pub fn price_rules(&self, sym: u32) -> &PriceRules {
+        self.sym_rules.get(&sym).unwrap_or(&self.auction_rules)
+    }
+    // indicative uncross while preAuction, None if not crossed
+    pub fn indicative(&self, sym: u32) -> Option<Indicative> {
+        self.book.get(&sym).and_then(|orb| orb.indicative())
+    }
+    pub fn set_indicative_callback(&mut self, cb: Option<IndicativeFn>) {
+        self.on_indicative = cb;
+    }
+    // execution reports of order lifecycle, e.g. mpsc::Sender<ExecReport>
+    pub fn set_report_sink(&mut self, sink: Option<Box<dyn ReportSink>>) {
+        self.reports = sink;
+    }
+    // L2 depth of orderBooks, e.g. mpsc::Sender<MarketData>
+    // full depth snapshot of every symbol published first
+    pub fn set_md_sink(&mut self, sink: Option<Box<dyn MdSink>>) {
+        self.md = sink;
+        let mut syms: Vec<u32> = self.book.keys().copied().collect();
+        syms.sort_unstable();
+        for sym in syms {
+            let orb = self.book.get_mut(&sym).unwrap();
+            if self.md.is_some() {
+                orb.enable_depth(&self.pool);
+                self.publish_snapshot(sym);
+            } else {
+                orb.disable_depth();
+            }
+        }
+    }
+    // publish full depth snapshot every n updates of symbol, 0 for never
+    pub fn set_snapshot_interval(&mut self, n: u64) {
+        self.md_interval = n;
+    }
+    // L3 order by order feed, e.g. mpsc::Sender<MarketData>
+    // full book of every symbol published first
+    pub fn set_l3_sink(&mut self, sink: Option<Box<dyn MdSink>>) {
+        self.l3 = sink;
+        self.l3_pending.clear();
+        self.publish_books();
+    }
+    // orders in priority order, for L3 subscriber bootstrap
+    pub fn book_snapshot(&self, sym: u32) -> Option<BookSnapshot> {
+        Some(self.book.get(&sym)?.book_snapshot(&self.pool))
+    }
+    // top of book and trade statistics of symbol
+    pub fn level1(&self, sym: u32) -> Option<&Level1> {
+        self.book.get(&sym).map(|orb| orb.level1())
+    }
+    // L2 depth while md published, for subscriber recover from gap
+    pub fn depth_snapshot(&self, sym: u32) -> Option<DepthSnapshot> {
+        self.book.get(&sym)?.depth_snapshot()
+    }
+    // results of last call auction, in symbol order
+    pub fn auction_results(&self) -> &[AuctionResult] {
+        &self.auctions
+    }
+    // journal state changing commands before applied
+    pub fn set_journal(&mut self, journal: Option<Journal>) {
+        self.journal = journal;
+    }
+    // rebuild books and deals from journal commands, on a fresh engine
+    pub fn replay(&mut self, cmds: &[Command]) -> usize {
+        let journal = self.journal.take();
+        for cmd in cmds {
+            self.execute(cmd);
+        }
+        self.journal = journal;
+        cmds.len()
+    }
+    // state, price rules, symbols, deal counters, reference prices,
+    // volatility bands and live orders of all books in priority order;
+    // filled/canceled orders not saved
+    pub fn snapshot<W: Write>(&self, w: W) -> io::Result<()> {
+        let mut w = SnapWriter::new(w);
+        w.put_bytes(SNAP_MAGIC)?;
+        w.put_u8(self.state as u8)?;
+        w.put_u8(self.price_rule as u8)?;
+        let bad_rules = || invalid_data("bad price rules");
+        w.put_u32(self.auction_rules.pack().ok_or_else(bad_rules)?)?;
+        let mut rules: Vec<(&u32, &PriceRules)> = self.sym_rules.iter()
+                                                    .collect();
+        rules.sort_by_key(|(sym, _)| **sym);
+        w.put_u32(rules.len() as u32)?;
+        for (sym, rules) in rules {
+            w.put_u32(*sym)?;
+            w.put_u32(rules.pack().ok_or_else(bad_rules)?)?;
+        }
+        let end_idx = self.symbols.end_idx();
+        w.put_u32(end_idx - 1)?;
+        for idx in 1..end_idx {
+            let sym = self.symbols.get_symbol(idx)
+                        .ok_or_else(|| invalid_data("symbol index gap"))?;
+            w.put_str(sym.name())?;
+            w.put_u8(sym.digits() as u8)?;
+            w.put_u32(sym.price_step())?;
+            w.put_u32(sym.lot_size())?;
+            w.put_u32(sym.vol_min())?;
+            w.put_u32(sym.vol_max())?;
+            w.put_i32(sym.limits().0)?;
+            w.put_i32(sym.limits().1)?;
+        }
+        w.put_u64(self.pool.len() as u64)?;
+        w.put_u64(self.deals.deal_no())?;
+        w.put_u32(self.deals.match_no())?;
+        let mut prices: Vec<(&u32, &i32)> = self.ref_price.iter().collect();
+        prices.sort();
+        w.put_u32(prices.len() as u32)?;
+        for (sym, price) in prices {
+            w.put_u32(*sym)?;
+            w.put_i32(*price)?;
+        }
+        let mut bands: Vec<(&u32, &u32)> = self.vol_band.iter().collect();
+        bands.sort();
+        w.put_u32(bands.len() as u32)?;
+        for (sym, bps) in bands {
+            w.put_u32(*sym)?;
+            w.put_u32(*bps)?;
+        }
+        let mut syms: Vec<&u32> = self.book.keys().collect();
+        syms.sort();
+        w.put_u32(syms.len() as u32)?;
+        for sym in syms {
+            let orb = &self.book[sym];
+            w.put_u32(*sym)?;
+            w.put_str(orb.symbol())?;
+            w.put_u8(orb.state().map_or(u8::MAX, |st| st as u8))?;
+            orb.level1().put_stats(&mut w)?;
+            for buy in [true, false] {
+                w.put_u32(orb.book(buy).len() as u32)?;
+                for okey in orb.book(buy).values() {
+                    let ord = self.pool.get(okey)
+                                .ok_or_else(|| invalid_data("orphan key"))?;
+                    w.put_u64(ord.oid())?;
+                    w.put_u8(ord.is_buy() as u8)?;
+                    w.put_i32(ord.price())?;
+                    w.put_u32(ord.qty())?;
+                    w.put_u32(ord.filled())?;
+                    w.put_i32(ord.price_filled())?;
+                    w.put_u8(ord.is_canceled() as u8)?;
+                    w.put_u8(ord.ord_type() as u8)?;
+                    w.put_u8(ord.tif() as u8)?;
+                }
+            }
+        }
+        w.flush()
+    }
+    // replace engine state with snapshot, journal kept
+    pub fn restore<R: Read>(&mut self, r: R) -> io::Result<()> {
+        let mut r = SnapReader::new(r);
+        if &r.get_bytes::<8>()? != SNAP_MAGIC {
+            return Err(invalid_data("not engine snapshot"))
+        }
+        let state = State::from_u8(r.get_u8()?)
+                        .ok_or_else(|| invalid_data("bad state"))?;
+        let price_rule = TradePrice::from_u8(r.get_u8()?)
+                            .ok_or_else(|| invalid_data("bad trade price"))?;
+        let bad_rules = || invalid_data("bad price rules");
+        let auction_rules = PriceRules::unpack(r.get_u32()?)
+                                .ok_or_else(bad_rules)?;
+        let mut sym_rules = HashMap::<u32, PriceRules>::new();
+        for _ in 0..r.get_u32()? {
+            let sym = r.get_u32()?;
+            let rules = PriceRules::unpack(r.get_u32()?).ok_or_else(bad_rules)?;
+            sym_rules.insert(sym, rules);
+        }
+        let mut symbols = Symbols::new();
+        for idx in 1..=r.get_u32()? {
+            let name = r.get_str()?;
+            let digits = r.get_u8()? as i8;
+            let (price_step, lot_size) = (r.get_u32()?, r.get_u32()?);
+            let (vol_min, vol_max) = (r.get_u32()?, r.get_u32()?);
+            let (limit_down, limit_up) = (r.get_i32()?, r.get_i32()?);
+            if price_step == 0 || lot_size == 0 || limit_down > limit_up {
+                return Err(invalid_data("bad symbol spec"))
+            }
+            let sym = Symbol::new(&name).with_spec(digits, price_step,
+                                                lot_size, vol_min, vol_max)
+                                .with_limits(limit_down, limit_up);
+            if symbols.add_spec(sym) != Ok(idx) {
+                return Err(invalid_data("symbol index mismatch"))
+            }
+        }
+        let pool_len = r.get_u64()? as usize;
+        let deal_no = r.get_u64()?;
+        let match_no = r.get_u32()?;
+        let mut ref_price = HashMap::<u32, i32>::new();
+        for _ in 0..r.get_u32()? {
+            ref_price.insert(r.get_u32()?, r.get_i32()?);
+        }
+        let mut vol_band = HashMap::<u32, u32>::new();
+        for _ in 0..r.get_u32()? {
+            vol_band.insert(r.get_u32()?, r.get_u32()?);
+        }
+        let mut books = HashMap::<u32, OrderBook>::new();
+        let mut orders = Vec::<Order>::new();
+        let mut keys = Vec::<(u32, bool, OrderKey)>::new();
+        for _ in 0..r.get_u32()? {
+            let sym = r.get_u32()?;
+            let mut orb = OrderBook::new(sym, &r.get_str()?);
+            let st = r.get_u8()?;
+            if st != u8::MAX {
+                let st = State::from_u8(st)
+                            .ok_or_else(|| invalid_data("bad symbol state"))?;
+                orb.set_state(Some(st));
+            }
+            orb.set_level1(Level1::get_stats(sym, &mut r)?);
+            books.insert(sym, orb);
+            for _ in 0..2 {
+                for _ in 0..r.get_u32()? {
+                    let oid = r.get_u64()?;
+                    let buy = r.get_bool()?;
+                    let price = r.get_i32()?;
+                    let mut ord = Order::new(oid, sym, buy, price, r.get_u32()?);
+                    let filled = r.get_u32()?;
+                    let price_filled = r.get_i32()?;
+                    if filled > 0 { ord.fill(filled, price_filled); }
+                    if r.get_bool()? { ord.cancel(); }
+                    let ord_type = OrderType::from_u8(r.get_u8()?);
+                    let tif = TimeInForce::from_u8(r.get_u8()?);
+                    if let (Some(ord_type), Some(tif)) = (ord_type, tif) {
+                        ord.set_type(ord_type, tif);
+                    } else {
+                        return Err(invalid_data("bad order type"))
+                    }
+                    keys.push((sym, buy, ord.key()));
+                    orders.push(ord);
+                }
+            }
+        }
+        let mut pool = OrderPool::new();
+        pool.reserve(pool_len);
+        orders.sort_by_key(|o| o.oid());
+        for ord in orders {
+            if pool.restore_order(ord).is_err() {
+                return Err(invalid_data("duplicate order"))
+            }
+        }
+        if pool.len() > pool_len {
+            return Err(invalid_data("order beyond pool"))
+        }
+        pool.pad_to(pool_len);
+        for (sym, buy, okey) in keys {
+            if let (Some(orb), Some(ord)) = (books.get_mut(&sym), pool.get(&okey))
+            {
+                orb.insert(buy, ord);
+            }
+        }
+        if !books.values().all(|orb| orb.validate(&pool)) {
+            return Err(invalid_data("orderBook validate failed"))
+        }
+        self.state = state;
+        self.price_rule = price_rule;
+        self.auction_rules = auction_rules;
+        self.sym_rules = sym_rules;
+        self.vol_band = vol_band;
+        self.symbols = symbols;
+        self.pool = pool;
+        self.book = books;
+        self.deals.restore(deal_no, match_no);
+        for (sym, orb) in self.book.iter_mut() {
+            if let Some(symbol) = self.symbols.get_symbol(*sym) {
+                orb.set_limits(symbol.limits());
+                orb.set_tick(symbol.price_step() as i32);
+            }
+            if orb.state().unwrap_or(state) == State::StatePreAuction {
+                let pclose = ref_price.get(sym).copied().unwrap_or(0);
+                let rules = self.sym_rules.get(sym)
+                                .unwrap_or(&self.auction_rules);
+                orb.enable_indicative(&self.pool, pclose, rules.clone());
+            }
+        }
+        self.ref_price = ref_price;
+        self.auctions.clear();
+        for orb in self.book.values_mut() {
+            orb.update_quote(&self.pool);
+        }
+        // depth of restored books
+        let md = self.md.take();
+        self.set_md_sink(md);
+        let l3 = self.l3.take();
+        self.set_l3_sink(l3);
+        Ok(())
+    }
+    pub fn execute(&mut self, cmd: &Command) -> Reply {
+        match *cmd {
+            Command::NewOrder { sym, buy, price, qty, ord_type, tif } => {
+                Reply::NewOrder(self.send_order_type(sym, buy, price, qty,
+                                                     ord_type, tif))
+            },
+            Command::Cancel { oid } => Reply::Cancel(self.cancel_order(oid)),
+            Command::Amend { oid, price, qty } => {
+                Reply::Amend(self.amend_order(oid, price, qty))
+            },
+            Command::ChangeState(state) => {
+                Reply::ChangeState(self.change_state(state))
+            },
+            Command::RefPrice { sym, price } => {
+                Reply::RefPrice(self.set_ref_price(sym, price))
+            },
+            Command::Reopen { sym } => Reply::Reopen(self.reopen_symbol(sym)),
+            Command::PriceLimit { sym, limit_down, limit_up } => {
+                Reply::PriceLimit(self.set_price_limits(sym, limit_down,
+                                                        limit_up))
+            },
+            Command::SymbolState { sym, state } => {
+                Reply::SymbolState(self.change_symbol_state(sym, state))
+            },
+            Command::TradePrice(rule) => {
+                Reply::TradePrice(self.set_trade_price(rule))
+            },
+            Command::AuctionRules { rules } => {
+                Reply::AuctionRules(match PriceRules::unpack(rules) {
+                    Some(rules) => self.set_auction_rules(rules),
+                    None => Err(EngineError::InvalidRules),
+                })
+            },
+            Command::SymbolRules { sym, rules } => {
+                Reply::SymbolRules(match rules.map(PriceRules::unpack) {
+                    Some(None) => Err(EngineError::InvalidRules),
+                    Some(rules) => self.set_symbol_auction_rules(sym, rules),
+                    None => self.set_symbol_auction_rules(sym, None),
+                })
+            },
+            Command::VolBand { sym, bps } => {
+                Reply::VolBand(self.set_volatility_band(sym, bps))
+            },
+        }
+    }
     pub fn symbol_idx(&self, name: &str) -> Option<u32> {
         self.symbols.get_idx(name)
     }
+    pub fn symbol(&self, sym: u32) -> Option<&Symbol> {
+        self.symbols.get_symbol(sym)
+    }
+    // symbol reference data check, market order price not checked
+    pub fn check_order(&self, sym: u32, price: i32, qty: u32,
+                       ord_type: OrderType) -> Result<(), Reject> {
+        let symbol = self.symbols.get_symbol(sym)
+                        .ok_or(Reject::UnknownSymbol)?;
+        if ord_type != OrderType::Market {
+            symbol.check_price(price)?;
+        }
+        symbol.check_qty(qty)
+    }
     pub fn send_order(&mut self, sym: u32, buy: bool, price: i32, qty: u32)
-    -> Option<u64> {
-        if !self.state.can_book() {
-            return None
+    -> Result<u64, EngineError> {
+        self.send_order_type(sym, buy, price, qty, OrderType::Limit,
+                             TimeInForce::Day)
+    }
+    // market/IOC/FOK orders only accepted while trading continue
+    pub fn send_order_type(&mut self, sym: u32, buy: bool, price: i32,
+                           qty: u32, ord_type: OrderType, tif: TimeInForce)
+    -> Result<u64, EngineError> {
+        let res = self.new_order(sym, buy, price, qty, ord_type, tif);
+        if let Err(e) = res {
+            self.emit(ExecReport::reject(sym, e));
         }
-        let new_or = self.pool.new_order(sym, buy, price, qty);
-        if new_or == None {
-            return None
+        res
+    }
+    fn new_order(&mut self, sym: u32, buy: bool, price: i32, qty: u32,
+                 ord_type: OrderType, tif: TimeInForce)
+    -> Result<u64, EngineError> {
+        if !self.journal(&Command::NewOrder { sym, buy, price, qty, ord_type,
+                                              tif }) {
+            return Err(EngineError::JournalFail)
+        }
+        if !self.symbol_state(sym).can_book() {
+            return Err(EngineError::InvalidState)
+        }
+        if let Err(reason) = self.check_order(sym, price, qty, ord_type) {
+            info!("symbol({}) order {}@{} reject: {}", sym, qty, price, reason);
+            return Err(EngineError::Rejected(reason))
+        }
+        let immediate = ord_type == OrderType::Market ||
+                tif == TimeInForce::IOC || tif == TimeInForce::FOK;
+        if immediate && !self.symbol_state(sym).is_tc() {
+            warn!("{:?}/{:?} order only for trading continue", ord_type, tif);
+            return Err(EngineError::InvalidState)
+        }
+        if tif == TimeInForce::FOK {
+            // check fillable qty before match
+            let prc = match_price(buy, price, ord_type);
+            let fill_qty = if let Some(orb) = self.book.get(&sym) {
+                    get_match_qty(orb, &self.pool, !buy, prc, qty)
+                } else { 0 };
+            if fill_qty < qty {
+                info!("FOK order reject, fillable {} of {}", fill_qty, qty);
+                return Err(EngineError::NotFillable)
+            }
+        }
+        if self.symbol_state(sym).is_tc() && !self.deals.has_room(2) {
+            return Err(EngineError::DealPoolFull)
+        }
+        let ord = self.pool.new_order(sym, buy, price, qty)?;
+        ord.set_type(ord_type, tif);
+        let okey = ord.key();
+        self.book_order(okey);
+        self.notify_indicative(sym);
+        self.publish_md(sym);
+        Ok(okey.key() as u64)
+    }
+    // report new, fills and canceled remain of immediate order
+    fn book_order(&mut self, okey: OrderKey) {
+        let deal_no = self.deals.deal_no();
+        if self.reports.is_some() {
+            let rep = ExecReport::accept(self.pool.get(&okey).unwrap());
+            self.emit(rep);
+        }
+        self.match_or_book(okey);
+        self.trade_stats(deal_no);
+        if self.md.is_some() {
+            self.fill_depth(deal_no);
+        }
+        if self.l3.is_some() {
+            self.l3_fills(deal_no);
+            let ord = self.pool.get(&okey).unwrap();
+            if !ord.is_filled() && !ord.is_canceled() {
+                self.l3_order(okey, OrderAction::Add);
+            }
         }
-        let ord = new_or.unwrap();
-        // try match or insert to orderBook
-        if self.state.is_tc() {
-            // try_match
-            if self.try_match(ord) {
-                return Some(ord.oid())
+        if self.reports.is_some() {
+            self.report_fills(deal_no);
+            let ord = self.pool.get(&okey).unwrap();
+            if ord.is_canceled() {
+                let rep = ExecReport::order(ord, ExecType::Canceled);
+                self.emit(rep);
+            }
+        }
+    }
+    // try match or insert to orderBook
+    fn match_or_book(&mut self, okey: OrderKey) {
+        let sym = self.pool.get(&okey).unwrap().symbol();
+        if self.symbol_state(sym).is_tc() {
+            match self.try_match(okey) {
+                Ok(true) => return,
+                Ok(false) => {},
+                // remain never booked crossed w/o room for deals
+                Err(e) => {
+                    warn!("order({}) remain canceled: {}", okey.key(), e);
+                    self.pool.get_mut(&okey).unwrap().cancel();
+                    return
+                },
             }
         }
+        let ord = self.pool.get_mut(&okey).unwrap();
+        if ord.is_immediate() {
+            // cancel remain qty, never rest in orderBook
+            ord.cancel();
+            return
+        }
+        let sym = ord.symbol();
         if let Some(or_book) = self.book.get_mut(&sym) {
-            or_book.insert(buy, ord);
+            or_book.insert(ord.is_buy(), ord);
         } else {
-            let mut or_book = OrderBook::new(sym, "symbol");
-            or_book.insert(buy, ord);
+            let mut or_book = self.new_book(sym);
+            if self.state == State::StatePreAuction {
+                let pclose = self.ref_price.get(&sym).copied().unwrap_or(0);
+                let rules = self.price_rules(sym).clone();
+                or_book.enable_indicative(&self.pool, pclose, rules);
+            }
+            let ord = self.pool.get(&okey).unwrap();
+            or_book.insert(ord.is_buy(), ord);
             self.book.insert(sym, or_book);
         }
-        Some(ord.oid())
+    }
+    fn new_book(&self, sym: u32) -> OrderBook {
+        let mut orb = OrderBook::new(sym, "symbol");
+        if let Some(symbol) = self.symbols.get_symbol(sym) {
+            orb.set_limits(symbol.limits());
+            orb.set_tick(symbol.price_step() as i32);
+        }
+        if self.md.is_some() {
+            orb.enable_depth(&self.pool);
+        }
+        orb
+    }
+    pub fn order(&self, oid: u64) -> Option<&Order> {
+        match self.pool.get(&OrderKey::from(oid)) {
+            Some(ord) if ord.oid() == oid => Some(ord),
+            _ => None,
+        }
+    }
+    fn set_symbol_state(&mut self, sym: u32, new_state: State)
+    -> Result<(), EngineError> {
+        let cur = self.symbol_state(sym);
+        match new_state {
+            State::StateTrading | State::StatePause | State::StateBreak |
+            State::StatePreAuction | State::StateCallAuction => {},
+            _ => return Err(EngineError::InvalidState),
+        }
+        if !self.state.is_tc() || !cur.review(&new_state) {
+            warn!("symbol({}) can't change from {} to {}", sym, cur,
+                  new_state);
+            return Err(EngineError::InvalidState)
+        }
+        if self.symbols.get_symbol(sym).is_none() {
+            return Err(EngineError::UnknownSymbol)
+        }
+        info!("symbol({}) change state {}", sym, new_state);
+        if !self.book.contains_key(&sym) {
+            let orb = self.new_book(sym);
+            self.book.insert(sym, orb);
+        }
+        match new_state {
+            State::StatePreAuction => {
+                let pclose = self.ref_price.get(&sym).copied().unwrap_or(0);
+                let rules = self.price_rules(sym).clone();
+                self.book.get_mut(&sym).unwrap()
+                    .enable_indicative(&self.pool, pclose, rules);
+            },
+            State::StateCallAuction => {
+                self.book.get_mut(&sym).unwrap().disable_indicative();
+                if let Some(res) = self.uncross_symbol(sym) {
+                    self.auctions.push(res);
+                }
+            },
+            _ => {
+                self.book.get_mut(&sym).unwrap().disable_indicative();
+            },
+        }
+        let orb = self.book.get_mut(&sym).unwrap();
+        if new_state == State::StateTrading {
+            orb.set_state(None);
+        } else {
+            orb.set_state(Some(new_state));
+        }
+        Ok(())
+    }
+    // equilibrium by try_uncross with reference price, uncross every
+    // symbol in symbol order for deterministic deal no
+    fn call_auction_all(&mut self) {
+        self.auctions.clear();
+        let mut syms: Vec<u32> = self.book.keys().copied().collect();
+        syms.sort_unstable();
+        for sym in syms {
+            if let Some(res) = self.uncross_symbol(sym) {
+                self.auctions.push(res);
+            }
+        }
+    }
+    fn uncross_symbol(&mut self, sym: u32) -> Option<AuctionResult> {
+        let pclose = match self.ref_price.get(&sym) {
+            Some(prc) => *prc,
+            None => {
+                warn!("symbol({}) no reference price, use 0", sym);
+                0
+            }
+        };
+        let res = match self.try_uncross(self.book.get(&sym)?, pclose) {
+            Some((last, qty, remain)) if qty > 0 => {
+                AuctionResult { sym, price: last, qty, remain }
+            },
+            _ => return None,
+        };
+        if let Err(e) = self.uncross(sym, res.price, res.qty) {
+            error!("symbol({}) uncross {}@{} failed: {}", sym, res.qty,
+                   res.price, e);
+            return None
+        }
+        info!("symbol({}) auction {}@{} remain {}", sym, res.qty,
+              res.price, res.remain);
+        self.ref_price.insert(sym, res.price);
+        Some(res)
+    }
+    // price range of volatility band
+    fn band_limits(&self, sym: u32) -> Option<(i32, i32)> {
+        let bps = *self.vol_band.get(&sym)?;
+        let pref = *self.ref_price.get(&sym)?;
+        let delta = (pref.unsigned_abs() as u64 * bps as u64 / 10000) as i32;
+        Some((pref.saturating_sub(delta), pref.saturating_add(delta)))
+    }
+    // trade out of band, symbol call auction w/o matching, straight from
+    // trading to preAuction
+    fn volatility_call(&mut self, sym: u32) {
+        let pclose = self.ref_price.get(&sym).copied().unwrap_or(0);
+        let rules = self.price_rules(sym).clone();
+        if let Some(orb) = self.book.get_mut(&sym) {
+            warn!("symbol({}) volatility interruption, ref price {}", sym,
+                  pclose);
+            orb.set_state(Some(State::StatePreAuction));
+            orb.enable_indicative(&self.pool, pclose, rules);
+        }
+    }
+    fn journal(&mut self, cmd: &Command) -> bool {
+        if let Some(jnl) = self.journal.as_mut() {
+            if let Err(e) = jnl.append(cmd) {
+                error!("journal {:?}: {}", cmd, e);
+                return false
+            }
+        }
+        true
+    }
+    fn emit(&mut self, rep: ExecReport) {
+        if let Some(sink) = self.reports.as_mut() {
+            sink.report(&rep);
+        }
+    }
+    // fill reports of deals after deal_no, cum qty as of each deal
+    fn report_fills(&mut self, deal_no: u64) {
+        if self.reports.is_none() { return }
+        let mut remain = HashMap::<u64, u32>::new();
+        for no in deal_no + 1..=self.deals.deal_no() {
+            let deal = self.deals.get(no).unwrap();
+            *remain.entry(deal.oid() as u64).or_insert(0) += deal.qty();
+        }
+        let mut reps = Vec::<ExecReport>::with_capacity(remain.len() * 2);
+        for no in deal_no + 1..=self.deals.deal_no() {
+            let deal = self.deals.get(no).unwrap();
+            let oid = deal.oid() as u64;
+            let left = remain.get_mut(&oid).unwrap();
+            *left -= deal.qty();
+            if let Some(ord) = self.order(oid) {
+                reps.push(ExecReport::fill(ord, ord.filled() - *left,
+                                           deal.price(), deal.qty()));
+            }
+        }
+        for rep in reps {
+            self.emit(rep);
+        }
+    }
+    // resting orders filled in place by try_match
+    fn fill_depth(&mut self, deal_no: u64) {
+        for no in deal_no + 1..=self.deals.deal_no() {
+            let deal = self.deals.get(no).unwrap();
+            if deal.liquidity() != Liquidity::Maker { continue }
+            let ord = match self.pool.get(&OrderKey::from(deal.oid() as u64)) {
+                Some(ord) => ord,
+                None => continue,
+            };
+            if let Some(orb) = self.book.get_mut(&deal.symbol()) {
+                orb.fill_depth(deal.is_buy(), ord.price(), deal.qty());
+            }
+        }
+    }
+    // trade statistics of deals after deal_no, once per match
+    fn trade_stats(&mut self, deal_no: u64) {
+        for no in deal_no + 1..=self.deals.deal_no() {
+            let deal = self.deals.get(no).unwrap();
+            match deal.liquidity() {
+                Liquidity::Taker => {},
+                Liquidity::Auction if deal.is_buy() => {},
+                _ => continue,
+            }
+            let mul = self.symbols.get_symbol(deal.symbol())
+                        .map_or(1, |sym| sym.turnover_mul());
+            if let Some(orb) = self.book.get_mut(&deal.symbol()) {
+                orb.trade(deal.price(), deal.qty(), mul);
+            }
+            if self.md.is_some() {
+                self.md_trades.push(Trade::new(deal));
+            }
+        }
+    }
+    // refresh quote, trades of symbol, L2 updates of changed levels w/ full
+    // snapshot every md_interval, then L1 if changed
+    fn publish_md(&mut self, sym: u32) {
+        self.publish_l3();
+        let orb = match self.book.get_mut(&sym) {
+            Some(orb) => orb,
+            None => return,
+        };
+        orb.update_quote(&self.pool);
+        let sink = match self.md.as_mut() {
+            Some(sink) => sink,
+            None => return,
+        };
+        self.md_trades.retain(|trade| {
+            if trade.symbol() != sym { return true }
+            sink.publish(&MarketData::Trade(*trade));
+            false
+        });
+        let ups = orb.depth_updates();
+        for up in ups.iter() {
+            sink.publish(&MarketData::Level(*up));
+        }
+        let n = self.md_interval;
+        if let (Some(first), Some(last)) = (ups.first(), ups.last()) {
+            if n > 0 && (first.seq() - 1) / n != last.seq() / n {
+                if let Some(snap) = orb.depth_snapshot() {
+                    sink.publish(&MarketData::Snapshot(snap));
+                }
+            }
+        }
+        if let Some(l1) = orb.level1_update() {
+            sink.publish(&MarketData::Level1(l1));
+        }
+    }
+    // full depth and L1 of symbol
+    fn publish_snapshot(&mut self, sym: u32) {
+        let (sink, orb) = match (self.md.as_mut(), self.book.get_mut(&sym)) {
+            (Some(sink), Some(orb)) => (sink, orb),
+            _ => return,
+        };
+        if let Some(snap) = orb.depth_snapshot() {
+            sink.publish(&MarketData::Snapshot(snap));
+        }
+        orb.update_quote(&self.pool);
+        orb.level1_update();
+        sink.publish(&MarketData::Level1(*orb.level1()));
+    }
+    // resting order event of L3 feed
+    fn l3_order(&mut self, okey: OrderKey, action: OrderAction) {
+        if self.l3.is_none() { return }
+        let ord = self.pool.get(&okey).unwrap();
+        let qty = if action == OrderAction::Delete { 0 }
+                  else { ord.remain_qty() };
+        self.l3_pending.push(OrderUpdate::new(ord, action, qty, 0));
+    }
+    // resting orders executed by deals after deal_no, remain as of deal
+    fn l3_fills(&mut self, deal_no: u64) {
+        if self.l3.is_none() { return }
+        let mut later = HashMap::<u64, u32>::new();
+        let mut ups = Vec::<OrderUpdate>::new();
+        for no in (deal_no + 1..=self.deals.deal_no()).rev() {
+            let deal = self.deals.get(no).unwrap();
+            if deal.liquidity() == Liquidity::Taker { continue }
+            let oid = deal.oid() as u64;
+            if let Some(ord) = self.order(oid) {
+                let done = later.entry(oid).or_insert(0);
+                ups.push(OrderUpdate::new(ord, OrderAction::Execute,
+                                          ord.remain_qty() + *done,
+                                          deal.qty()));
+                *done += deal.qty();
+            }
+        }
+        ups.reverse();
+        self.l3_pending.extend(ups);
+    }
+    // sequence pending L3 updates per symbol
+    fn publish_l3(&mut self) {
+        let sink = match self.l3.as_mut() {
+            Some(sink) => sink,
+            None => return,
+        };
+        for up in self.l3_pending.drain(..) {
+            if let Some(orb) = self.book.get_mut(&up.symbol()) {
+                let pos = orb.queue_position(up.is_buy(), up.price(),
+                                             up.oid());
+                let up = up.with_seq(orb.next_l3_seq(), pos);
+                sink.publish(&MarketData::Order(up));
+            }
+        }
+    }
+    // full L3 book of every symbol, in symbol order
+    fn publish_books(&mut self) {
+        let sink = match self.l3.as_mut() {
+            Some(sink) => sink,
+            None => return,
+        };
+        let mut syms: Vec<u32> = self.book.keys().copied().collect();
+        syms.sort_unstable();
+        for sym in syms {
+            let snap = self.book[&sym].book_snapshot(&self.pool);
+            sink.publish(&MarketData::Book(snap));
+        }
+    }
+    // market data of all symbols, in symbol order
+    fn publish_md_all(&mut self) {
+        let mut syms: Vec<u32> = self.book.keys().copied().collect();
+        syms.sort_unstable();
+        for sym in syms {
+            self.publish_md(sym);
+        }
+    }
+    fn notify_indicative(&mut self, sym: u32) {
+        if let Some(orb) = self.book.get_mut(&sym) {
+            if !orb.update_indicative() { return }
+            if let Some(cb) = self.on_indicative.as_mut() {
+                cb(sym, orb.indicative());
+            }
+        }
+    }
+    // pending order for cancel/amend
+    fn live_order(&self, oid: u64) -> Result<&Order, EngineError> {
+        let ord = self.order(oid).ok_or(EngineError::UnknownOrder)?;
+        if ord.is_canceled() {
+            return Err(EngineError::OrderCanceled)
+        }
+        if ord.is_filled() {
+            return Err(EngineError::OrderFilled)
+        }
+        Ok(ord)
+    }
+    // cancel pending order, return canceled remain qty
+    pub fn cancel_order(&mut self, oid: u64) -> Result<u32, EngineError> {
+        if !self.journal(&Command::Cancel { oid }) {
+            return Err(EngineError::JournalFail)
+        }
+        let sym = self.order(oid).map_or(0, |ord| ord.symbol());
+        if !self.symbol_state(sym).can_cancel() {
+            return Err(EngineError::InvalidState)
+        }
+        self.live_order(oid)?;
+        let ord = self.pool.get_mut(&OrderKey::from(oid)).unwrap();
+        let (qty, sym) = (ord.remain_qty(), ord.symbol());
+        if let Some(orb) = self.book.get_mut(&sym) {
+            if !orb.remove(ord.is_buy(), ord) {
+                warn!("cancel oid({}) not in orderBook", oid);
+            }
+        }
+        ord.cancel();
+        let (rep, okey) = (ExecReport::order(ord, ExecType::Canceled),
+                           ord.key());
+        self.emit(rep);
+        self.l3_order(okey, OrderAction::Delete);
+        self.notify_indicative(sym);
+        self.publish_md(sym);
+        Ok(qty)
+    }
+    // amend pending order, new_qty is total qty include filled
+    // qty down w/ same price keep oid and priority, otherwise replaced by
+    // new order w/ remain qty, return oid of amended order
+    pub fn amend_order(&mut self, oid: u64, new_price: i32, new_qty: u32)
+    -> Result<u64, EngineError> {
+        if !self.journal(&Command::Amend { oid, price: new_price,
+                                           qty: new_qty }) {
+            return Err(EngineError::JournalFail)
+        }
+        let sym = self.order(oid).map_or(0, |ord| ord.symbol());
+        if !self.symbol_state(sym).can_book() {
+            return Err(EngineError::InvalidState)
+        }
+        let ord = self.live_order(oid)?;
+        let filled = ord.filled();
+        if new_qty <= filled {
+            return Err(EngineError::InvalidQty)
+        }
+        let okey = ord.key();
+        if new_price == ord.price() && new_qty <= ord.qty() {
+            let (sym, buy, delta) = (ord.symbol(), ord.is_buy(),
+                                     ord.qty() - new_qty);
+            let ord = self.pool.get_mut(&okey).unwrap();
+            ord.reduce_qty(new_qty);
+            let rep = ExecReport::order(ord, ExecType::Replaced);
+            if let Some(orb) = self.book.get_mut(&sym) {
+                orb.reduce(buy, new_price, delta);
+            }
+            self.emit(rep);
+            self.l3_order(okey, OrderAction::Modify);
+            self.notify_indicative(sym);
+            self.publish_md(sym);
+            return Ok(oid)
+        }
+        // lose priority, re-key as new order
+        let (sym, buy) = (ord.symbol(), ord.is_buy());
+        let (ord_type, tif) = (ord.ord_type(), ord.tif());
+        let qty = new_qty - filled;
+        if let Err(reason) = self.check_order(sym, new_price, qty, ord_type) {
+            info!("order({}) amend {}@{} reject: {}", oid, qty, new_price,
+                  reason);
+            return Err(EngineError::Rejected(reason))
+        }
+        let new_ord = self.pool.new_order(sym, buy, new_price, qty)?;
+        new_ord.set_type(ord_type, tif);
+        let new_key = new_ord.key();
+        let ord = self.pool.get_mut(&okey).unwrap();
+        if let Some(orb) = self.book.get_mut(&sym) {
+            orb.remove(buy, ord);
+        }
+        ord.cancel();
+        let rep = ExecReport::order(ord, ExecType::Canceled);
+        self.emit(rep);
+        self.l3_order(okey, OrderAction::Delete);
+        self.book_order(new_key);
+        self.notify_indicative(sym);
+        self.publish_md(sym);
+        Ok(new_key.key() as u64)
     }
     #[cfg(not(feature = "btree_maple"))]
     #[inline(always)]
-    pub fn try_match(&mut self, order: &mut Order) -> bool {
+    pub fn try_match(&mut self, okey: OrderKey)
+    -> Result<bool, EngineError> {
         // filled
-        let orb = self.book.get_mut(& order.symbol());
+        let order = self.pool.get(&okey).unwrap();
+        let sym = order.symbol();
+        let band = self.band_limits(sym);
+        let mut breach = false;
+        let mut full = false;
+        let orb = self.book.get_mut(&sym);
         if orb == None {
-            return false
+            return Ok(false)
         }
         let orb = orb.unwrap();
+        if !self.state.bound(orb.state()).is_tc() {
+            return Ok(false)
+        }
         let buy = order.is_buy();
         if orb.book(!buy).len() == 0 {
-            return false
+            return Ok(false)
         }
-        let prc = order.price();
+        let prc = match_price(buy, order.price(), order.ord_type());
+        // market order always fill at book price
+        let at_maker = self.price_rule == TradePrice::Maker ||
+                        order.is_market();
         let mut qty = order.remain_qty();
-        let mut okey=OrderKey::new(0);
+        let mut last_key=OrderKey::new(0);
         let mut it = orb.book(!buy).iter();
         while let Some((_, oid)) = it.next() {
-            let orv = oid.get_mut().unwrap();
+            if qty == 0 { break }
+            let orv = self.pool.get_mut(oid).unwrap();
             if may_match(buy, prc, orv.price()) {
                 // fill
                 let fill_qty = if qty > orv.remain_qty()
                                 { orv.remain_qty() } else { qty };
-                DealPool::new_match();  // increase match no
-                set_fill(& self.deals, orv, fill_qty, prc);
-                set_fill(& self.deals, order, fill_qty, prc);
+                let last = if at_maker { orv.price() } else { prc };
+                if let Some((lo, hi)) = band {
+                    if last < lo || last > hi {
+                        breach = true;
+                        break
+                    }
+                }
+                // deal pair of maker and taker, never split
+                if !self.deals.has_room(2) {
+                    full = true;
+                    break
+                }
+                self.deals.new_match();  // increase match no
+                set_fill(&mut self.deals, orv, okey.key() as u64,
+                         Liquidity::Maker, fill_qty, last)?;
+                let contra = orv.oid();
+                let order = self.pool.get_mut(&okey).unwrap();
+                set_fill(&mut self.deals, order, contra, Liquidity::Taker,
+                         fill_qty, last)?;
                 //ord.fill(fill_qty, last);
                 //self.deals.push_deal(ord.oid() as u32, last, fill_qty);
                 qty -= fill_qty;
-                okey = orv.key();
+                last_key = *oid;
             } else { break }
         }
-        if !okey.is_null() {
-            orb.retain(!buy, okey);
+        if !last_key.is_null() {
+            orb.retain(!buy, self.pool.get(&last_key).unwrap());
+        }
+        if breach {
+            self.volatility_call(sym);
         }
-        qty == 0
+        if full { return Err(EngineError::DealPoolFull) }
+        Ok(qty == 0)
     }
     #[cfg(feature = "btree_maple")]
     #[inline(always)]
-    pub fn try_match(&mut self, order: &mut Order) -> bool {
+    pub fn try_match(&mut self, okey: OrderKey)
+    -> Result<bool, EngineError> {
         // filled
-        let orb = self.book.get_mut(& order.symbol());
+        let order = self.pool.get(&okey).unwrap();
+        let sym = order.symbol();
+        let band = self.band_limits(sym);
+        let mut breach = false;
+        let mut full = false;
+        let orb = self.book.get_mut(&sym);
         if orb == None {
-            return false
+            return Ok(false)
         }
         let orb = orb.unwrap();
+        if !self.state.bound(orb.state()).is_tc() {
+            return Ok(false)
+        }
         let buy = order.is_buy();
         if orb.book(!buy).len() == 0 {
-            return false
+            return Ok(false)
         }
-        let prc = order.price();
+        let prc = match_price(buy, order.price(), order.ord_type());
+        // market order always fill at book price
+        let at_maker = self.price_rule == TradePrice::Maker ||
+                        order.is_market();
         let mut qty = order.remain_qty();
         while let Some(or_entry) = orb.book_mut(!buy).first_entry() {
-            let orv = or_entry.get().get_mut().unwrap();
+            if qty == 0 { break }
+            let orv = self.pool.get_mut(or_entry.get()).unwrap();
             if may_match(buy, prc, orv.price()) {
                 // fill
                 let fill_qty = if qty > orv.remain_qty()
                                 { orv.remain_qty() } else { qty };
-                DealPool::new_match();  // increase match no
-                set_fill(& self.deals, orv, fill_qty, prc);
-                set_fill(& self.deals, order, fill_qty, prc);
+                let last = if at_maker { orv.price() } else { prc };
+                if let Some((lo, hi)) = band {
+                    if last < lo || last > hi {
+                        breach = true;
+                        break
+                    }
+                }
+                // deal pair of maker and taker, never split
+                if !self.deals.has_room(2) {
+                    full = true;
+                    break
+                }
+                self.deals.new_match();  // increase match no
+                set_fill(&mut self.deals, orv, okey.key() as u64,
+                         Liquidity::Maker, fill_qty, last)?;
+                let (contra, orv_filled) = (orv.oid(), orv.is_filled());
+                let order = self.pool.get_mut(&okey).unwrap();
+                set_fill(&mut self.deals, order, contra, Liquidity::Taker,
+                         fill_qty, last)?;
                 //ord.fill(fill_qty, last);
                 //self.deals.push_deal(ord.oid() as u32, last, fill_qty);
                 qty -= fill_qty;
-                if orv.is_filled() {
+                if orv_filled {
                     or_entry.remove_entry();
                 } else {
                     break
                 };
             } else { break }
         }
-        qty == 0
+        if breach {
+            self.volatility_call(sym);
+        }
+        if full { return Err(EngineError::DealPoolFull) }
+        Ok(qty == 0)
     }
     pub fn book(&self, sym: u32) -> Option<&OrderBook> {
         self.book.get(&sym)
     }
-    pub fn uncross(&mut self, sym: u32, last: i32, qty: u32) -> bool {
-        DealPool::new_match();
-        let mut measure = Measure::start("uncross bench");
-        info!("uncross {} bid side orders {} @{}", sym, qty, last);
-        if !self.uncross_side(sym, true, last, qty) {
-            warn!("uncross {} bid side error", sym);
-            return false
-        }
-        info!("uncross {} ask side orders {} @{}", sym, qty, last);
-        if !self.uncross_side(sym, false, last, qty) {
-            warn!("uncross {} ask side error", sym);
-            return false
-        }
-        measure.stop();
-        println!("MatchUnCross cost {}us", measure.as_us());
-        let orb = self.book.get(&sym).unwrap();
-        let (blen, alen) = orb.len();
-        println!("After uncross qlen: {}/{}", blen, alen);
-        true
+    pub fn deals(&self) -> &DealPool {
+        &self.deals
     }
-    #[cfg(feature = "btree_maple")]
-    fn uncross_side(&mut self, sym: u32, buy: bool, last: i32, qty: u32)
-    -> bool {
-        if let Some(orb) = self.book.get_mut(&sym) {
-            let (blen, alen) = orb.len();
-            info!("before uncross qlen: {}/{}", blen, alen);
-            let mut sum: u32 = qty;
-            while let Some(or_entry) = orb.book_mut(!buy).first_entry() {
-                let ord = or_entry.get().get_mut().unwrap();
-                if may_match(buy, last, ord.price()) {
-                    // fill
-                    let fill_qty = if sum >= ord.remain_qty()
-                                { ord.remain_qty() } else { sum };
-                    sum -= fill_qty;
-                    //ord.fill(fill_qty, last);
-                    //self.deals.push_deal(ord.oid() as u32, last, fill_qty);
-                    set_fill(& self.deals, ord, fill_qty, last);
-                    if ord.is_filled() {
-                        or_entry.remove_entry();
-                    } else { break }
-                    if sum == 0 {
-                        break
-                    }
-                } else {
-                    break
-                }
-            }
-            sum == 0
-        } else {
-            error!("orderbook for symbol({}) NOT FOUND", sym);
-            false
-        }
+    pub fn pool(&self) -> &OrderPool {
+        &self.pool
     }
-    #[cfg(not(feature = "btree_maple"))]
-    fn uncross_side(&mut self, sym: u32, buy: bool, last: i32, qty: u32)
-    -> bool {
-        if let Some(orb) = self.book.get_mut(&sym) {
-            let (blen, alen) = orb.len();
-            info!("before uncross qlen: {}/{}", blen, alen);
-            let mut sum: u32 = qty;
-            let mut okey=OrderKey::new(0);
-            let mut it=orb.book(!buy).iter();
-            while let Some((_, oid)) = it.next() {
-                let ord = oid.get_mut().unwrap();
-                if may_match(buy, last, ord.price()) {
-                    // fill
-                    let fill_qty = if sum >= ord.remain_qty()
-                                { ord.remain_qty() } else { sum };
-                    sum -= fill_qty;
-                    //ord.fill(fill_qty, last);
-                    //self.deals.push_deal(ord.oid() as u32, last, fill_qty);
-                    set_fill(& self.deals, ord, fill_qty, last);
-                    if ord.remain_qty() > 0 {
-                        okey = ord.key();
-                    }
-                    if sum == 0 {
-                        okey = ord.key();
-                        break
-                    }
-                } else {
-                    okey = ord.key();
-                    break
-                }
+    // fill auction of symbol, via journaled call_auction/reopen_symbol only
+    fn uncross(&mut self, sym: u32, last: i32, qty: u32)
+    -> Result<(), EngineError> {
+        let mut measure = Measure::start("uncross bench");
+        info!("uncross {} orders {} @{}", sym, qty, last);
+        let deal_no = self.deals.deal_no();
+        let orb = self.book.get_mut(&sym).ok_or(EngineError::NoBook)?;
+        let (blen, alen) = orb.len();
+        info!("before uncross qlen: {}/{}", blen, alen);
+        // all or nothing, no fill unless qty fillable with deal room
+        let pairs = match uncross_pairs(orb, &self.pool, last, qty) {
+            Some(pairs) => pairs,
+            None => {
+                warn!("uncross {} {}@{} not fillable", sym, qty, last);
+                return Err(EngineError::UncrossShort)
+            },
+        };
+        if !self.deals.has_room(2 * pairs) {
+            warn!("uncross {} {} fills w/o deal room", sym, pairs);
+            return Err(EngineError::DealPoolFull)
+        }
+        // fill bid/ask in pair, both side at last price
+        let mut sum: u32 = qty;
+        while sum > 0 {
+            let bkey = match orb.book(true).values().next() {
+                Some(okey) => *okey,
+                None => break,
+            };
+            let akey = match orb.book(false).values().next() {
+                Some(okey) => *okey,
+                None => break,
+            };
+            let bid = self.pool.get(&bkey).unwrap();
+            let ask = self.pool.get(&akey).unwrap();
+            if !may_match(true, bid.price(), last) ||
+                !may_match(false, ask.price(), last) {
+                break
             }
-            if !okey.is_null() {
-                orb.retain(!buy, okey);
+            let mut fill_qty = sum.min(bid.remain_qty());
+            fill_qty = fill_qty.min(ask.remain_qty());
+            let (bid_oid, ask_oid) = (bid.oid(), ask.oid());
+            self.deals.new_match();
+            let bid = self.pool.get_mut(&bkey).unwrap();
+            set_fill(&mut self.deals, bid, ask_oid, Liquidity::Auction,
+                     fill_qty, last)?;
+            orb.reduce(true, bid.price(), fill_qty);
+            if bid.is_filled() {
+                orb.remove(true, bid);
             }
-            sum == 0
-        } else {
-            error!("orderbook for symbol({}) NOT FOUND", sym);
-            false
+            let ask = self.pool.get_mut(&akey).unwrap();
+            set_fill(&mut self.deals, ask, bid_oid, Liquidity::Auction,
+                     fill_qty, last)?;
+            orb.reduce(false, ask.price(), fill_qty);
+            if ask.is_filled() {
+                orb.remove(false, ask);
+            }
+            sum -= fill_qty;
         }
+        let (blen, alen) = orb.len();
+        self.trade_stats(deal_no);
+        self.l3_fills(deal_no);
+        self.report_fills(deal_no);
+        measure.stop();
+        println!("MatchUnCross cost {}us", measure.as_us());
+        println!("After uncross qlen: {}/{}", blen, alen);
+        self.notify_indicative(sym);
+        self.publish_md(sym);
+        Ok(())
     }
     // return  Option<(last, max_qty, remain_qty)>
     fn try_uncross(&self, orb: &OrderBook, pclose: i32)
     -> Option<(i32,u32,u32)> {
-        let mut bit = orb.pv_iter(true);
-        let mut ait = orb.pv_iter(false);
-        let bp = bit.next();
-        let ap = ait.next();
-        if bp == None || ap == None { return None }
-        let (mut bp, mut bvol) = bp.unwrap();
-        let (mut ap, mut avol) = ap.unwrap();
-        if bp < ap { return None }
-        let (best_bid, best_ask) = (bp, ap);
-        let mut max_qty: u32 = 0;
-        let mut remain_qty: u32 = 0;
-        let mut last: i32 = pclose;
-        let mut b_end = false;
-        let mut a_end = false;
-        #[cfg(test)]
-        info!("sym({}) MatchCross BBS: {}/{}", orb.symbol(), bp, ap);
-        while !b_end && !a_end && bp >= ap
-        {
-            if bvol > avol {
-                max_qty += avol;
-                bvol -= avol;
-                remain_qty = bvol;
-                last = ap;
-                if let Some((p,v)) = ait.next() {
-                    ap = p;
-                    avol = v;
-                } else {
-                    break
-                }
-            } else if bvol < avol {
-                max_qty += bvol;
-                avol -= bvol;
-                remain_qty = avol;
-                last = bp;
-                if let Some((p,v)) = bit.next() {
-                    bp = p;
-                    bvol = v;
-                } else {
-                    break
-                }
-            } else {
-                max_qty += bvol;
-                remain_qty = 0;
-                last = bp;
-                if bp == ap {
-                    break
-                }
-                let oap = ap;
-                let obp = bp;
-                if let Some((p,v)) = bit.next() {
-                    bp = p;
-                    bvol = v;
-                    b_end = bp < best_ask;
-                } else {
-                    b_end = true;
-                }
-                if let Some((p,v)) = ait.next() {
-                    ap = p;
-                    avol = v;
-                    a_end = ap > best_bid;
-                } else {
-                    a_end = true;
-                }
-                if b_end && a_end {
-                    if oap > pclose {
-                        last = oap;
-                    } else if obp < pclose {
-                        last = obp;
-                    } else {
-                        last = pclose;
-                    }
-                    break
-                }
-                if b_end { last = oap }
-                if a_end { last = obp }
-            }
-            #[cfg(test)]
-            info!("update MatchCross price: {} {}/{} volume: {}(left: {})",
-                    last, bp, ap, max_qty, remain_qty);
-        }
+        // auction price inside limits, bid above limit up buy at limit up,
+        // bid below limit down out of cross. asks alike
+        let (lo, hi) = orb.limits();
+        let mut bit = orb.pv_iter(true, &self.pool);
+        let mut ait = orb.pv_iter(false, &self.pool);
+        let mut bnext = || bit.next().filter(|(p, _)| *p >= lo)
+                            .map(|(p, v)| (p.min(hi), v));
+        let mut anext = || ait.next().filter(|(p, _)| *p <= hi)
+                            .map(|(p, v)| (p.max(lo), v));
         #[cfg(test)]
-        info!("symbol({}) MatchCross end, bp/ap: {}/{} volume: {}(left: {})",
-                orb.symbol(), bp, ap, max_qty, remain_qty);
-        Some((last, max_qty, remain_qty))
+        info!("sym({}) MatchCross", orb.symbol());
+        let res = match self.price_rules(orb.sym_idx()) {
+            PriceRules::Classic => equilibrium(&mut bnext, &mut anext, pclose),
+            PriceRules::Chain(rules) => {
+                determine(&mut bnext, &mut anext, pclose, orb.tick(), rules)
+            },
+        };
+        res.map(|(last, qty, remain, _)| (last, qty, remain))
     }
+    // None if book not crossed
     pub fn match_cross(&mut self, sym: u32, pclose: i32)
-    -> Option<(i32,u32,u32)> {
+    -> Result<Option<(i32,u32,u32)>, EngineError> {
         // only uncross on PreAuction
         if self.state != State::StatePreAuction {
-            return None
-        } else {
-            let orb = self.book.get(&sym);
-            if orb == None { return None }
-            let orb = orb.unwrap();
-            self.try_uncross(orb, pclose)
+            return Err(EngineError::InvalidState)
         }
+        let orb = self.book.get(&sym).ok_or(EngineError::NoBook)?;
+        Ok(self.try_uncross(orb, pclose))
     }
-    pub fn load_orders(&mut self, sym: u32, filen: &str) -> bool {
+    // return count of orders loaded
+    pub fn load_orders(&mut self, sym: u32, filen: &str)
+    -> Result<u32, EngineError> {
         use std::fs::File;
         use std::io::Read;
         let mut buff = Vec::<u8>::new();
         let mut measure = Measure::start("load_orders bench");
-        if let Ok(mut rdr) = File::open(filen) {
-            if filen.ends_with(".zst") {
-                buff = zstd::stream::decode_all(rdr).unwrap();
-            } else {
-                rdr.read_to_end(&mut buff).unwrap();
-            }
-        } else {
-            warn!("can't open {}", filen);
-            return false
-        }
-        if let Ok(sbuf) = std::str::from_utf8(buff.as_slice()) {
-            let mut it = sbuf.lines();
-            let mut cnt: u32 = 0;
-            while let Some(aline) = it.next() {
-                if let Some((buy, prc, qty)) = parse_orderfile(aline) {
-                    if self.send_order(sym, buy, prc, qty) == None {
-                        warn!("send_order failed");
-                        return false
-                    }
-                    cnt += 1;
-                } else { continue }
-            }
-            measure.stop();
-            println!("load {} orders from {} cost {}ms", cnt, filen,
-                  measure.as_ms());
+        let mut rdr = File::open(filen).map_err(|e| {
+            warn!("can't open {}: {}", filen, e);
+            EngineError::IoFail
+        })?;
+        let res = if filen.ends_with(".zst") {
+            zstd::stream::decode_all(rdr).map(|b| buff = b)
         } else {
-            return false
+            rdr.read_to_end(&mut buff).map(|_| ())
+        };
+        if let Err(e) = res {
+            warn!("read {} failed: {}", filen, e);
+            return Err(EngineError::IoFail)
         }
-        true
+        let sbuf = std::str::from_utf8(buff.as_slice())
+                    .map_err(|_| EngineError::BadData)?;
+        let mut it = sbuf.lines();
+        let mut cnt: u32 = 0;
+        while let Some(aline) = it.next() {
+            if let Some((buy, prc, qty)) = parse_orderfile(aline) {
+                self.send_order(sym, buy, prc, qty)?;
+                cnt += 1;
+            } else { continue }
+        }
+        measure.stop();
+        println!("load {} orders from {} cost {}ms", cnt, filen,
+              measure.as_ms());
+        Ok(cnt)
     }
     #[cfg(test)]
     pub fn build_orders(&mut self, sym: u32, orders: &str) -> Vec<u64> {
@@ -515,7 +1636,7 @@ impl MatchEngine {
         while let Some(aline) = it.next() {
             //info!("send order: {}", aline);
             if let Some((buy, prc, qty)) = parse_orderfile(aline) {
-                if let Some(oid) = self.send_order(sym, buy, prc, qty) {
+                if let Ok(oid) = self.send_order(sym, buy, prc, qty) {
                     ov.push(oid);
                 } else {
                     warn!("send_order failed");
@@ -533,9 +1654,16 @@ impl MatchEngine {
 #[cfg(test)]
 mod tests {
     use super::{may_match, is_price_better, get_mid_price, MatchEngine};
+    use super::{TradePrice, AuctionResult};
+    use crate::order_book::Indicative;
+    use crate::auction::{PriceRules, TieBreak};
+    use crate::report::{ExecReport, ExecType};
+    use std::sync::{Arc, Mutex};
     use simple_logger::SimpleLogger;
-    use crate::state::State;
-    use match_base::Deal;
+    use crate::{state::State, journal::Journal};
+    use crate::command::{Command, Reply};
+    use match_base::{Deal, OrderType, TimeInForce, Liquidity};
+    use match_base::{Symbols, Reject, EngineError};
     use log::{info, warn, LevelFilter};
 
     #[test]
@@ -564,7 +1692,6 @@ mod tests {
 
     #[test]
     fn test_trading() {
-        use match_base::DealPool;
         if let Err(s) = SimpleLogger::new().init() {
             warn!("SimpleLogger init: {}", s);
         }
@@ -583,23 +1710,714 @@ mod tests {
 12,43200,20,0\n";
         let mut me = MatchEngine::new();
         assert!(me.state.eq(&State::StateIdle));
-        assert!(me.begin_market());
-        assert!(me.start_trading());
+        assert!(me.set_trade_price(TradePrice::Aggressor).is_ok());
+        assert!(me.begin_market().is_ok());
+        assert!(me.start_trading().is_ok());
         let orders = me.build_orders(1, orders1);
         assert_eq!(orders.len(), 12);
-        let deals1 = vec![Deal::new(1, 1, orders[3] as u32, 43500, 45),
-                        Deal::new(2, 1, orders[7] as u32, 43500, 45),
-                        Deal::new(3, 2, orders[3] as u32, 43200, 5),
-                        Deal::new(4, 2, orders[9] as u32, 43200, 5),
-                        Deal::new(5, 3, orders[8] as u32, 43200, 5),
-                        Deal::new(6, 3, orders[9] as u32, 43200, 5),
-                        Deal::new(7, 4, orders[8] as u32, 43200, 20),
-                        Deal::new(8, 4, orders[11] as u32, 43200, 20)];
-        let dealp = DealPool::new();
-        assert!(dealp.eq(&deals1));
-    }
-
-    // clear orders cause order_book test fails since static orderPool
+        let oids: Vec<u32> = orders.iter().map(|x| *x as u32).collect();
+        let deals1 = vec![Deal::new(1, 1, oids[3], 43500, 45)
+                            .with_side(1, true, oids[7], Liquidity::Maker),
+                        Deal::new(2, 1, oids[7], 43500, 45)
+                            .with_side(1, false, oids[3], Liquidity::Taker),
+                        Deal::new(3, 2, oids[3], 43200, 5)
+                            .with_side(1, true, oids[9], Liquidity::Maker),
+                        Deal::new(4, 2, oids[9], 43200, 5)
+                            .with_side(1, false, oids[3], Liquidity::Taker),
+                        Deal::new(5, 3, oids[8], 43200, 5)
+                            .with_side(1, true, oids[9], Liquidity::Maker),
+                        Deal::new(6, 3, oids[9], 43200, 5)
+                            .with_side(1, false, oids[8], Liquidity::Taker),
+                        Deal::new(7, 4, oids[8], 43200, 20)
+                            .with_side(1, true, oids[11], Liquidity::Maker),
+                        Deal::new(8, 4, oids[11], 43200, 20)
+                            .with_side(1, false, oids[8], Liquidity::Taker)];
+        assert!(me.deals().eq(&deals1));
+        let ord = me.order(orders[9]).unwrap();
+        assert_eq!(ord.price_filled(), 43200);
+    }
+
+    #[test]
+    fn test_trading_maker() {
+        if let Err(s) = SimpleLogger::new().init() {
+            warn!("SimpleLogger init: {}", s);
+        }
+        log::set_max_level(LevelFilter::Info);
+        let orders1 = "1, 42000, 10, 1\n\
+2,43000,20,1\n\
+3,41000,30,1\n\
+4,44000,50,1\n\
+5,45000,10,0\n\
+6,48000,20,0\n\
+7,46000,30,0\n\
+8,43500,45,0\n\
+9,43900,25,1\n\
+10,43200,10,0\n\
+11,43800,15,1\n\
+12,43200,20,0\n";
+        let mut me = MatchEngine::new();
+        assert!(me.begin_market().is_ok());
+        assert!(me.start_trading().is_ok());
+        let orders = me.build_orders(1, orders1);
+        assert_eq!(orders.len(), 12);
+        let oids: Vec<u32> = orders.iter().map(|x| *x as u32).collect();
+        let deals1 = vec![Deal::new(1, 1, oids[3], 44000, 45)
+                            .with_side(1, true, oids[7], Liquidity::Maker),
+                        Deal::new(2, 1, oids[7], 44000, 45)
+                            .with_side(1, false, oids[3], Liquidity::Taker),
+                        Deal::new(3, 2, oids[3], 44000, 5)
+                            .with_side(1, true, oids[9], Liquidity::Maker),
+                        Deal::new(4, 2, oids[9], 44000, 5)
+                            .with_side(1, false, oids[3], Liquidity::Taker),
+                        Deal::new(5, 3, oids[8], 43900, 5)
+                            .with_side(1, true, oids[9], Liquidity::Maker),
+                        Deal::new(6, 3, oids[9], 43900, 5)
+                            .with_side(1, false, oids[8], Liquidity::Taker),
+                        Deal::new(7, 4, oids[8], 43900, 20)
+                            .with_side(1, true, oids[11], Liquidity::Maker),
+                        Deal::new(8, 4, oids[11], 43900, 20)
+                            .with_side(1, false, oids[8], Liquidity::Taker)];
+        assert!(me.deals().eq(&deals1));
+        let ord = me.order(orders[9]).unwrap();
+        assert_eq!(ord.price_filled(), 43900);
+        assert!(ord.is_filled());
+    }
+
+    #[test]
+    fn test_cancel() {
+        if let Err(s) = SimpleLogger::new().init() {
+            warn!("SimpleLogger init: {}", s);
+        }
+        log::set_max_level(LevelFilter::Info);
+        let orders1 = "1, 42000, 10, 1\n\
+2,43000,20,1\n\
+3,45000,10,0\n\
+4,44000,30,0\n";
+        let mut me = MatchEngine::new();
+        let orders = me.build_orders(2, orders1);
+        assert!(orders.is_empty());
+        assert!(me.begin_market().is_ok());
+        assert_eq!(me.cancel_order(1), Err(EngineError::InvalidState));
+        assert!(me.start_trading().is_ok());
+        let orders = me.build_orders(2, orders1);
+        assert_eq!(orders.len(), 4);
+        assert_eq!(me.book(2).unwrap().len(), (2, 2));
+        assert_eq!(me.cancel_order(orders[1]), Ok(20));
+        assert_eq!(me.book(2).unwrap().len(), (1, 2));
+        assert_eq!(me.cancel_order(orders[1]), Err(EngineError::OrderCanceled));
+        assert_eq!(me.cancel_order(0), Err(EngineError::UnknownOrder));
+        assert_eq!(me.cancel_order(u64::MAX), Err(EngineError::UnknownOrder));
+        assert!(me.pause_trading().is_ok());
+        assert_eq!(me.cancel_order(orders[3]), Ok(30));
+        assert_eq!(me.book(2).unwrap().len(), (1, 1));
+        assert!(me.book(2).unwrap().validate(me.pool()));
+    }
+
+    #[test]
+    fn test_amend() {
+        if let Err(s) = SimpleLogger::new().init() {
+            warn!("SimpleLogger init: {}", s);
+        }
+        log::set_max_level(LevelFilter::Info);
+        let orders1 = "1, 42000, 20, 1\n\
+2,42000,10,1\n\
+3,45000,10,0\n";
+        let mut me = MatchEngine::new();
+        assert!(me.begin_market().is_ok());
+        assert!(me.start_market().is_ok());
+        let orders = me.build_orders(3, orders1);
+        assert_eq!(orders.len(), 3);
+        // qty down keep priority
+        assert_eq!(me.amend_order(orders[0], 42000, 15), Ok(orders[0]));
+        let first_bid = |me: &MatchEngine| {
+            let (_, okey) = me.book(3).unwrap().book(true).iter().next()
+                            .unwrap();
+            okey.key() as u64
+        };
+        assert_eq!(first_bid(&me), orders[0]);
+        assert_eq!(me.amend_order(orders[0], 42000, 0),
+                   Err(EngineError::InvalidQty));
+        // qty up lose priority
+        let oid = me.amend_order(orders[0], 42000, 30).unwrap();
+        assert!(oid != orders[0]);
+        assert_eq!(first_bid(&me), orders[1]);
+        assert_eq!(me.book(3).unwrap().len(), (2, 1));
+        assert_eq!(me.amend_order(orders[0], 42000, 30),
+                   Err(EngineError::OrderCanceled));
+        // price change, re-key
+        let oid2 = me.amend_order(orders[1], 43000, 10).unwrap();
+        assert!(oid2 != orders[1]);
+        assert_eq!(first_bid(&me), oid2);
+        assert_eq!(me.book(3).unwrap().len(), (2, 1));
+        assert!(me.book(3).unwrap().validate(me.pool()));
+        assert!(me.call_auction().is_ok());
+        assert_eq!(me.amend_order(oid2, 43000, 5), Err(EngineError::InvalidState));
+    }
+
+    #[test]
+    fn test_order_type() {
+        if let Err(s) = SimpleLogger::new().init() {
+            warn!("SimpleLogger init: {}", s);
+        }
+        log::set_max_level(LevelFilter::Info);
+        let orders1 = "1, 45000, 10, 0\n\
+2,46000,20,0\n";
+        let mut me = MatchEngine::new();
+        assert!(me.begin_market().is_ok());
+        assert!(me.start_market().is_ok());
+        assert!(me.send_order_type(4, true, 0, 5, OrderType::Market,
+                                   TimeInForce::Day).is_err());
+        assert!(me.send_order_type(4, true, 45000, 5, OrderType::Limit,
+                                   TimeInForce::IOC).is_err());
+        assert!(me.stop_trading().is_ok());
+        assert!(me.begin_market().is_ok());
+        assert!(me.start_trading().is_ok());
+        let orders = me.build_orders(4, orders1);
+        assert_eq!(orders.len(), 2);
+        // FOK can't fill all, reject
+        assert!(me.send_order_type(4, true, 45500, 20, OrderType::Limit,
+                                   TimeInForce::FOK).is_err());
+        assert_eq!(me.book(4).unwrap().len(), (0, 2));
+        // IOC cancel remain
+        let oid = me.send_order_type(4, true, 45500, 15, OrderType::Limit,
+                                     TimeInForce::IOC).unwrap();
+        let ord = me.order(oid).unwrap();
+        assert!(ord.is_canceled());
+        assert_eq!(ord.filled(), 10);
+        assert_eq!(me.book(4).unwrap().len(), (0, 1));
+        // market sweep book
+        let oid = me.send_order_type(4, true, 0, 5, OrderType::Market,
+                                     TimeInForce::Day).unwrap();
+        let ord = me.order(oid).unwrap();
+        assert!(ord.is_filled());
+        assert_eq!(me.book(4).unwrap().len(), (0, 1));
+        // market w/out opposite orders, cancel
+        let oid = me.send_order_type(4, false, 0, 5, OrderType::Market,
+                                     TimeInForce::Day).unwrap();
+        assert!(me.order(oid).unwrap().is_canceled());
+        assert_eq!(me.book(4).unwrap().len(), (0, 1));
+        // FOK fill all
+        let oid = me.send_order_type(4, true, 46000, 15, OrderType::Limit,
+                                     TimeInForce::FOK).unwrap();
+        assert!(me.order(oid).unwrap().is_filled());
+        assert_eq!(me.book(4).unwrap().len(), (0, 0));
+        // GTC keep after stop while Day expired
+        let gtc = me.send_order_type(4, true, 44000, 10, OrderType::Limit,
+                                     TimeInForce::GTC).unwrap();
+        let day = me.send_order(4, true, 44000, 10).unwrap();
+        assert_eq!(me.book(4).unwrap().len(), (2, 0));
+        assert!(me.stop_trading().is_ok());
+        assert_eq!(me.book(4).unwrap().len(), (1, 0));
+        assert!(!me.order(gtc).unwrap().is_canceled());
+        assert!(me.order(day).unwrap().is_canceled());
+    }
+
+    #[test]
+    fn test_engines() {
+        if let Err(s) = SimpleLogger::new().init() {
+            warn!("SimpleLogger init: {}", s);
+        }
+        log::set_max_level(LevelFilter::Info);
+        let orders1 = "1, 44000, 50, 1\n\
+2,43500,45,0\n";
+        // orders/deals owned by each engine
+        let mut me1 = MatchEngine::new();
+        let mut me2 = MatchEngine::new();
+        for me in [&mut me1, &mut me2] {
+            assert!(me.begin_market().is_ok());
+            assert!(me.start_trading().is_ok());
+            assert_eq!(me.build_orders(1, orders1), vec![1, 2]);
+            assert_eq!(me.deals().deal_no(), 2);
+        }
+        assert_eq!(me1.send_order(1, false, 43000, 5), Ok(3));
+        assert_eq!(me1.deals().deal_no(), 4);
+        assert_eq!(me2.deals().deal_no(), 2);
+        assert_eq!(me1.order(1).unwrap().remain_qty(), 0);
+        assert_eq!(me2.order(1).unwrap().remain_qty(), 5);
+        assert!(me1.stop_trading().is_ok());
+        assert!(me1.init_market().is_ok());
+        assert!(me1.order(1).is_none());
+        assert!(me2.order(1).is_some());
+    }
+
+    #[test]
+    fn test_replay() {
+        if let Err(s) = SimpleLogger::new().init() {
+            warn!("SimpleLogger init: {}", s);
+        }
+        log::set_max_level(LevelFilter::Info);
+        let orders1 = "1, 44000, 50, 1\n\
+2,43500,45,0\n\
+3,43900,30,1\n\
+4,44100,20,0\n";
+        let path = std::env::temp_dir().join(format!("replay-{}",
+                                                      std::process::id()));
+        let path = path.to_str().unwrap();
+        assert!(Journal::remove(path).is_ok());
+        let mut me = MatchEngine::new();
+        me.set_journal(Some(Journal::with_seg_size(path, 64).unwrap()));
+        assert!(me.begin_market().is_ok());
+        assert!(me.start_trading().is_ok());
+        let orders = me.build_orders(1, orders1);
+        assert_eq!(orders.len(), 4);
+        assert!(me.cancel_order(orders[2]).is_ok());
+        assert!(me.amend_order(orders[3], 44000, 25).is_ok());
+        assert_eq!(me.send_order(1, true, 44000, 10), Ok(6));
+        // rejected commands replay rejected as well
+        assert_eq!(me.cancel_order(orders[2]), Err(EngineError::OrderCanceled));
+        assert!(me.call_auction().is_err());
+        me.set_journal(None);
+        let cmds = Journal::load(path).unwrap();
+        assert_eq!(cmds.len(), 11);
+        let mut me2 = MatchEngine::new();
+        assert_eq!(me2.replay(&cmds), cmds.len());
+        assert!(me2.state == State::StateTrading);
+        assert_eq!(me2.pool().len(), me.pool().len());
+        for oid in 1..=me.pool().len() as u64 {
+            let (o1, o2) = (me.order(oid).unwrap(), me2.order(oid).unwrap());
+            assert!(o1 == o2 && o1.remain_qty() == o2.remain_qty());
+        }
+        assert_eq!(me2.book(1).unwrap().len(), me.book(1).unwrap().len());
+        assert_eq!(me2.deals().deal_no(), me.deals().deal_no());
+        for no in 1..=me.deals().deal_no() {
+            assert!(me2.deals().get(no) == me.deals().get(no));
+        }
+        // restart, journal continued after replay
+        me2.set_journal(Some(Journal::open(path).unwrap()));
+        assert_eq!(me2.send_order(1, false, 44000, 5), Ok(7));
+        me2.set_journal(None);
+        let cmds = Journal::load(path).unwrap();
+        assert_eq!(cmds.len(), 12);
+        let mut me3 = MatchEngine::new();
+        me3.replay(&cmds);
+        assert_eq!(me3.order(7).unwrap().remain_qty(),
+                   me2.order(7).unwrap().remain_qty());
+        assert_eq!(me3.deals().deal_no(), me2.deals().deal_no());
+        assert!(Journal::remove(path).unwrap() > 1);
+    }
+
+    #[test]
+    fn test_replay_config() {
+        if let Err(s) = SimpleLogger::new().init() {
+            warn!("SimpleLogger init: {}", s);
+        }
+        log::set_max_level(LevelFilter::Info);
+        let orders1 = "1, 43000, 20, 1\n\
+2, 44000, 50, 1\n\
+3, 43900, 15, 1\n\
+4, 45000, 10, 0\n\
+5, 43500, 45, 0\n\
+6, 43200, 10, 0\n\
+7, 43200, 20, 0\n";
+        let path = std::env::temp_dir().join(format!("replay-cfg-{}",
+                                                      std::process::id()));
+        let path = path.to_str().unwrap();
+        assert!(Journal::remove(path).is_ok());
+        let mut me = MatchEngine::new();
+        me.set_journal(Some(Journal::create(path).unwrap()));
+        assert!(me.begin_market().is_ok());
+        assert!(me.start_market().is_ok());
+        for sym in 1..=2 {
+            assert!(me.set_ref_price(sym, 50000).is_ok());
+            assert_eq!(me.build_orders(sym, orders1).len(), 7);
+        }
+        // rules changed while orders collected
+        assert!(me.set_auction_rules(PriceRules::standard()).is_ok());
+        assert!(me.set_symbol_auction_rules(2, Some(PriceRules::Chain(
+                                    vec![TieBreak::Midpoint]))).is_ok());
+        assert!(me.call_auction().is_ok());
+        assert!(me.start_trading().is_ok());
+        assert!(me.set_trade_price(TradePrice::Aggressor).is_ok());
+        assert!(me.send_order(3, false, 44000, 5).is_ok());
+        assert!(me.send_order(3, true, 44100, 5).is_ok());
+        let last = me.deals().deal_no();
+        assert_eq!(me.deals().get(last).unwrap().price(), 44100);
+        me.set_journal(None);
+        let cmds = Journal::load(path).unwrap();
+        let mut me2 = MatchEngine::new();
+        me2.replay(&cmds);
+        assert_eq!(me2.auction_results(),
+                   &[AuctionResult::new(1, 43500, 65, 10),
+                     AuctionResult::new(2, 43700, 65, 10)]);
+        assert_eq!(me2.price_rule, TradePrice::Aggressor);
+        assert_eq!(me2.deals().deal_no(), last);
+        for no in 1..=last {
+            assert!(me2.deals().get(no) == me.deals().get(no));
+        }
+        assert_eq!(me2.execute(&Command::AuctionRules { rules: 0xf5 }),
+                   Reply::AuctionRules(Err(EngineError::InvalidRules)));
+        assert_eq!(Journal::remove(path).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_errors() {
+        if let Err(s) = SimpleLogger::new().init() {
+            warn!("SimpleLogger init: {}", s);
+        }
+        log::set_max_level(LevelFilter::Info);
+        let mut me = MatchEngine::new();
+        assert_eq!(me.start_trading(), Err(EngineError::InvalidState));
+        assert_eq!(me.send_order(1, true, 43000, 5),
+                   Err(EngineError::InvalidState));
+        assert_eq!(me.match_cross(1, 43000), Err(EngineError::InvalidState));
+        assert!(me.begin_market().is_ok());
+        assert!(me.start_market().is_ok());
+        assert_eq!(me.match_cross(1, 43000), Err(EngineError::NoBook));
+        assert_eq!(me.uncross(1, 43000, 5), Err(EngineError::NoBook));
+        assert_eq!(me.load_orders(1, "/nonexist/orders.txt"),
+                   Err(EngineError::IoFail));
+        assert!(me.send_order(1, true, 43000, 5).is_ok());
+        assert_eq!(me.match_cross(1, 43000), Ok(None));
+        assert_eq!(me.uncross(1, 43000, 5), Err(EngineError::UncrossShort));
+        assert_eq!(me.set_price_limits(9, 43000, 44000),
+                   Err(EngineError::UnknownSymbol));
+        assert_eq!(me.set_price_limits(1, 44000, 43000),
+                   Err(EngineError::InvalidLimits));
+        assert!(me.call_auction().is_ok());
+        assert!(me.start_trading().is_ok());
+        assert_eq!(me.halt_symbol(9), Err(EngineError::UnknownSymbol));
+        assert_eq!(me.resume_symbol(1), Err(EngineError::InvalidState));
+    }
+
+    #[test]
+    fn test_deal_pool_full() {
+        use std::sync::mpsc;
+        use crate::report::ExecType::*;
+        if let Err(s) = SimpleLogger::new().init() {
+            warn!("SimpleLogger init: {}", s);
+        }
+        log::set_max_level(LevelFilter::Info);
+        let mut me = MatchEngine::new();
+        let (tx, rx) = mpsc::channel::<ExecReport>();
+        me.set_report_sink(Some(Box::new(tx)));
+        assert!(me.begin_market().is_ok());
+        assert!(me.start_trading().is_ok());
+        me.deals.set_limit(2);
+        assert!(me.send_order(1, false, 44000, 3).is_ok());
+        assert!(me.send_order(1, false, 44000, 3).is_ok());
+        // room for one fill, remain canceled not booked
+        assert_eq!(me.send_order(1, true, 44000, 6), Ok(3));
+        let reps: Vec<(u64, ExecType, u32, u32)> = rx.try_iter()
+            .map(|r| (r.oid(), r.exec_type(), r.cum_qty(), r.leaves_qty()))
+            .collect();
+        assert_eq!(reps, vec![(1, New, 0, 3), (2, New, 0, 3), (3, New, 0, 6),
+                              (1, Filled, 3, 0), (3, PartialFill, 3, 3),
+                              (3, Canceled, 3, 0)]);
+        assert_eq!(me.deals().deal_no(), 2);
+        assert_eq!(me.order(2).unwrap().remain_qty(), 3);
+        assert_eq!(me.send_order(1, true, 44000, 3),
+                   Err(EngineError::DealPoolFull));
+        assert_eq!(rx.try_recv().unwrap().exec_type(), Rejected);
+        assert_eq!(me.order(2).unwrap().remain_qty(), 3);
+    }
+
+    #[test]
+    fn test_exec_report() {
+        use std::sync::mpsc;
+        use crate::report::ExecType::*;
+        if let Err(s) = SimpleLogger::new().init() {
+            warn!("SimpleLogger init: {}", s);
+        }
+        log::set_max_level(LevelFilter::Info);
+        let mut me = MatchEngine::new();
+        let (tx, rx) = mpsc::channel::<ExecReport>();
+        me.set_report_sink(Some(Box::new(tx)));
+        assert!(me.begin_market().is_ok());
+        assert!(me.start_trading().is_ok());
+        let reps = || -> Vec<(u64, ExecType, u32, u32, i32, u32)> {
+            rx.try_iter().map(|r| (r.oid(), r.exec_type(), r.cum_qty(),
+                                   r.leaves_qty(), r.last_price(),
+                                   r.last_qty())).collect()
+        };
+        assert!(me.send_order(1, false, 44000, 10).is_ok());
+        assert!(me.send_order(1, true, 44000, 4).is_ok());
+        assert_eq!(reps(), vec![(1, New, 0, 10, 0, 0), (2, New, 0, 4, 0, 0),
+                                (1, PartialFill, 4, 6, 44000, 4),
+                                (2, Filled, 4, 0, 44000, 4)]);
+        // IOC remain canceled
+        assert!(me.send_order_type(1, true, 44000, 8, OrderType::Limit,
+                                   TimeInForce::IOC).is_ok());
+        assert_eq!(reps(), vec![(3, New, 0, 8, 0, 0),
+                                (1, Filled, 10, 0, 44000, 6),
+                                (3, PartialFill, 6, 2, 44000, 6),
+                                (3, Canceled, 6, 0, 0, 0)]);
+        assert!(me.send_order(9, false, 45000, 10).is_err());
+        let rep = rx.try_recv().unwrap();
+        assert_eq!((rep.oid(), rep.exec_type(), rep.symbol()),
+                   (0, Rejected, 9));
+        assert_eq!(rep.reason(),
+                   Some(EngineError::Rejected(Reject::UnknownSymbol)));
+        // amend in place, then re-keyed
+        assert!(me.send_order(1, false, 45000, 10).is_ok());
+        assert_eq!(me.amend_order(4, 45000, 5), Ok(4));
+        assert_eq!(me.amend_order(4, 44900, 5), Ok(5));
+        assert_eq!(me.cancel_order(5), Ok(5));
+        assert_eq!(reps(), vec![(4, New, 0, 10, 0, 0),
+                                (4, Replaced, 0, 5, 0, 0),
+                                (4, Canceled, 0, 0, 0, 0), (5, New, 0, 5, 0, 0),
+                                (5, Canceled, 0, 0, 0, 0)]);
+        // day order expired, GTC kept
+        assert!(me.send_order(1, false, 45000, 5).is_ok());
+        assert!(me.send_order_type(1, false, 45000, 5, OrderType::Limit,
+                                   TimeInForce::GTC).is_ok());
+        assert!(me.stop_trading().is_ok());
+        assert_eq!(reps(), vec![(6, New, 0, 5, 0, 0), (7, New, 0, 5, 0, 0),
+                                (6, Expired, 0, 0, 0, 0)]);
+    }
+
+    #[test]
+    fn test_market_data() {
+        use std::sync::mpsc;
+        use crate::market_data::{MarketData, LevelAction, LevelAction::*};
+        if let Err(s) = SimpleLogger::new().init() {
+            warn!("SimpleLogger init: {}", s);
+        }
+        log::set_max_level(LevelFilter::Info);
+        let mut me = MatchEngine::new();
+        let (tx, rx) = mpsc::channel::<MarketData>();
+        me.set_md_sink(Some(Box::new(tx)));
+        me.set_snapshot_interval(4);
+        assert!(me.begin_market().is_ok());
+        assert!(me.start_trading().is_ok());
+        let levels = || -> Vec<(u64, bool, LevelAction, i32, u32)> {
+            rx.try_iter().filter_map(|md| match md {
+                MarketData::Level(up) => Some((up.seq(), up.is_buy(),
+                                    up.action(), up.price(), up.qty())),
+                _ => None,
+            }).collect()
+        };
+        assert!(me.send_order(1, true, 43000, 10).is_ok());
+        assert!(me.send_order(1, true, 43000, 5).is_ok());
+        assert!(me.send_order(1, false, 43100, 7).is_ok());
+        assert_eq!(levels(), vec![(1, true, New, 43000, 10),
+                                  (2, true, Change, 43000, 15),
+                                  (3, false, New, 43100, 7)]);
+        // resting bids filled, snapshot after 4th update
+        assert!(me.send_order(1, false, 42900, 12).is_ok());
+        let md: Vec<MarketData> = rx.try_iter()
+                    .filter(|md| !matches!(md, MarketData::Level1(_)))
+                    .collect();
+        assert_eq!(md.len(), 4);
+        // taker trades first
+        let trades: Vec<(bool, i32, u32)> = md[..2].iter().map(|md| match md {
+            MarketData::Trade(t) => (t.is_buy(), t.price(), t.qty()),
+            _ => panic!("trade expected"),
+        }).collect();
+        assert_eq!(trades, vec![(false, 43000, 10), (false, 43000, 2)]);
+        let md = &md[2..];
+        match &md[0] {
+            MarketData::Level(up) => {
+                assert_eq!((up.seq(), up.action(), up.qty()), (4, Change, 3));
+            },
+            _ => panic!("level update expected"),
+        }
+        match &md[1] {
+            MarketData::Snapshot(snap) => {
+                assert_eq!(snap.seq(), 4);
+                assert_eq!(snap.bids(), &[(43000, 3)]);
+                assert_eq!(snap.asks(), &[(43100, 7)]);
+            },
+            _ => panic!("snapshot expected"),
+        }
+        assert_eq!(me.cancel_order(2), Ok(3));
+        assert_eq!(me.amend_order(3, 43100, 4), Ok(3));
+        assert_eq!(levels(), vec![(5, true, Delete, 43000, 0),
+                                  (6, false, Change, 43100, 4)]);
+        let snap = me.depth_snapshot(1).unwrap();
+        assert_eq!((snap.seq(), snap.bids().len()), (6, 0));
+        // day orders expired at stop
+        assert!(me.stop_trading().is_ok());
+        assert_eq!(levels(), vec![(7, false, Delete, 43100, 0)]);
+    }
+
+    #[test]
+    fn test_level1() {
+        use std::sync::mpsc;
+        use crate::market_data::{MarketData, Level1};
+        if let Err(s) = SimpleLogger::new().init() {
+            warn!("SimpleLogger init: {}", s);
+        }
+        log::set_max_level(LevelFilter::Info);
+        let mut me = MatchEngine::new();
+        let (tx, rx) = mpsc::channel::<MarketData>();
+        me.set_md_sink(Some(Box::new(tx)));
+        assert!(me.begin_market().is_ok());
+        assert!(me.start_trading().is_ok());
+        assert!(me.level1(1).is_none());
+        assert!(me.send_order(1, true, 43000, 10).is_ok());
+        assert!(me.send_order(1, true, 42900, 5).is_ok());
+        assert!(me.send_order(1, false, 43100, 8).is_ok());
+        let l1 = *me.level1(1).unwrap();
+        assert_eq!((l1.bid(), l1.ask()), (Some((43000, 10)), Some((43100, 8))));
+        assert_eq!((l1.last(), l1.volume()), (0, 0));
+        // sweep bids at maker price
+        assert!(me.send_order(1, false, 42900, 12).is_ok());
+        let l1 = *me.level1(1).unwrap();
+        assert_eq!(l1.bid(), Some((42900, 3)));
+        assert_eq!((l1.last(), l1.last_qty(), l1.volume()), (42900, 2, 12));
+        assert_eq!((l1.open(), l1.high(), l1.low()), (43000, 43000, 42900));
+        assert_eq!(l1.turnover(), (43000 * 10 + 42900 * 2) * 5);
+        let quotes: Vec<Level1> = rx.try_iter().filter_map(|md| match md {
+                MarketData::Level1(l1) => Some(l1),
+                _ => None,
+            }).collect();
+        assert_eq!(quotes.len(), 3);
+        assert_eq!(quotes[2], l1);
+        // no change of L1, none published
+        assert!(me.send_order(1, true, 42800, 5).is_ok());
+        assert!(rx.try_iter().all(|md| !matches!(md, MarketData::Level1(_))));
+    }
+
+    #[test]
+    fn test_l3_feed() {
+        use std::sync::mpsc;
+        use crate::market_data::{MarketData, OrderAction, OrderAction::*};
+        if let Err(s) = SimpleLogger::new().init() {
+            warn!("SimpleLogger init: {}", s);
+        }
+        log::set_max_level(LevelFilter::Info);
+        let mut me = MatchEngine::new();
+        assert!(me.begin_market().is_ok());
+        assert!(me.start_trading().is_ok());
+        assert!(me.send_order(1, true, 43000, 10).is_ok());
+        let (tx, rx) = mpsc::channel::<MarketData>();
+        me.set_l3_sink(Some(Box::new(tx)));
+        // bootstrap, then orders by priority
+        let mut bids = match rx.try_recv() {
+            Ok(MarketData::Book(snap)) => snap.bids().to_vec(),
+            _ => panic!("book snapshot expected"),
+        };
+        assert_eq!(bids, vec![(1, 43000, 10)]);
+        let ups = || -> Vec<(u64, u64, OrderAction, u32, u32, u32)> {
+            rx.try_iter().map(|md| match md {
+                MarketData::Order(up) => (up.seq(), up.oid(), up.action(),
+                                up.qty(), up.exec_qty(), up.position()),
+                _ => panic!("order update expected"),
+            }).collect()
+        };
+        assert!(me.send_order(1, true, 43000, 5).is_ok());
+        assert!(me.send_order(1, false, 43100, 7).is_ok());
+        assert!(me.send_order(1, false, 43000, 12).is_ok());
+        assert_eq!(ups(), vec![(1, 2, Add, 5, 0, 1), (2, 3, Add, 7, 0, 0),
+                               (3, 1, Execute, 0, 10, 0),
+                               (4, 2, Execute, 3, 2, 0)]);
+        bids.remove(0);
+        bids.push((2, 43000, 3));
+        let snap = me.book_snapshot(1).unwrap();
+        assert_eq!((snap.seq(), snap.bids()), (4, &bids[..]));
+        assert_eq!(snap.asks(), &[(3, 43100, 7)]);
+        // amend in place, re-keyed, cancel
+        assert_eq!(me.amend_order(2, 43000, 4), Ok(2));
+        assert_eq!(me.amend_order(3, 43200, 7), Ok(5));
+        assert_eq!(me.cancel_order(2), Ok(2));
+        assert_eq!(ups(), vec![(5, 2, Modify, 2, 0, 0), (6, 3, Delete, 0, 0, 0),
+                               (7, 5, Add, 7, 0, 0), (8, 2, Delete, 0, 0, 0)]);
+        // uncross executes both sides
+        assert!(me.send_order(1, true, 43000, 6).is_ok());
+        assert!(me.auction_symbol(1).is_ok());
+        assert!(me.send_order(1, false, 42900, 4).is_ok());
+        assert!(me.reopen_symbol(1).is_ok());
+        assert_eq!(ups(), vec![(9, 6, Add, 6, 0, 0), (10, 7, Add, 4, 0, 0),
+                               (11, 6, Execute, 2, 4, 0),
+                               (12, 7, Execute, 0, 4, 0)]);
+    }
+
+    #[test]
+    fn test_symbol_spec() {
+        if let Err(s) = SimpleLogger::new().init() {
+            warn!("SimpleLogger init: {}", s);
+        }
+        log::set_max_level(LevelFilter::Info);
+        let csv = "name,digits,price_step,lot_size,vol_min,vol_max\n\
+                   cu1906,0,10,5,5,1000\n";
+        let syms = Symbols::read_csv(csv.as_bytes()).unwrap();
+        let mut me = MatchEngine::with_symbols(syms);
+        assert!(me.begin_market().is_ok());
+        assert!(me.start_trading().is_ok());
+        let rej = |r| Err(EngineError::Rejected(r));
+        assert_eq!(me.send_order(2, true, 43000, 5),
+                   rej(Reject::UnknownSymbol));
+        assert_eq!(me.send_order(1, true, 43005, 5), rej(Reject::PriceTick));
+        assert_eq!(me.send_order(1, true, 43000, 0), rej(Reject::QtyMin));
+        assert_eq!(me.send_order(1, true, 43000, 1005), rej(Reject::QtyMax));
+        assert_eq!(me.send_order(1, true, 43000, 12), rej(Reject::QtyLot));
+        assert_eq!(me.send_order(1, true, 43000, 10), Ok(1));
+        assert!(me.send_order_type(1, false, 1, 5, OrderType::Market,
+                                   TimeInForce::IOC).is_ok());
+        assert_eq!(me.deals().deal_no(), 2);
+        // trading limits kept in snapshot
+        let mut buf = Vec::<u8>::new();
+        me.snapshot(&mut buf).unwrap();
+        let mut me2 = MatchEngine::new();
+        me2.restore(buf.as_slice()).unwrap();
+        assert_eq!(me2.send_order(1, false, 43000, 7), rej(Reject::QtyLot));
+        assert_eq!(me2.symbol_idx("cu1908"), None);
+        // amend re-keyed checked as new order, remain qty 5
+        assert_eq!(me.amend_order(1, 43005, 10), rej(Reject::PriceTick));
+        assert_eq!(me.amend_order(1, 43000, 12), rej(Reject::QtyLot));
+        assert_eq!(me.amend_order(1, 43000, 1010), rej(Reject::QtyMax));
+        assert_eq!(me.order(1).unwrap().remain_qty(), 5);
+        assert_eq!(me.amend_order(1, 42990, 15), Ok(3));
+    }
+
+    #[test]
+    fn test_snapshot() {
+        if let Err(s) = SimpleLogger::new().init() {
+            warn!("SimpleLogger init: {}", s);
+        }
+        log::set_max_level(LevelFilter::Info);
+        let orders1 = "1, 44000, 50, 1\n\
+2,43500,45,0\n\
+3,43900,30,1\n\
+4,44100,20,0\n\
+5,43900,10,1\n\
+6,44200,20,0\n";
+        let mut me = MatchEngine::new();
+        assert!(me.begin_market().is_ok());
+        assert!(me.start_trading().is_ok());
+        let orders = me.build_orders(2, orders1);
+        assert!(me.cancel_order(orders[2]).is_ok());
+        assert!(me.send_order_type(1, false, 0, 5, OrderType::Market,
+                                   TimeInForce::IOC).is_ok());
+        assert!(me.set_ref_price(2, 44000).is_ok());
+        assert!(me.set_trade_price(TradePrice::Aggressor).is_ok());
+        assert!(me.set_auction_rules(PriceRules::standard()).is_ok());
+        assert!(me.set_symbol_auction_rules(2, Some(PriceRules::Chain(
+                                    vec![TieBreak::Midpoint]))).is_ok());
+        let mut buf = Vec::<u8>::new();
+        me.snapshot(&mut buf).unwrap();
+        let snap_no = me.deals().deal_no();
+        let mut me2 = MatchEngine::new();
+        assert!(me2.restore(&buf[..7]).is_err());
+        me2.restore(buf.as_slice()).unwrap();
+        assert!(me2.state == State::StateTrading);
+        assert_eq!(me2.symbol_idx("cu1908"), Some(2));
+        assert_eq!(me2.ref_price(2), Some(44000));
+        assert_eq!(me2.price_rule, TradePrice::Aggressor);
+        assert_eq!(me2.price_rules(1), &PriceRules::standard());
+        assert_eq!(me2.price_rules(2), me.price_rules(2));
+        assert_eq!(me2.pool().len(), me.pool().len());
+        assert!(me2.order(orders[2]).unwrap().is_canceled());
+        assert_eq!(me2.order(orders[0]).unwrap().filled(), 45);
+        assert!(me2.book(2).unwrap() == me.book(2).unwrap());
+        assert!(me2.book(2).unwrap().validate(me2.pool()));
+        assert_eq!(me2.level1(2), me.level1(2));
+        assert_eq!(me2.deals().deal_no(), me.deals().deal_no());
+        // continue matching, same result
+        for me in [&mut me, &mut me2] {
+            assert_eq!(me.send_order(2, false, 43800, 30), Ok(8));
+            assert_eq!(me.amend_order(orders[5], 43900, 20), Ok(9));
+        }
+        assert_eq!(me2.deals().deal_no(), me.deals().deal_no());
+        assert_eq!(me2.deals().match_no(), me.deals().match_no());
+        assert!(me.deals().deal_no() > snap_no);
+        for no in snap_no+1..=me.deals().deal_no() {
+            assert!(me2.deals().get(no) == me.deals().get(no));
+        }
+        assert!(me2.deals().get(snap_no).is_none());
+        assert!(me2.book(2).unwrap() == me.book(2).unwrap());
+    }
+
     #[test]
     fn test_cross() {
         if let Err(s) = SimpleLogger::new().init() {
@@ -634,39 +2452,449 @@ mod tests {
 7, 43200, 20, 0\n";
         let mut me = MatchEngine::new();
         assert!(me.state.eq(&State::StateIdle));
-        assert!(me.begin_market());
-        assert!(me.start_market());
+        assert!(me.begin_market().is_ok());
+        assert!(me.start_market().is_ok());
         assert_eq!(me.build_orders(1, orders1).len(), 12);
         let orb = me.book(1);
         assert!(orb != None);
         let mc_ret = me.match_cross(1, 40000);
-        assert!(mc_ret == Some((43900, 75, 0)));
+        assert!(mc_ret == Ok(Some((43900, 75, 0))));
         let mc_ret = me.match_cross(1, 50000);
-        assert!(mc_ret == Some((43900, 75, 0)));
-        assert!(me.stop_trading());
-        assert!(me.init_market());
+        assert!(mc_ret == Ok(Some((43900, 75, 0))));
+        assert!(me.stop_trading().is_ok());
+        assert!(me.init_market().is_ok());
         //let mut me = MatchEngine::new();
         //assert!(me.state.eq(&State::StateIdle));
-        assert!(me.begin_market());
-        assert!(me.start_market());
+        assert!(me.begin_market().is_ok());
+        assert!(me.start_market().is_ok());
         assert!(me.build_orders(1, orders2).len() == 7);
         let orb = me.book(1);
         assert!(orb != None);
         let mc_ret = me.match_cross(1, 40000);
-        assert!(mc_ret == Some((43500, 75, 0)));
+        assert!(mc_ret == Ok(Some((43500, 75, 0))));
         let mc_ret = me.match_cross(1, 50000);
-        assert!(mc_ret == Some((43900, 75, 0)));
-        assert!(me.stop_trading());
-        assert!(me.init_market());
-        assert!(me.begin_market());
-        assert!(me.start_market());
+        assert!(mc_ret == Ok(Some((43900, 75, 0))));
+        assert!(me.stop_trading().is_ok());
+        assert!(me.init_market().is_ok());
+        assert!(me.begin_market().is_ok());
+        assert!(me.start_market().is_ok());
         assert_eq!(me.build_orders(1, orders3).len(), 7);
         let orb = me.book(1);
         assert!(orb != None);
         let mc_ret = me.match_cross(1, 40000);
-        assert!(mc_ret == Some((43900, 65, 10)));
+        assert!(mc_ret == Ok(Some((43900, 65, 10))));
         let mc_ret = me.match_cross(1, 50000);
-        assert!(mc_ret == Some((43900, 65, 10)));
+        assert!(mc_ret == Ok(Some((43900, 65, 10))));
+    }
+
+    #[test]
+    fn test_uncross() {
+        if let Err(s) = SimpleLogger::new().init() {
+            warn!("SimpleLogger init: {}", s);
+        }
+        log::set_max_level(LevelFilter::Info);
+        let orders1 = "1, 43000, 20, 1\n\
+2, 44000, 50, 1\n\
+3, 43900, 15, 1\n\
+4, 45000, 10, 0\n\
+5, 43500, 45, 0\n\
+6, 43200, 10, 0\n\
+7, 43200, 20, 0\n";
+        let mut me = MatchEngine::new();
+        assert!(me.begin_market().is_ok());
+        assert!(me.start_market().is_ok());
+        let orders = me.build_orders(1, orders1);
+        assert_eq!(orders.len(), 7);
+        let (last, qty, _) = me.match_cross(1, 40000).unwrap().unwrap();
+        assert_eq!((last, qty), (43900, 65));
+        assert!(me.uncross(1, last, qty).is_ok());
+        assert_eq!(me.book(1).unwrap().len(), (1, 2));
+        let oids: Vec<u32> = orders.iter().map(|x| *x as u32).collect();
+        // bid 44000x50 w/ asks 43200x10, 43200x20, 43500x20
+        // bid 43900x15 w/ ask 43500x15
+        let deals1 = vec![Deal::new(1, 1, oids[1], 43900, 10)
+                            .with_side(1, true, oids[5], Liquidity::Auction),
+                        Deal::new(2, 1, oids[5], 43900, 10)
+                            .with_side(1, false, oids[1], Liquidity::Auction),
+                        Deal::new(3, 2, oids[1], 43900, 20)
+                            .with_side(1, true, oids[6], Liquidity::Auction),
+                        Deal::new(4, 2, oids[6], 43900, 20)
+                            .with_side(1, false, oids[1], Liquidity::Auction),
+                        Deal::new(5, 3, oids[1], 43900, 20)
+                            .with_side(1, true, oids[4], Liquidity::Auction),
+                        Deal::new(6, 3, oids[4], 43900, 20)
+                            .with_side(1, false, oids[1], Liquidity::Auction),
+                        Deal::new(7, 4, oids[2], 43900, 15)
+                            .with_side(1, true, oids[4], Liquidity::Auction),
+                        Deal::new(8, 4, oids[4], 43900, 15)
+                            .with_side(1, false, oids[2], Liquidity::Auction)];
+        assert!(me.deals().eq(&deals1));
+        assert!(me.deals().get(9).is_none());
+        assert!(me.book(1).unwrap().validate(me.pool()));
+    }
+
+    #[test]
+    fn test_call_auction() {
+        if let Err(s) = SimpleLogger::new().init() {
+            warn!("SimpleLogger init: {}", s);
+        }
+        log::set_max_level(LevelFilter::Info);
+        let orders1 = "1, 43000, 20, 1\n\
+2, 44000, 50, 1\n\
+3, 43900, 15, 1\n\
+4, 45000, 10, 0\n\
+5, 43500, 45, 0\n\
+6, 43200, 10, 0\n\
+7, 43200, 20, 0\n";
+        let orders2 = "1, 43000, 20, 1\n\
+2, 44000, 10, 0\n";
+        let mut me = MatchEngine::new();
+        assert!(me.begin_market().is_ok());
+        assert!(me.start_market().is_ok());
+        assert!(me.set_ref_price(1, 40000).is_ok());
+        assert!(me.set_ref_price(2, 43500).is_ok());
+        assert_eq!(me.build_orders(1, orders1).len(), 7);
+        // symbol 2 not crossed, symbol 3 w/o reference price
+        assert_eq!(me.build_orders(2, orders2).len(), 2);
+        assert_eq!(me.build_orders(3, orders1).len(), 7);
+        assert!(me.call_auction().is_ok());
+        assert_eq!(me.auction_results(),
+                   &[AuctionResult::new(1, 43900, 65, 10),
+                     AuctionResult::new(3, 43900, 65, 10)]);
+        assert_eq!(me.ref_price(1), Some(43900));
+        assert_eq!(me.ref_price(2), Some(43500));
+        assert_eq!(me.ref_price(3), Some(43900));
+        // deals of symbol 1 before symbol 3
+        assert_eq!(me.deals().deal_no(), 16);
+        assert_eq!(me.deals().get(8).unwrap().symbol(), 1);
+        assert_eq!(me.deals().get(9).unwrap().symbol(), 3);
+        for sym in 1..=3 {
+            assert!(me.book(sym).unwrap().validate(me.pool()));
+        }
+        assert_eq!(me.book(1).unwrap().len(), (1, 2));
+        assert_eq!(me.book(2).unwrap().len(), (1, 1));
+        assert!(me.start_trading().is_ok());
+    }
+
+    #[test]
+    fn test_indicative() {
+        if let Err(s) = SimpleLogger::new().init() {
+            warn!("SimpleLogger init: {}", s);
+        }
+        log::set_max_level(LevelFilter::Info);
+        let orders1 = "1, 43000, 20, 1\n\
+2, 44000, 50, 1\n\
+3, 43900, 15, 1\n\
+4, 45000, 10, 0\n\
+5, 43500, 45, 0\n\
+6, 43200, 10, 0\n\
+7, 43200, 20, 0\n";
+        let published = Arc::new(Mutex::new(Vec::<Option<Indicative>>::new()));
+        let mut me = MatchEngine::new();
+        let pub1 = published.clone();
+        me.set_indicative_callback(Some(Box::new(move |sym, ind| {
+            assert_eq!(sym, 1);
+            pub1.lock().unwrap().push(ind);
+        })));
+        assert!(me.begin_market().is_ok());
+        assert!(me.start_market().is_ok());
+        assert!(me.set_ref_price(1, 40000).is_ok());
+        // indicative same as full book rescan
+        let check = |me: &mut MatchEngine| {
+            let ind = me.indicative(1);
+            assert_eq!(ind.map(|i| (i.price(), i.qty(), i.imbalance())),
+                       me.match_cross(1, 40000).unwrap());
+            ind
+        };
+        let mut orders = Vec::<u64>::new();
+        for aline in orders1.lines() {
+            orders.append(&mut me.build_orders(1, aline));
+            check(&mut me);
+        }
+        let ind = check(&mut me).unwrap();
+        assert_eq!(ind, Indicative::new(43900, 65, 10, false));
+        assert_eq!(ind.imbalance_side(), Some(false));
+        // no cross until order 5 arrived
+        assert_eq!(published.lock().unwrap().len(), 3);
+        assert!(me.cancel_order(orders[2]).is_ok());
+        assert_eq!(check(&mut me), Some(Indicative::new(44000, 50, 25, false)));
+        assert!(me.amend_order(orders[1], 44000, 20).is_ok());
+        assert_eq!(check(&mut me), Some(Indicative::new(44000, 20, 10, false)));
+        // unchanged indicative not published
+        assert!(me.amend_order(orders[4], 43600, 45).is_ok());
+        assert_eq!(check(&mut me), Some(Indicative::new(44000, 20, 10, false)));
+        assert_eq!(published.lock().unwrap().len(), 5);
+        assert!(me.call_auction().is_ok());
+        assert!(me.indicative(1).is_none());
+        assert_eq!(me.auction_results()[0].price(),
+                   published.lock().unwrap().last().unwrap().unwrap().price());
+    }
+
+    #[test]
+    fn test_auction_rules() {
+        if let Err(s) = SimpleLogger::new().init() {
+            warn!("SimpleLogger init: {}", s);
+        }
+        log::set_max_level(LevelFilter::Info);
+        let orders1 = "1, 43000, 20, 1\n\
+2, 44000, 50, 1\n\
+3, 43900, 15, 1\n\
+4, 45000, 10, 0\n\
+5, 43500, 45, 0\n\
+6, 43200, 10, 0\n\
+7, 43200, 20, 0\n";
+        let mut me = MatchEngine::new();
+        assert!(me.begin_market().is_ok());
+        assert!(me.start_market().is_ok());
+        for sym in 1..=2 {
+            assert!(me.set_ref_price(sym, 50000).is_ok());
+            assert_eq!(me.build_orders(sym, orders1).len(), 7);
+        }
+        assert_eq!(me.indicative(1), Some(Indicative::new(43900, 65, 10,
+                                                          false)));
+        // sell surplus at 43500 and 43900, market pressure lowest price
+        assert!(me.set_auction_rules(PriceRules::standard()).is_ok());
+        assert!(me.set_symbol_auction_rules(2, Some(PriceRules::Classic))
+                    .is_ok());
+        assert_eq!(me.match_cross(1, 50000), Ok(Some((43500, 65, 10))));
+        assert_eq!(me.match_cross(2, 50000), Ok(Some((43900, 65, 10))));
+        assert_eq!(me.indicative(1), Some(Indicative::new(43500, 65, 10,
+                                                          false)));
+        assert!(me.set_symbol_auction_rules(2, Some(PriceRules::Chain(
+                                    vec![TieBreak::Midpoint]))).is_ok());
+        assert_eq!(me.price_rules(1), &PriceRules::standard());
+        assert_eq!(me.indicative(2).unwrap().price(), 43700);
+        assert!(me.call_auction().is_ok());
+        assert_eq!(me.auction_results(),
+                   &[AuctionResult::new(1, 43500, 65, 10),
+                     AuctionResult::new(2, 43700, 65, 10)]);
+        // reference price off tick, auction on tick of symbol
+        let syms = Symbols::read_csv("cu1906,0,100,1,1,1000\n".as_bytes())
+                    .unwrap();
+        let mut me = MatchEngine::with_symbols(syms);
+        assert!(me.begin_market().is_ok());
+        assert!(me.start_market().is_ok());
+        assert!(me.set_ref_price(1, 43250).is_ok());
+        assert!(me.set_auction_rules(PriceRules::standard()).is_ok());
+        assert!(me.send_order(1, true, 43500, 10).is_ok());
+        assert!(me.send_order(1, false, 43000, 10).is_ok());
+        assert_eq!(me.match_cross(1, 43250), Ok(Some((43200, 10, 0))));
+        assert_eq!(me.indicative(1).unwrap().price(), 43200);
+    }
+
+    #[test]
+    fn test_price_limits() {
+        if let Err(s) = SimpleLogger::new().init() {
+            warn!("SimpleLogger init: {}", s);
+        }
+        log::set_max_level(LevelFilter::Info);
+        let csv = "cu1906,0,10,1,1,1000,43000,45000\n";
+        let syms = Symbols::read_csv(csv.as_bytes()).unwrap();
+        let mut me = MatchEngine::with_symbols(syms);
+        assert!(me.begin_market().is_ok());
+        assert!(me.start_market().is_ok());
+        assert!(me.set_ref_price(1, 44000).is_ok());
+        assert_eq!(me.send_order(1, true, 45010, 5),
+                   Err(EngineError::Rejected(Reject::PriceLimit)));
+        assert!(me.send_order(1, true, 45000, 5).is_ok());
+        assert!(me.send_order(1, false, 43000, 10).is_ok());
+        assert_eq!(me.indicative(1), Some(Indicative::new(45000, 5, 5,
+                                                          false)));
+        // limits narrowed intraday, equilibrium inside new limits
+        assert!(me.set_price_limits(1, 44500, 43500).is_err());
+        assert!(me.set_price_limits(5, 43500, 44500).is_err());
+        assert!(me.set_price_limits(1, 43500, 44500).is_ok());
+        assert_eq!(me.indicative(1), Some(Indicative::new(44500, 5, 5,
+                                                          false)));
+        assert_eq!(me.send_order(1, true, 45000, 5),
+                   Err(EngineError::Rejected(Reject::PriceLimit)));
+        assert_eq!(me.amend_order(1, 44990, 10),
+                   Err(EngineError::Rejected(Reject::PriceLimit)));
+        let mut buf = Vec::<u8>::new();
+        me.snapshot(&mut buf).unwrap();
+        assert!(me.call_auction().is_ok());
+        assert_eq!(me.auction_results(), &[AuctionResult::new(1, 44500, 5, 5)]);
+        assert_eq!(me.deals().get(1).unwrap().price(), 44500);
+        let mut me2 = MatchEngine::new();
+        me2.restore(buf.as_slice()).unwrap();
+        assert_eq!(me2.indicative(1), Some(Indicative::new(44500, 5, 5,
+                                                           false)));
+        assert_eq!(me2.send_order(1, false, 43000, 5),
+                   Err(EngineError::Rejected(Reject::PriceLimit)));
+        // ask above new limit up out of cross, not sold at limit up
+        let csv = "cu1906,0,100,1,1,1000,40000,48000\n";
+        let syms = Symbols::read_csv(csv.as_bytes()).unwrap();
+        let mut me = MatchEngine::with_symbols(syms);
+        assert!(me.begin_market().is_ok());
+        assert!(me.start_market().is_ok());
+        assert!(me.send_order(1, true, 44000, 10).is_ok());
+        assert!(me.send_order(1, false, 43900, 5).is_ok());
+        assert!(me.send_order(1, false, 44400, 5).is_ok());
+        let ind = me.indicative(1);
+        assert_eq!(ind.map(|i| (i.price(), i.qty())), Some((43900, 5)));
+        assert!(me.set_price_limits(1, 43500, 44000).is_ok());
+        assert_eq!(me.indicative(1), ind);
+        assert_eq!(me.match_cross(1, 0), Ok(Some((43900, 5, 5))));
+        // uncross all or nothing
+        assert_eq!(me.uncross(1, 44000, 10), Err(EngineError::UncrossShort));
+        assert_eq!(me.deals().deal_no(), 0);
+        assert_eq!(me.order(1).unwrap().remain_qty(), 10);
+        assert!(me.call_auction().is_ok());
+        assert_eq!(me.auction_results(), &[AuctionResult::new(1, 43900, 5, 5)]);
+        assert_eq!(me.deals().deal_no(), 2);
+        assert_eq!(me.ref_price(1), Some(43900));
+        assert_eq!(me.order(3).unwrap().remain_qty(), 5);
+    }
+
+    #[test]
+    fn test_volatility() {
+        if let Err(s) = SimpleLogger::new().init() {
+            warn!("SimpleLogger init: {}", s);
+        }
+        log::set_max_level(LevelFilter::Info);
+        let path = std::env::temp_dir().join(format!("volatility-{}",
+                                                      std::process::id()));
+        let path = path.to_str().unwrap();
+        assert!(Journal::remove(path).is_ok());
+        let mut me = MatchEngine::new();
+        me.set_journal(Some(Journal::create(path).unwrap()));
+        assert!(me.begin_market().is_ok());
+        assert!(me.start_trading().is_ok());
+        // 1% band, 43560 .. 44440
+        assert!(me.set_ref_price(1, 44000).is_ok());
+        assert!(me.set_volatility_band(1, Some(100)).is_ok());
+        assert!(me.send_order(1, false, 44000, 10).is_ok());
+        assert!(me.send_order(1, false, 44500, 10).is_ok());
+        assert!(me.send_order(2, false, 44500, 10).is_ok());
+        assert!(me.reopen_symbol(1).is_err());
+        // 2nd level out of band, stop matching
+        let oid = me.send_order(1, true, 44600, 15).unwrap();
+        assert_eq!(me.order(oid).unwrap().remain_qty(), 5);
+        assert_eq!(me.deals().deal_no(), 2);
+        assert!(me.symbol_state(1) == State::StatePreAuction);
+        assert!(me.symbol_state(2) == State::StateTrading);
+        assert_eq!(me.book(1).unwrap().len(), (1, 1));
+        assert_eq!(me.indicative(1), Some(Indicative::new(44600, 5, 5,
+                                                          false)));
+        // collect orders w/o matching, immediate orders rejected
+        assert!(me.send_order_type(1, true, 0, 5, OrderType::Market,
+                                   TimeInForce::IOC).is_err());
+        assert!(me.send_order(1, true, 44500, 5).is_ok());
+        assert_eq!(me.deals().deal_no(), 2);
+        assert!(me.send_order(2, true, 44600, 5).is_ok());
+        assert_eq!(me.deals().deal_no(), 4);
+        // pause keep volatility auction
+        assert!(me.pause_trading().is_ok());
+        assert!(me.start_trading().is_ok());
+        assert!(me.symbol_state(1) == State::StatePreAuction);
+        assert_eq!(me.reopen_symbol(1),
+                   Ok(Some(AuctionResult::new(1, 44500, 10, 0))));
+        assert!(me.symbol_state(1) == State::StateTrading);
+        assert!(me.indicative(1).is_none());
+        assert_eq!(me.ref_price(1), Some(44500));
+        assert_eq!(me.book(1).unwrap().len(), (0, 0));
+        assert_eq!(me.deals().deal_no(), 8);
+        assert_eq!(me.reopen_symbol(1), Err(EngineError::InvalidState));
+        // band kept in snapshot and journal
+        let mut buf = Vec::<u8>::new();
+        me.snapshot(&mut buf).unwrap();
+        let mut me2 = MatchEngine::new();
+        me2.restore(buf.as_slice()).unwrap();
+        me.set_journal(None);
+        let mut me3 = MatchEngine::new();
+        me3.replay(&Journal::load(path).unwrap());
+        assert_eq!(me3.deals().deal_no(), 8);
+        for me in [&mut me, &mut me2, &mut me3] {
+            // band follow new reference price
+            assert!(me.send_order(1, false, 44900, 10).is_ok());
+            assert!(me.send_order(1, true, 44900, 10).is_ok());
+            assert!(me.symbol_state(1) == State::StateTrading);
+            assert_eq!(me.deals().deal_no(), 10);
+            // 44055 .. 44945
+            assert!(me.send_order(1, false, 45000, 10).is_ok());
+            assert!(me.send_order(1, true, 45000, 10).is_ok());
+            assert!(me.symbol_state(1) == State::StatePreAuction);
+            assert_eq!(me.deals().deal_no(), 10);
+        }
+        assert_eq!(Journal::remove(path).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_closing_auction() {
+        if let Err(s) = SimpleLogger::new().init() {
+            warn!("SimpleLogger init: {}", s);
+        }
+        log::set_max_level(LevelFilter::Info);
+        let mut me = MatchEngine::new();
+        assert!(me.begin_market().is_ok());
+        assert!(me.start_trading().is_ok());
+        assert!(me.send_order(1, true, 43000, 10).is_ok());
+        assert!(me.send_order(1, false, 43500, 10).is_ok());
+        assert!(me.closing_call().is_ok());
+        assert!(me.state == State::StatePreAuction);
+        // collect crossed orders
+        assert!(me.send_order(1, true, 43600, 5).is_ok());
+        assert_eq!(me.deals().deal_no(), 0);
+        assert_eq!(me.indicative(1), Some(Indicative::new(43600, 5, 5,
+                                                          false)));
+        assert!(me.call_auction().is_ok());
+        assert_eq!(me.auction_results(),
+                   &[AuctionResult::new(1, 43600, 5, 5)]);
+        assert_eq!(me.deals().deal_no(), 2);
+        assert!(me.stop_trading().is_ok());
+        assert_eq!(me.book(1).unwrap().len(), (0, 0));
+    }
+
+    #[test]
+    fn test_symbol_state() {
+        if let Err(s) = SimpleLogger::new().init() {
+            warn!("SimpleLogger init: {}", s);
+        }
+        log::set_max_level(LevelFilter::Info);
+        let mut me = MatchEngine::new();
+        assert!(me.begin_market().is_ok());
+        assert!(me.halt_symbol(1).is_err());
+        assert!(me.start_trading().is_ok());
+        assert!(me.set_ref_price(1, 44050).is_ok());
+        let oid = me.send_order(1, false, 44000, 10).unwrap();
+        // halted symbol cancel only
+        assert!(me.halt_symbol(1).is_ok());
+        assert!(me.symbol_state(1) == State::StatePause);
+        assert!(me.auction_symbol(1).is_err());
+        assert!(me.send_order(1, true, 44000, 5).is_err());
+        assert_eq!(me.amend_order(oid, 44000, 5),
+                   Err(EngineError::InvalidState));
+        let oid2 = me.send_order(2, false, 44000, 10).unwrap();
+        assert!(me.send_order(2, true, 44000, 5).is_ok());
+        assert_eq!(me.deals().deal_no(), 2);
+        assert_eq!(me.cancel_order(oid2), Ok(5));
+        // market state as upper bound
+        assert!(me.halt_symbol(2).is_ok());
+        assert!(me.resume_symbol(1).is_ok());
+        assert!(me.pause_trading().is_ok());
+        assert!(me.symbol_state(1) == State::StatePause);
+        assert!(me.resume_symbol(2).is_err());
+        assert!(me.start_trading().is_ok());
+        assert!(me.symbol_state(1) == State::StateTrading);
+        assert!(me.symbol_state(2) == State::StatePause);
+        assert_eq!(me.execute(&Command::SymbolState { sym: 2,
+                                        state: State::StateTrading }),
+                   Reply::SymbolState(Ok(())));
+        assert!(me.change_symbol_state(2, State::StateStart).is_err());
+        assert!(me.send_order(1, true, 44000, 5).is_ok());
+        assert_eq!(me.deals().deal_no(), 4);
+        // symbol call auction, no matching until reopen
+        assert!(me.auction_symbol(1).is_ok());
+        assert!(me.symbol_state(1) == State::StatePreAuction);
+        assert!(me.send_order(1, true, 44100, 10).is_ok());
+        assert_eq!(me.deals().deal_no(), 4);
+        assert!(me.indicative(1).is_some());
+        let res = me.reopen_symbol(1).unwrap().unwrap();
+        assert_eq!((res.symbol(), res.qty(), res.remain()), (1, 5, 5));
+        assert!(me.symbol_state(1) == State::StateTrading);
+        assert!(me.indicative(1).is_none());
+        assert_eq!(me.deals().deal_no(), 6);
+        assert_eq!(me.reopen_symbol(1), Err(EngineError::InvalidState));
     }
 
     #[test]
@@ -681,8 +2909,8 @@ mod tests {
         log::set_max_level(LevelFilter::Info);
         let mut me = MatchEngine::new();
         assert!(me.state.eq(&State::StateIdle));
-        assert!(me.begin_market());
-        assert!(me.start_market());
+        assert!(me.begin_market().is_ok());
+        assert!(me.start_market().is_ok());
         let long_filen: &str = "/tmp/long.txt.zst";
         let short_filen: &str = "/tmp/short.txt.zst";
         if ! std::path::Path::new(long_filen).exists() {
@@ -690,21 +2918,21 @@ mod tests {
             warn!("no long/short orders file, SKIP match_cross bench");
             return
         }
-        assert!(me.load_orders(1, long_filen));
-        assert!(me.load_orders(1, short_filen));
+        assert!(me.load_orders(1, long_filen).is_ok());
+        assert!(me.load_orders(1, short_filen).is_ok());
         //println!("Before UnCross qlen: {}/{}", blen, alen);
         let mut measure = Measure::start("cross bench");
         let mc_ret = me.match_cross(1, 50000);
         measure.stop();
-        assert!(Some((50500, 2753442, 25718)) == mc_ret);
-        let (last, qty, rem_qty) = mc_ret.unwrap();
+        assert!(Ok(Some((50500, 2753442, 25718))) == mc_ret);
+        let (last, qty, rem_qty) = mc_ret.unwrap().unwrap();
         println!("MatchCross last: {}, volume: {}, remain: {}",
               last, qty, rem_qty);
         println!("MatchCross cost {}us", measure.as_us());
-        assert!(me.uncross(1, last, qty), "uncross failed");
+        assert!(me.uncross(1, last, qty).is_ok(), "uncross failed");
         // now benchmark trading continue
-        assert!(me.call_auction()); // do nothing currently
-        assert!(me.start_trading());
+        assert!(me.call_auction().is_ok()); // book already uncrossed
+        assert!(me.start_trading().is_ok());
         const N: u32 = 2_000_000;
         let mut rng = rand::thread_rng();
         let mut measure = Measure::start("TC bench");
@@ -713,7 +2941,7 @@ mod tests {
             let price = (rng.gen::<i32>() % 10000) + 40000;
             let qty: u32 = (rng.gen::<u32>() % 200) + 1;
             let buy: bool = (rng.gen::<u32>() & 1) != 0;
-            me.send_order(1, buy, price, qty);
+            assert!(me.send_order(1, buy, price, qty).is_ok());
         }
         measure.stop();
         let ns_ops = measure.as_ns() / (N as u64);
@@ -0,0 +1,426 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Read, Write};
+use log::{info, warn};
+use match_base::{OrderType, TimeInForce};
+use crate::{state::State, command::Command, engine::TradePrice};
+
+// write-ahead log of inbound commands, appended before command applied.
+// segments named {path}.{seg:06}, full segment rotated and compressed as
+// {path}.{seg:06}.zst; replay all segments in order rebuild same state.
+// restart: restore snapshot or replay load(path), then open(path) append
+pub struct Journal {
+    path:   String,
+    seg:    u32,
+    seg_size: u64,
+    written: u64,
+    sync:   bool,       // sync_data each record
+    w:      BufWriter<File>,
+}
+
+const TAG_NEW_ORDER: u8 = 1;
+const TAG_CANCEL: u8 = 2;
+const TAG_AMEND: u8 = 3;
+const TAG_STATE: u8 = 4;
+const TAG_REF_PRICE: u8 = 5;
+const TAG_REOPEN: u8 = 6;
+const TAG_SYM_STATE: u8 = 7;
+const TAG_PRICE_LIMIT: u8 = 8;
+const TAG_TRADE_PRICE: u8 = 9;
+const TAG_AUCTION_RULES: u8 = 10;
+const TAG_SYM_RULES: u8 = 11;
+const TAG_VOL_BAND: u8 = 12;
+// default segment size before rotate
+const SEG_SIZE: u64 = 64 << 20;
+
+fn seg_name(path: &str, seg: u32) -> String {
+    format!("{}.{:06}", path, seg)
+}
+
+// binary record, little endian: tag u8 and fixed fields per tag
+pub fn encode(cmd: &Command, buf: &mut Vec<u8>) {
+    match *cmd {
+        Command::NewOrder { sym, buy, price, qty, ord_type, tif } => {
+            buf.push(TAG_NEW_ORDER);
+            buf.extend_from_slice(&sym.to_le_bytes());
+            buf.push(buy as u8);
+            buf.extend_from_slice(&price.to_le_bytes());
+            buf.extend_from_slice(&qty.to_le_bytes());
+            buf.push(ord_type as u8);
+            buf.push(tif as u8);
+        },
+        Command::Cancel { oid } => {
+            buf.push(TAG_CANCEL);
+            buf.extend_from_slice(&oid.to_le_bytes());
+        },
+        Command::Amend { oid, price, qty } => {
+            buf.push(TAG_AMEND);
+            buf.extend_from_slice(&oid.to_le_bytes());
+            buf.extend_from_slice(&price.to_le_bytes());
+            buf.extend_from_slice(&qty.to_le_bytes());
+        },
+        Command::ChangeState(state) => {
+            buf.push(TAG_STATE);
+            buf.push(state as u8);
+        },
+        Command::RefPrice { sym, price } => {
+            buf.push(TAG_REF_PRICE);
+            buf.extend_from_slice(&sym.to_le_bytes());
+            buf.extend_from_slice(&price.to_le_bytes());
+        },
+        Command::Reopen { sym } => {
+            buf.push(TAG_REOPEN);
+            buf.extend_from_slice(&sym.to_le_bytes());
+        },
+        Command::SymbolState { sym, state } => {
+            buf.push(TAG_SYM_STATE);
+            buf.extend_from_slice(&sym.to_le_bytes());
+            buf.push(state as u8);
+        },
+        Command::PriceLimit { sym, limit_down, limit_up } => {
+            buf.push(TAG_PRICE_LIMIT);
+            buf.extend_from_slice(&sym.to_le_bytes());
+            buf.extend_from_slice(&limit_down.to_le_bytes());
+            buf.extend_from_slice(&limit_up.to_le_bytes());
+        },
+        Command::TradePrice(rule) => {
+            buf.push(TAG_TRADE_PRICE);
+            buf.push(rule as u8);
+        },
+        Command::AuctionRules { rules } => {
+            buf.push(TAG_AUCTION_RULES);
+            buf.extend_from_slice(&rules.to_le_bytes());
+        },
+        Command::SymbolRules { sym, rules } => {
+            buf.push(TAG_SYM_RULES);
+            buf.extend_from_slice(&sym.to_le_bytes());
+            buf.push(rules.is_some() as u8);
+            buf.extend_from_slice(&rules.unwrap_or(0).to_le_bytes());
+        },
+        Command::VolBand { sym, bps } => {
+            buf.push(TAG_VOL_BAND);
+            buf.extend_from_slice(&sym.to_le_bytes());
+            buf.push(bps.is_some() as u8);
+            buf.extend_from_slice(&bps.unwrap_or(0).to_le_bytes());
+        },
+    }
+}
+
+#[inline]
+fn get_u32(buf: &[u8], off: usize) -> u32 {
+    u32::from_le_bytes(buf[off..off+4].try_into().unwrap())
+}
+
+#[inline]
+fn get_u64(buf: &[u8], off: usize) -> u64 {
+    u64::from_le_bytes(buf[off..off+8].try_into().unwrap())
+}
+
+// decode one record, returns command and record length
+// None for truncated or bad record
+pub fn decode(buf: &[u8]) -> Option<(Command, usize)> {
+    let tag = *buf.first()?;
+    let rlen = match tag {
+        TAG_NEW_ORDER => 16,
+        TAG_CANCEL => 9,
+        TAG_AMEND => 17,
+        TAG_STATE => 2,
+        TAG_REF_PRICE => 9,
+        TAG_REOPEN => 5,
+        TAG_SYM_STATE => 6,
+        TAG_PRICE_LIMIT => 13,
+        TAG_TRADE_PRICE => 2,
+        TAG_AUCTION_RULES => 5,
+        TAG_SYM_RULES | TAG_VOL_BAND => 10,
+        _ => return None,
+    };
+    if buf.len() < rlen { return None }
+    let cmd = match tag {
+        TAG_NEW_ORDER => Command::NewOrder {
+            sym: get_u32(buf, 1),
+            buy: buf[5] != 0,
+            price: get_u32(buf, 6) as i32,
+            qty: get_u32(buf, 10),
+            ord_type: OrderType::from_u8(buf[14])?,
+            tif: TimeInForce::from_u8(buf[15])?,
+        },
+        TAG_CANCEL => Command::Cancel { oid: get_u64(buf, 1) },
+        TAG_AMEND => Command::Amend {
+            oid: get_u64(buf, 1),
+            price: get_u32(buf, 9) as i32,
+            qty: get_u32(buf, 13),
+        },
+        TAG_REF_PRICE => Command::RefPrice {
+            sym: get_u32(buf, 1),
+            price: get_u32(buf, 5) as i32,
+        },
+        TAG_REOPEN => Command::Reopen { sym: get_u32(buf, 1) },
+        TAG_SYM_STATE => Command::SymbolState {
+            sym: get_u32(buf, 1),
+            state: State::from_u8(buf[5])?,
+        },
+        TAG_PRICE_LIMIT => Command::PriceLimit {
+            sym: get_u32(buf, 1),
+            limit_down: get_u32(buf, 5) as i32,
+            limit_up: get_u32(buf, 9) as i32,
+        },
+        TAG_TRADE_PRICE => Command::TradePrice(TradePrice::from_u8(buf[1])?),
+        TAG_AUCTION_RULES => Command::AuctionRules { rules: get_u32(buf, 1) },
+        TAG_SYM_RULES => Command::SymbolRules {
+            sym: get_u32(buf, 1),
+            rules: if buf[5] != 0 { Some(get_u32(buf, 6)) } else { None },
+        },
+        TAG_VOL_BAND => Command::VolBand {
+            sym: get_u32(buf, 1),
+            bps: if buf[5] != 0 { Some(get_u32(buf, 6)) } else { None },
+        },
+        _ => Command::ChangeState(State::from_u8(buf[1])?),
+    };
+    Some((cmd, rlen))
+}
+
+impl Journal {
+    pub fn create(path: &str) -> io::Result<Journal> {
+        Journal::with_seg_size(path, SEG_SIZE)
+    }
+    // start new journal, fails if segments of path exist
+    pub fn with_seg_size(path: &str, seg_size: u64) -> io::Result<Journal> {
+        if Journal::last_segment(path).is_some() {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists,
+                                      format!("journal {} exists", path)))
+        }
+        let w = BufWriter::new(OpenOptions::new().write(true)
+                                .create_new(true).open(seg_name(path, 0))?);
+        Ok(Journal { path: path.to_string(), seg: 0, seg_size, written: 0,
+                     sync: false, w })
+    }
+    // continue journal after restart, append to last segment or start
+    // next one if last compressed. torn record at tail truncated
+    pub fn open(path: &str) -> io::Result<Journal> {
+        let seg = match Journal::last_segment(path) {
+            Some(seg) => seg,
+            None => return Journal::create(path),
+        };
+        let fname = seg_name(path, seg);
+        if fs::metadata(fname.clone() + ".zst").is_ok() {
+            let w = BufWriter::new(OpenOptions::new().write(true)
+                        .create_new(true).open(seg_name(path, seg + 1))?);
+            return Ok(Journal { path: path.to_string(), seg: seg + 1,
+                                seg_size: SEG_SIZE, written: 0, sync: false,
+                                w })
+        }
+        let mut buff = Vec::<u8>::new();
+        File::open(&fname)?.read_to_end(&mut buff)?;
+        let mut off = 0;
+        while let Some((_, rlen)) = decode(&buff[off..]) {
+            off += rlen;
+        }
+        let f = OpenOptions::new().append(true).open(&fname)?;
+        if off < buff.len() {
+            warn!("{} bad record at {}, truncate {} bytes", fname, off,
+                  buff.len() - off);
+            f.set_len(off as u64)?;
+            f.sync_data()?;
+        }
+        info!("journal {} opened at {} bytes", fname, off);
+        Ok(Journal { path: path.to_string(), seg, seg_size: SEG_SIZE,
+                     written: off as u64, sync: false, w: BufWriter::new(f) })
+    }
+    // remove all segments of journal, returns number of segments
+    pub fn remove(path: &str) -> io::Result<u32> {
+        let mut seg = 0;
+        loop {
+            let fname = seg_name(path, seg);
+            let r1 = fs::remove_file(&fname).is_ok();
+            let r2 = fs::remove_file(fname.clone() + ".zst").is_ok();
+            let _ = fs::remove_file(fname + ".zst.tmp");
+            if !r1 && !r2 { break }
+            seg += 1;
+        }
+        Ok(seg)
+    }
+    fn last_segment(path: &str) -> Option<u32> {
+        let exists = |seg| {
+            let fname = seg_name(path, seg);
+            fs::metadata(&fname).is_ok()
+                || fs::metadata(fname + ".zst").is_ok()
+        };
+        if !exists(0) { return None }
+        let mut seg = 0;
+        while exists(seg + 1) {
+            seg += 1;
+        }
+        Some(seg)
+    }
+    // rotate after seg_size bytes written to segment
+    pub fn set_seg_size(&mut self, seg_size: u64) {
+        self.seg_size = seg_size;
+    }
+    // sync_data every append, record survives power loss before command
+    // applied. otherwise flushed to OS only, durable on sync or rotate
+    pub fn set_sync(&mut self, sync: bool) {
+        self.sync = sync;
+    }
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.w.flush()?;
+        self.w.get_ref().sync_data()
+    }
+    pub fn segment(&self) -> u32 {
+        self.seg
+    }
+    // record flushed to file before return, synced if set_sync
+    pub fn append(&mut self, cmd: &Command) -> io::Result<()> {
+        let mut buf = Vec::<u8>::with_capacity(16);
+        encode(cmd, &mut buf);
+        self.w.write_all(&buf)?;
+        self.w.flush()?;
+        if self.sync {
+            self.w.get_ref().sync_data()?;
+        }
+        self.written += buf.len() as u64;
+        if self.written >= self.seg_size {
+            self.rotate()?;
+        }
+        Ok(())
+    }
+    // compress current segment, continue with next segment. .zst renamed
+    // in place once synced, raw segment kept until then for crash
+    pub fn rotate(&mut self) -> io::Result<()> {
+        let fname = seg_name(&self.path, self.seg);
+        let w = OpenOptions::new().append(true)
+                    .create(true).open(seg_name(&self.path, self.seg + 1))?;
+        let mut old = std::mem::replace(&mut self.w, BufWriter::new(w));
+        old.flush()?;
+        old.get_ref().sync_all()?;
+        drop(old);
+        let (zname, tmp) = (fname.clone() + ".zst", fname.clone() + ".zst.tmp");
+        let mut zf = File::create(&tmp)?;
+        zstd::stream::copy_encode(File::open(&fname)?, &mut zf, 3)?;
+        zf.sync_all()?;
+        fs::rename(&tmp, &zname)?;
+        fs::remove_file(&fname)?;
+        info!("journal {} rotated {} bytes", fname, self.written);
+        self.seg += 1;
+        self.written = 0;
+        Ok(())
+    }
+    // read all commands of journal segments in order
+    pub fn load(path: &str) -> io::Result<Vec<Command>> {
+        let mut cmds = Vec::<Command>::new();
+        let mut seg = 0;
+        loop {
+            let fname = seg_name(path, seg);
+            let buff = if let Ok(rdr) = File::open(fname.clone() + ".zst") {
+                zstd::stream::decode_all(rdr)?
+            } else if let Ok(mut rdr) = File::open(&fname) {
+                let mut buff = Vec::<u8>::new();
+                rdr.read_to_end(&mut buff)?;
+                buff
+            } else {
+                break
+            };
+            let mut off = 0;
+            while off < buff.len() {
+                if let Some((cmd, rlen)) = decode(&buff[off..]) {
+                    cmds.push(cmd);
+                    off += rlen;
+                } else {
+                    // torn write on crash
+                    warn!("{} bad record at {}, skip {} bytes", fname, off,
+                          buff.len() - off);
+                    break
+                }
+            }
+            seg += 1;
+        }
+        Ok(cmds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_journal() {
+        let path = std::env::temp_dir().join(format!("journal-{}",
+                                                      std::process::id()));
+        let path = path.to_str().unwrap();
+        let cmds = vec![Command::ChangeState(State::StateStart),
+            Command::NewOrder { sym: 1, buy: true, price: -100, qty: 5,
+                ord_type: OrderType::Market, tif: TimeInForce::FOK },
+            Command::Cancel { oid: 1 << 40 },
+            Command::Amend { oid: 3, price: 43000, qty: 7 },
+            Command::RefPrice { sym: 2, price: -5 },
+            Command::Reopen { sym: 3 },
+            Command::SymbolState { sym: 1, state: State::StatePause },
+            Command::PriceLimit { sym: 1, limit_down: 41000,
+                                  limit_up: 45000 },
+            Command::TradePrice(TradePrice::Aggressor),
+            Command::AuctionRules { rules: 0xf321 },
+            Command::SymbolRules { sym: 2, rules: Some(0) },
+            Command::SymbolRules { sym: 2, rules: None },
+            Command::VolBand { sym: 1, bps: Some(100) },
+            Command::VolBand { sym: 1, bps: None }];
+        let mut buf = Vec::<u8>::new();
+        for cmd in cmds.iter() {
+            buf.clear();
+            encode(cmd, &mut buf);
+            assert_eq!(decode(&buf), Some((*cmd, buf.len())));
+            assert!(decode(&buf[..buf.len()-1]).is_none());
+        }
+        // rotate every 20 bytes
+        let mut jnl = Journal::with_seg_size(path, 20).unwrap();
+        for cmd in cmds.iter() {
+            jnl.append(cmd).unwrap();
+        }
+        let seg = jnl.segment();
+        assert!(seg > 3);
+        drop(jnl);
+        assert_eq!(Journal::load(path).unwrap(), cmds);
+        // existing journal kept, open continues after last segment
+        assert_eq!(Journal::create(path).err().map(|e| e.kind()),
+                   Some(io::ErrorKind::AlreadyExists));
+        let mut jnl = Journal::open(path).unwrap();
+        assert_eq!(jnl.segment(), seg);
+        jnl.set_sync(true);
+        jnl.append(&Command::Reopen { sym: 4 }).unwrap();
+        drop(jnl);
+        // torn record at tail truncated before append
+        let fname = seg_name(path, seg);
+        let mut f = OpenOptions::new().append(true).open(&fname).unwrap();
+        f.write_all(&[TAG_CANCEL, 1, 2]).unwrap();
+        drop(f);
+        let mut jnl = Journal::open(path).unwrap();
+        jnl.append(&Command::Cancel { oid: 9 }).unwrap();
+        drop(jnl);
+        let mut cmds = cmds;
+        cmds.extend([Command::Reopen { sym: 4 }, Command::Cancel { oid: 9 }]);
+        assert_eq!(Journal::load(path).unwrap(), cmds);
+        // last segment compressed, open starts next one
+        let mut jnl = Journal::open(path).unwrap();
+        jnl.rotate().unwrap();
+        drop(jnl);
+        fs::remove_file(seg_name(path, seg + 1)).unwrap();
+        let mut jnl = Journal::open(path).unwrap();
+        assert_eq!(jnl.segment(), seg + 1);
+        // crash while compressing, partial .zst.tmp ignored
+        jnl.append(&Command::Reopen { sym: 5 }).unwrap();
+        let fname = seg_name(path, seg + 1);
+        fs::write(fname + ".zst.tmp", [0x28, 0xb5]).unwrap();
+        cmds.push(Command::Reopen { sym: 5 });
+        assert_eq!(Journal::load(path).unwrap(), cmds);
+        jnl.rotate().unwrap();
+        let fname = seg_name(path, seg + 1);
+        assert!(fs::metadata(fname.clone() + ".zst.tmp").is_err());
+        assert!(fs::metadata(fname).is_err());
+        jnl.sync().unwrap();
+        drop(jnl);
+        assert_eq!(Journal::load(path).unwrap(), cmds);
+        assert_eq!(Journal::remove(path).unwrap(), seg + 3);
+        assert!(Journal::load(path).unwrap().is_empty());
+        let jnl = Journal::create(path).unwrap();
+        assert_eq!(jnl.segment(), 0);
+        drop(jnl);
+        assert_eq!(Journal::remove(path).unwrap(), 1);
+    }
+}
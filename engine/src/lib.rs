@@ -1,6 +1,23 @@
 mod state;
 mod engine;
 mod order_book;
+mod command;
+mod shard;
+mod journal;
+mod snapshot;
+mod auction;
+mod report;
+mod market_data;
 
 pub use crate::state::State;
-pub use crate::engine::MatchEngine;
+pub use match_base::EngineError;
+pub use crate::engine::{MatchEngine, TradePrice, AuctionResult, IndicativeFn};
+pub use crate::order_book::Indicative;
+pub use crate::auction::{PriceRules, TieBreak};
+pub use crate::command::{Command, Reply};
+pub use crate::journal::Journal;
+pub use crate::shard::{ShardRouter, ShardReply, ShardDeal};
+pub use crate::report::{ExecReport, ExecType, ReportSink};
+pub use crate::market_data::{MarketData, MdSink, LevelUpdate, LevelAction,
+                              DepthSnapshot, Level1, Trade, OrderUpdate,
+                              OrderAction, BookSnapshot};
@@ -0,0 +1,390 @@
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+use std::sync::mpsc::{Sender, SyncSender};
+use match_base::{Order, Deal};
+use crate::snapshot::{SnapWriter, SnapReader};
+
+// change of L2 price level
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum LevelAction {
+    New,
+    Change,
+    Delete,
+}
+
+// incremental L2 update, qty is aggregated remain of level, 0 for delete
+// seq per symbol, increased by 1 for every update
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct LevelUpdate {
+    seq:    u64,
+    sym:    u32,
+    buy:    bool,
+    action: LevelAction,
+    price:  i32,
+    qty:    u32,
+}
+
+// full L2 depth, best price first, seq of last update included
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct DepthSnapshot {
+    seq:    u64,
+    sym:    u32,
+    bids:   Vec<(i32, u32)>,
+    asks:   Vec<(i32, u32)>,
+}
+
+// top of book and trade statistics of the day, prices 0 before trade
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub struct Level1 {
+    sym:    u32,
+    bid:    Option<(i32, u32)>,     // best price and aggregated qty
+    ask:    Option<(i32, u32)>,
+    last:   i32,
+    last_qty: u32,
+    volume: u64,
+    turnover: i64,      // sum of price * qty * turnover_mul
+    open:   i32,
+    high:   i32,
+    low:    i32,
+}
+
+// trade of match, side of aggressor. call auction trade as buy side
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct Trade {
+    deal_no: u64,
+    sym:    u32,
+    buy:    bool,
+    price:  i32,
+    qty:    u32,
+}
+
+// L3 order event
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum OrderAction {
+    Add,
+    Modify,     // qty reduced in place, priority kept
+    Execute,    // resting order filled, removed once qty 0
+    Delete,
+}
+
+// order by order update, qty is remain after action, position is count
+// of orders ahead at same price, seq per symbol
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct OrderUpdate {
+    seq:    u64,
+    sym:    u32,
+    oid:    u64,
+    buy:    bool,
+    action: OrderAction,
+    price:  i32,
+    qty:    u32,
+    exec_qty: u32,
+    position: u32,
+}
+
+// orders of book in priority order as (oid, price, remain qty), seq of
+// last order update included
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct BookSnapshot {
+    seq:    u64,
+    sym:    u32,
+    bids:   Vec<(u64, i32, u32)>,
+    asks:   Vec<(u64, i32, u32)>,
+}
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum MarketData {
+    Level(LevelUpdate),
+    Snapshot(DepthSnapshot),
+    Level1(Level1),
+    Trade(Trade),
+    Order(OrderUpdate),
+    Book(BookSnapshot),
+}
+
+// receiver of market data, called in engine thread
+pub trait MdSink: Send {
+    fn publish(&mut self, md: &MarketData);
+}
+
+// remain qty aggregated per price of orderBook, levels touched since
+// last drain kept w/ qty before
+pub struct Depth {
+    bids:   BTreeMap<i32, u32>,
+    asks:   BTreeMap<i32, u32>,
+    seq:    u64,
+    touched: BTreeMap<(bool, i32), u32>,
+}
+
+impl LevelUpdate {
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+    pub fn symbol(&self) -> u32 {
+        self.sym
+    }
+    pub fn is_buy(&self) -> bool {
+        self.buy
+    }
+    pub fn action(&self) -> LevelAction {
+        self.action
+    }
+    pub fn price(&self) -> i32 {
+        self.price
+    }
+    pub fn qty(&self) -> u32 {
+        self.qty
+    }
+}
+
+impl DepthSnapshot {
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+    pub fn symbol(&self) -> u32 {
+        self.sym
+    }
+    pub fn bids(&self) -> &[(i32, u32)] {
+        &self.bids
+    }
+    pub fn asks(&self) -> &[(i32, u32)] {
+        &self.asks
+    }
+}
+
+impl Trade {
+    pub fn new(deal: &Deal) -> Trade {
+        Trade { deal_no: deal.no(), sym: deal.symbol(), buy: deal.is_buy(),
+                price: deal.price(), qty: deal.qty() }
+    }
+    pub fn deal_no(&self) -> u64 {
+        self.deal_no
+    }
+    pub fn symbol(&self) -> u32 {
+        self.sym
+    }
+    pub fn is_buy(&self) -> bool {
+        self.buy
+    }
+    pub fn price(&self) -> i32 {
+        self.price
+    }
+    pub fn qty(&self) -> u32 {
+        self.qty
+    }
+}
+
+impl OrderUpdate {
+    // seq and position assigned once published
+    pub fn new(ord: &Order, action: OrderAction, qty: u32, exec_qty: u32)
+    -> OrderUpdate {
+        OrderUpdate { seq: 0, sym: ord.symbol(), oid: ord.oid(),
+                      buy: ord.is_buy(), action, price: ord.price(), qty,
+                      exec_qty, position: 0 }
+    }
+    pub fn with_seq(self, seq: u64, position: u32) -> OrderUpdate {
+        OrderUpdate { seq, position, ..self }
+    }
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+    pub fn symbol(&self) -> u32 {
+        self.sym
+    }
+    pub fn oid(&self) -> u64 {
+        self.oid
+    }
+    pub fn is_buy(&self) -> bool {
+        self.buy
+    }
+    pub fn action(&self) -> OrderAction {
+        self.action
+    }
+    pub fn price(&self) -> i32 {
+        self.price
+    }
+    pub fn qty(&self) -> u32 {
+        self.qty
+    }
+    pub fn exec_qty(&self) -> u32 {
+        self.exec_qty
+    }
+    pub fn position(&self) -> u32 {
+        self.position
+    }
+}
+
+impl BookSnapshot {
+    pub fn new(seq: u64, sym: u32, bids: Vec<(u64, i32, u32)>,
+               asks: Vec<(u64, i32, u32)>) -> BookSnapshot {
+        BookSnapshot { seq, sym, bids, asks }
+    }
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+    pub fn symbol(&self) -> u32 {
+        self.sym
+    }
+    pub fn bids(&self) -> &[(u64, i32, u32)] {
+        &self.bids
+    }
+    pub fn asks(&self) -> &[(u64, i32, u32)] {
+        &self.asks
+    }
+}
+
+impl Level1 {
+    pub fn new(sym: u32) -> Level1 {
+        Level1 { sym, ..Default::default() }
+    }
+    pub fn trade(&mut self, price: i32, qty: u32, turnover_mul: u32) {
+        if self.volume == 0 {
+            (self.open, self.high, self.low) = (price, price, price);
+        } else {
+            self.high = self.high.max(price);
+            self.low = self.low.min(price);
+        }
+        self.last = price;
+        self.last_qty = qty;
+        self.volume += qty as u64;
+        self.turnover += price as i64 * qty as i64 * turnover_mul as i64;
+    }
+    // true if best bid/ask changed
+    pub fn set_quote(&mut self, bid: Option<(i32, u32)>,
+                     ask: Option<(i32, u32)>) -> bool {
+        if self.bid == bid && self.ask == ask { return false }
+        self.bid = bid;
+        self.ask = ask;
+        true
+    }
+    pub fn symbol(&self) -> u32 {
+        self.sym
+    }
+    pub fn bid(&self) -> Option<(i32, u32)> {
+        self.bid
+    }
+    pub fn ask(&self) -> Option<(i32, u32)> {
+        self.ask
+    }
+    pub fn last(&self) -> i32 {
+        self.last
+    }
+    pub fn last_qty(&self) -> u32 {
+        self.last_qty
+    }
+    pub fn volume(&self) -> u64 {
+        self.volume
+    }
+    pub fn turnover(&self) -> i64 {
+        self.turnover
+    }
+    pub fn open(&self) -> i32 {
+        self.open
+    }
+    pub fn high(&self) -> i32 {
+        self.high
+    }
+    pub fn low(&self) -> i32 {
+        self.low
+    }
+    // trade statistics of snapshot, quote rebuilt from orderBook
+    pub fn put_stats<W: Write>(&self, w: &mut SnapWriter<W>)
+    -> io::Result<()> {
+        w.put_i32(self.last)?;
+        w.put_u32(self.last_qty)?;
+        w.put_u64(self.volume)?;
+        w.put_i64(self.turnover)?;
+        w.put_i32(self.open)?;
+        w.put_i32(self.high)?;
+        w.put_i32(self.low)
+    }
+    pub fn get_stats<R: Read>(sym: u32, r: &mut SnapReader<R>)
+    -> io::Result<Level1> {
+        Ok(Level1 { sym, bid: None, ask: None, last: r.get_i32()?,
+                    last_qty: r.get_u32()?, volume: r.get_u64()?,
+                    turnover: r.get_i64()?, open: r.get_i32()?,
+                    high: r.get_i32()?, low: r.get_i32()? })
+    }
+}
+
+impl Depth {
+    pub fn new() -> Depth {
+        Depth { bids: BTreeMap::<i32, u32>::new(),
+                asks: BTreeMap::<i32, u32>::new(), seq: 0,
+                touched: BTreeMap::<(bool, i32), u32>::new() }
+    }
+    fn touch(&mut self, buy: bool, price: i32) {
+        let lv = if buy { &self.bids } else { &self.asks };
+        let qty = lv.get(&price).copied().unwrap_or(0);
+        self.touched.entry((buy, price)).or_insert(qty);
+    }
+    pub fn add(&mut self, buy: bool, price: i32, qty: u32) {
+        if qty == 0 { return }
+        self.touch(buy, price);
+        let lv = if buy { &mut self.bids } else { &mut self.asks };
+        *lv.entry(price).or_insert(0) += qty;
+    }
+    pub fn sub(&mut self, buy: bool, price: i32, qty: u32) {
+        if qty == 0 { return }
+        self.touch(buy, price);
+        let lv = if buy { &mut self.bids } else { &mut self.asks };
+        if let Some(vol) = lv.get_mut(&price) {
+            if *vol > qty {
+                *vol -= qty;
+            } else {
+                lv.remove(&price);
+            }
+        }
+    }
+    // all levels deleted
+    pub fn clear(&mut self) {
+        let bids: Vec<i32> = self.bids.keys().copied().collect();
+        let asks: Vec<i32> = self.asks.keys().copied().collect();
+        for prc in bids { self.touch(true, prc); }
+        for prc in asks { self.touch(false, prc); }
+        self.bids.clear();
+        self.asks.clear();
+    }
+    // forget levels touched, seq unchanged
+    pub fn discard(&mut self) {
+        self.touched.clear();
+    }
+    // sequenced updates of levels changed since last drain
+    pub fn drain(&mut self, sym: u32) -> Vec<LevelUpdate> {
+        let mut ups = Vec::<LevelUpdate>::with_capacity(self.touched.len());
+        let touched = std::mem::take(&mut self.touched);
+        for ((buy, price), old) in touched {
+            let lv = if buy { &self.bids } else { &self.asks };
+            let qty = lv.get(&price).copied().unwrap_or(0);
+            let action = match (old, qty) {
+                (o, q) if o == q => continue,
+                (0, _) => LevelAction::New,
+                (_, 0) => LevelAction::Delete,
+                _ => LevelAction::Change,
+            };
+            self.seq += 1;
+            ups.push(LevelUpdate { seq: self.seq, sym, buy, action, price,
+                                   qty });
+        }
+        ups
+    }
+    pub fn snapshot(&self, sym: u32) -> DepthSnapshot {
+        DepthSnapshot { seq: self.seq, sym,
+            bids: self.bids.iter().rev().map(|(p, v)| (*p, *v)).collect(),
+            asks: self.asks.iter().map(|(p, v)| (*p, *v)).collect() }
+    }
+}
+
+// market data dropped once receiver gone
+impl MdSink for Sender<MarketData> {
+    fn publish(&mut self, md: &MarketData) {
+        let _ = self.send(md.clone());
+    }
+}
+
+// bounded queue, engine blocks while queue full
+impl MdSink for SyncSender<MarketData> {
+    fn publish(&mut self, md: &MarketData) {
+        let _ = self.send(md.clone());
+    }
+}
@@ -1,7 +1,11 @@
 use std::collections::{btree_map, BTreeMap};
 use std::fmt;
-use match_base::{OidPrice, OrderKey, Order};
+use match_base::{OidPrice, OrderKey, Order, OrderPool, TimeInForce};
 use log::{error, info, warn};
+use crate::auction::{PriceRules, determine};
+use crate::state::State;
+use crate::market_data::{Depth, DepthSnapshot, LevelUpdate, Level1};
+use crate::market_data::BookSnapshot;
 
 type OrderBookMap = BTreeMap<OidPrice, OrderKey>;
 type OrderBookIter<'a> = btree_map::Iter<'a, OidPrice, OrderKey>;
@@ -11,10 +15,37 @@ pub struct OrderBook {
     sym_name:   String,
     bids:       OrderBookMap,
     asks:       OrderBookMap,
+    levels:     Option<PriceLevels>,
+    state:      Option<State>,  // symbol state, None follow market
+    limits:     (i32, i32),     // price limits, auction price inside
+    tick:       i32,            // price step, auction price on tick
+    depth:      Option<Depth>,  // L2 levels, kept while md published
+    l1:         Level1,
+    l1_changed: bool,
+    l3_seq:     u64,
+}
+
+// remain qty aggregated per price, kept while preAuction for indicative
+struct PriceLevels {
+    bids:   BTreeMap<i32, u32>,
+    asks:   BTreeMap<i32, u32>,
+    pclose: i32,
+    rules:  PriceRules,
+    last:   Option<Indicative>,
+}
+
+// indicative uncross, imbalance is unmatched qty at equilibrium price
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Indicative {
+    price:  i32,
+    qty:    u32,
+    imbalance:  u32,
+    buy:    bool,   // imbalance side
 }
 
 pub struct OrderPriceQty<'a> {
     it:     OrderBookIter<'a>,
+    pool:   &'a OrderPool,
     last_oid:   u64,
 }
 
@@ -31,12 +62,26 @@ impl OrderBook {
         OrderBook{sym_idx, sym_name: String::from(sym_name),
             bids: BTreeMap::<OidPrice, OrderKey>::new(),
             asks: BTreeMap::<OidPrice, OrderKey>::new(),
+            levels: None,
+            state: None,
+            limits: (i32::MIN, i32::MAX),
+            tick: 1,
+            depth: None,
+            l1: Level1::new(sym_idx),
+            l1_changed: false,
+            l3_seq: 0,
         }
     }
     pub fn clear(&mut self) {
         info!("clear symbol({}) orderBook", self.sym_name);
         self.bids.clear();
         self.asks.clear();
+        self.levels = None;
+        if let Some(dp) = self.depth.as_mut() {
+            dp.clear();
+        }
+        self.l1 = Level1::new(self.sym_idx);
+        self.l1_changed = true;
     }
     pub fn insert(&mut self, buy: bool, ord: &Order) {
         if buy {
@@ -44,23 +89,217 @@ impl OrderBook {
         } else {
             self.asks.insert(ord.to_OidPrice(), ord.key());
         }
+        if let Some(lv) = self.levels.as_mut() {
+            lv.add(buy, ord.price(), ord.remain_qty());
+        }
+        if let Some(dp) = self.depth.as_mut() {
+            dp.add(buy, ord.price(), ord.remain_qty());
+        }
+    }
+    // remove order key from orderBook, for cancel
+    pub fn remove(&mut self, buy: bool, ord: &Order) -> bool {
+        let ret = if buy {
+            self.bids.remove(&ord.to_OidPrice()).is_some()
+        } else {
+            self.asks.remove(&ord.to_OidPrice()).is_some()
+        };
+        if ret {
+            if let Some(lv) = self.levels.as_mut() {
+                lv.sub(buy, ord.price(), ord.remain_qty());
+            }
+            if let Some(dp) = self.depth.as_mut() {
+                dp.sub(buy, ord.price(), ord.remain_qty());
+            }
+        }
+        ret
+    }
+    // order qty reduced in place, keep price levels
+    pub fn reduce(&mut self, buy: bool, price: i32, qty: u32) {
+        if let Some(lv) = self.levels.as_mut() {
+            lv.sub(buy, price, qty);
+        }
+        if let Some(dp) = self.depth.as_mut() {
+            dp.sub(buy, price, qty);
+        }
+    }
+    // resting order filled by try_match, book already updated in place
+    pub fn fill_depth(&mut self, buy: bool, price: i32, qty: u32) {
+        if let Some(dp) = self.depth.as_mut() {
+            dp.sub(buy, price, qty);
+        }
+    }
+    // start keep L2 levels of orderBook
+    pub fn enable_depth(&mut self, pool: &OrderPool) {
+        let mut dp = Depth::new();
+        for buy in [true, false] {
+            for okey in self.book(buy).values() {
+                if let Some(ord) = pool.get(okey) {
+                    dp.add(buy, ord.price(), ord.remain_qty());
+                }
+            }
+        }
+        // levels of snapshot, not updates
+        dp.discard();
+        self.depth = Some(dp);
+    }
+    pub fn disable_depth(&mut self) {
+        self.depth = None;
+    }
+    // L2 updates since last call
+    pub fn depth_updates(&mut self) -> Vec<LevelUpdate> {
+        match self.depth.as_mut() {
+            Some(dp) => dp.drain(self.sym_idx),
+            None => Vec::new(),
+        }
+    }
+    pub fn depth_snapshot(&self) -> Option<DepthSnapshot> {
+        self.depth.as_ref().map(|dp| dp.snapshot(self.sym_idx))
+    }
+    pub fn level1(&self) -> &Level1 {
+        &self.l1
+    }
+    pub fn set_level1(&mut self, l1: Level1) {
+        self.l1 = l1;
+    }
+    pub fn trade(&mut self, price: i32, qty: u32, turnover_mul: u32) {
+        self.l1.trade(price, qty, turnover_mul);
+        self.l1_changed = true;
+    }
+    // best bid/ask from orderBook
+    pub fn update_quote(&mut self, pool: &OrderPool) {
+        let bid = self.pv_iter(true, pool).next();
+        let ask = self.pv_iter(false, pool).next();
+        if self.l1.set_quote(bid, ask) {
+            self.l1_changed = true;
+        }
+    }
+    // L1 if changed since last call
+    pub fn level1_update(&mut self) -> Option<Level1> {
+        if !self.l1_changed { return None }
+        self.l1_changed = false;
+        Some(self.l1)
+    }
+    // orders ahead of oid at price
+    pub fn queue_position(&self, buy: bool, price: i32, oid: u64) -> u32 {
+        let (lo, hi) = (OidPrice::new(buy, price, 0),
+                        OidPrice::new(buy, price, oid as u32));
+        self.book(buy).range(lo..hi).count() as u32
+    }
+    pub fn next_l3_seq(&mut self) -> u64 {
+        self.l3_seq += 1;
+        self.l3_seq
+    }
+    // orders in priority order, for L3 subscriber bootstrap
+    pub fn book_snapshot(&self, pool: &OrderPool) -> BookSnapshot {
+        let side = |buy: bool| -> Vec<(u64, i32, u32)> {
+            self.book(buy).values().filter_map(|okey| pool.get(okey))
+                .map(|ord| (ord.oid(), ord.price(), ord.remain_qty()))
+                .collect()
+        };
+        BookSnapshot::new(self.l3_seq, self.sym_idx, side(true), side(false))
+    }
+    // start keep price levels, for preAuction only since fills not tracked
+    pub fn enable_indicative(&mut self, pool: &OrderPool, pclose: i32,
+                             rules: PriceRules) {
+        let mut lv = PriceLevels { bids: BTreeMap::<i32, u32>::new(),
+                        asks: BTreeMap::<i32, u32>::new(), pclose, rules,
+                        last: None };
+        for buy in [true, false] {
+            for okey in self.book(buy).values() {
+                if let Some(ord) = pool.get(okey) {
+                    lv.add(buy, ord.price(), ord.remain_qty());
+                }
+            }
+        }
+        lv.last = lv.calc(self.limits, self.tick);
+        self.levels = Some(lv);
+    }
+    pub fn disable_indicative(&mut self) {
+        self.levels = None;
+    }
+    pub fn limits(&self) -> (i32, i32) {
+        self.limits
+    }
+    pub fn set_limits(&mut self, limits: (i32, i32)) {
+        self.limits = limits;
+    }
+    pub fn tick(&self) -> i32 {
+        self.tick
+    }
+    pub fn set_tick(&mut self, tick: i32) {
+        self.tick = tick.max(1);
+    }
+    pub fn set_ref_price(&mut self, pclose: i32) {
+        if let Some(lv) = self.levels.as_mut() {
+            lv.pclose = pclose;
+        }
+    }
+    pub fn set_price_rules(&mut self, rules: &PriceRules) {
+        if let Some(lv) = self.levels.as_mut() {
+            lv.rules = rules.clone();
+        }
+    }
+    pub fn indicative(&self) -> Option<Indicative> {
+        self.levels.as_ref().and_then(|lv| lv.last)
+    }
+    // recalc indicative, true if changed
+    pub fn update_indicative(&mut self) -> bool {
+        if let Some(lv) = self.levels.as_mut() {
+            let ind = lv.calc(self.limits, self.tick);
+            if ind != lv.last {
+                lv.last = ind;
+                return true
+            }
+        }
+        false
+    }
+    // cancel and remove Day orders, return keys of expired orders
+    pub fn expire_day(&mut self, pool: &mut OrderPool) -> Vec<OrderKey> {
+        let mut expired = Vec::<OrderKey>::new();
+        let depth = &mut self.depth;
+        let mut expire = |_: &OidPrice, okey: &mut OrderKey| {
+            if let Some(ord) = pool.get_mut(okey) {
+                if ord.tif() != TimeInForce::Day { return true }
+                if let Some(dp) = depth.as_mut() {
+                    dp.sub(ord.is_buy(), ord.price(), ord.remain_qty());
+                }
+                ord.cancel();
+                expired.push(*okey);
+            }
+            false
+        };
+        self.bids.retain(&mut expire);
+        self.asks.retain(&mut expire);
+        expired
     }
     pub fn symbol(&self) -> &str {
         &self.sym_name
     }
-    pub fn pv_iter(&self, buy: bool) -> OrderPriceQty {
+    pub fn sym_idx(&self) -> u32 {
+        self.sym_idx
+    }
+    pub fn state(&self) -> Option<State> {
+        self.state
+    }
+    pub fn set_state(&mut self, state: Option<State>) {
+        self.state = state;
+    }
+    pub fn is_indicative(&self) -> bool {
+        self.levels.is_some()
+    }
+    pub fn pv_iter<'a>(&'a self, buy: bool, pool: &'a OrderPool)
+    -> OrderPriceQty<'a> {
         if buy {
-            OrderPriceQty { it: self.bids.iter(), last_oid: 0}
+            OrderPriceQty { it: self.bids.iter(), pool, last_oid: 0}
         } else {
-            OrderPriceQty { it: self.asks.iter(), last_oid: 0}
+            OrderPriceQty { it: self.asks.iter(), pool, last_oid: 0}
         }
     }
     pub fn len(&self) -> (usize, usize) {
         (self.bids.len(), self.asks.len())
     }
     #[cfg(not(feature = "btree_maple"))]
-    pub fn retain(&mut self, buy: bool, okey: OrderKey) {
-        let ord = okey.get().unwrap();
+    pub fn retain(&mut self, buy: bool, ord: &Order) {
         let key = ord.to_OidPrice();
         if buy {
             self.bids = self.bids.split_off(&key);
@@ -88,18 +327,18 @@ impl OrderBook {
             &mut self.asks
         }
     }
-    pub fn validate(&self) -> bool {
+    pub fn validate(&self, pool: &OrderPool) -> bool {
         // validate bids
         #[cfg(test)]
         info!("validate bid orderBook for {}", self.sym_name);
         if self.bids.len() > 1 {
             let mut it = self.bids.iter();
             let (_, orkey) = it.next().unwrap();
-            let ord = orkey.get().unwrap();
+            let ord = pool.get(orkey).unwrap();
             let mut last = ord.price();
             let mut oid = ord.oid();
             while let Some((_, orkey)) = it.next() {
-                if let Some(ord) = orkey.get() {
+                if let Some(ord) = pool.get(orkey) {
                     if ord.is_canceled() { continue }
                     if ord.is_filled() {
                         error!("{} order oid({}) is filled, MUST removed",
@@ -134,11 +373,11 @@ impl OrderBook {
         if self.asks.len() < 2 { return true }
         let mut it = self.asks.iter();
         let (_, orkey) = it.next().unwrap();
-        let ord = orkey.get().unwrap();
+        let ord = pool.get(orkey).unwrap();
         let mut last = ord.price();
         let mut oid = ord.oid();
         while let Some((_, orkey)) = it.next() {
-            if let Some(ord) = orkey.get() {
+            if let Some(ord) = pool.get(orkey) {
                 if ord.is_canceled() {
                     continue
                 }
@@ -183,13 +422,164 @@ impl fmt::Display for OrderBook {
     }
 }
 
+impl Indicative {
+    pub const fn new(price: i32, qty: u32, imbalance: u32, buy: bool)
+    -> Indicative {
+        Indicative { price, qty, imbalance, buy: buy && imbalance > 0 }
+    }
+    pub fn price(&self) -> i32 {
+        self.price
+    }
+    pub fn qty(&self) -> u32 {
+        self.qty
+    }
+    pub fn imbalance(&self) -> u32 {
+        self.imbalance
+    }
+    // Some(true) for buy surplus, None if balanced
+    pub fn imbalance_side(&self) -> Option<bool> {
+        if self.imbalance == 0 { None } else { Some(self.buy) }
+    }
+}
+
+impl PriceLevels {
+    fn add(&mut self, buy: bool, price: i32, qty: u32) {
+        if qty == 0 { return }
+        let lv = if buy { &mut self.bids } else { &mut self.asks };
+        *lv.entry(price).or_insert(0) += qty;
+    }
+    fn sub(&mut self, buy: bool, price: i32, qty: u32) {
+        let lv = if buy { &mut self.bids } else { &mut self.asks };
+        if let Some(vol) = lv.get_mut(&price) {
+            if *vol > qty {
+                *vol -= qty;
+            } else {
+                lv.remove(&price);
+            }
+        }
+    }
+    // walk crossed levels only
+    // bid above limit up buy at limit up, bid below limit down out of
+    // cross. asks alike
+    fn calc(&self, (lo, hi): (i32, i32), tick: i32) -> Option<Indicative> {
+        let mut bit = self.bids.iter().rev().take_while(|(p, _)| **p >= lo)
+                        .map(|(p, v)| ((*p).min(hi), *v));
+        let mut ait = self.asks.iter().take_while(|(p, _)| **p <= hi)
+                        .map(|(p, v)| ((*p).max(lo), *v));
+        let res = match &self.rules {
+            PriceRules::Classic => {
+                equilibrium(|| bit.next(), || ait.next(), self.pclose)
+            },
+            PriceRules::Chain(rules) => {
+                determine(|| bit.next(), || ait.next(), self.pclose, tick,
+                          rules)
+            },
+        };
+        res.map(|(last, qty, remain, buy)| Indicative::new(last, qty, remain,
+                                                            buy))
+    }
+}
+
+// equilibrium price of (price, qty) levels, best price first
+// return Option<(last, max_qty, remain_qty, remain is buy)>
+pub fn equilibrium<B, A>(mut bnext: B, mut anext: A, pclose: i32)
+-> Option<(i32, u32, u32, bool)>
+where B: FnMut() -> Option<(i32, u32)>, A: FnMut() -> Option<(i32, u32)>
+{
+    let bp = bnext();
+    let ap = anext();
+    if bp.is_none() || ap.is_none() { return None }
+    let (mut bp, mut bvol) = bp.unwrap();
+    let (mut ap, mut avol) = ap.unwrap();
+    if bp < ap { return None }
+    let (best_bid, best_ask) = (bp, ap);
+    let mut max_qty: u32 = 0;
+    let mut remain_qty: u32 = 0;
+    let mut remain_buy = false;
+    let mut last: i32 = pclose;
+    let mut b_end = false;
+    let mut a_end = false;
+    #[cfg(test)]
+    info!("MatchCross BBS: {}/{}", bp, ap);
+    while !b_end && !a_end && bp >= ap
+    {
+        if bvol > avol {
+            max_qty += avol;
+            bvol -= avol;
+            remain_qty = bvol;
+            remain_buy = true;
+            last = ap;
+            if let Some((p,v)) = anext() {
+                ap = p;
+                avol = v;
+            } else {
+                break
+            }
+        } else if bvol < avol {
+            max_qty += bvol;
+            avol -= bvol;
+            remain_qty = avol;
+            remain_buy = false;
+            last = bp;
+            if let Some((p,v)) = bnext() {
+                bp = p;
+                bvol = v;
+            } else {
+                break
+            }
+        } else {
+            max_qty += bvol;
+            remain_qty = 0;
+            last = bp;
+            if bp == ap {
+                break
+            }
+            let oap = ap;
+            let obp = bp;
+            if let Some((p,v)) = bnext() {
+                bp = p;
+                bvol = v;
+                b_end = bp < best_ask;
+            } else {
+                b_end = true;
+            }
+            if let Some((p,v)) = anext() {
+                ap = p;
+                avol = v;
+                a_end = ap > best_bid;
+            } else {
+                a_end = true;
+            }
+            if b_end && a_end {
+                if oap > pclose {
+                    last = oap;
+                } else if obp < pclose {
+                    last = obp;
+                } else {
+                    last = pclose;
+                }
+                break
+            }
+            if b_end { last = oap }
+            if a_end { last = obp }
+        }
+        #[cfg(test)]
+        info!("update MatchCross price: {} {}/{} volume: {}(left: {})",
+                last, bp, ap, max_qty, remain_qty);
+    }
+    #[cfg(test)]
+    info!("MatchCross end, bp/ap: {}/{} volume: {}(left: {})",
+            bp, ap, max_qty, remain_qty);
+    Some((last, max_qty, remain_qty, remain_buy))
+}
+
 impl OrderPriceQty<'_> {
     pub fn next(&mut self) -> Option<(i32, u32)> {
         let prc: i32;
         let mut qty: u32;
         if self.last_oid == 0 {
             if let Some((_, orkey)) = self.it.next() {
-                let ord = orkey.get().unwrap();
+                let ord = self.pool.get(orkey).unwrap();
                 prc = ord.price();
                 qty = ord.remain_qty();
             } else {
@@ -197,12 +587,12 @@ impl OrderPriceQty<'_> {
             }
         } else {
             let orkey = OrderKey::from(self.last_oid);
-            let ord = orkey.get().unwrap();
+            let ord = self.pool.get(&orkey).unwrap();
             prc = ord.price();
             qty = ord.remain_qty();
         }
         while let Some((_, orkey)) = self.it.next() {
-            let ord = orkey.get().unwrap();
+            let ord = self.pool.get(orkey).unwrap();
             if prc == ord.price() {
                 qty += ord.remain_qty();
                 continue
@@ -231,7 +621,7 @@ mod tests {
         }
         log::set_max_level(LevelFilter::Info);
         info!("build orderBook");
-        let pool = OrderPool::new();
+        let mut pool = OrderPool::new();
         let mut orb = OrderBook::new(1, "cu1906");
         let mut rng = rand::thread_rng();
         let mut measure = Measure::start("orderbook bench");
@@ -252,7 +642,7 @@ mod tests {
         println!("build orderBook cost {} us, bids: {}, asks: {}",
                  measure.as_us(), orb.bids.len(), orb.asks.len());
         println!("orderBook insert cost {} ns per Op", ns_ops);
-        assert!(orb.validate(), "orderBook disorder");
+        assert!(orb.validate(&pool), "orderBook disorder");
     }
 
     #[test]
@@ -262,7 +652,7 @@ mod tests {
         }
         log::set_max_level(LevelFilter::Info);
         info!("build orderBook");
-        let pool = OrderPool::new();
+        let mut pool = OrderPool::new();
         let mut orb = OrderBook::new(1, "cu1906");
         let b_buy = true;
         let ord = pool.new_order(1, b_buy, 30000, 10).unwrap();
@@ -271,7 +661,7 @@ mod tests {
         orb.insert(b_buy, ord);
         let ord = pool.new_order(1, b_buy, 31000, 18).unwrap();
         orb.insert(b_buy, ord);
-        let mut pv_it = orb.pv_iter(b_buy);
+        let mut pv_it = orb.pv_iter(b_buy, &pool);
         let opv = pv_it.next();
         assert!(opv != None);
         let (prc, vol) = opv.unwrap();
@@ -293,7 +683,7 @@ mod tests {
         }
         log::set_max_level(LevelFilter::Info);
         info!("build orderBook");
-        let pool = OrderPool::new();
+        let mut pool = OrderPool::new();
         let mut orb = OrderBook::new(1, "cu1906");
         let mut rng = rand::thread_rng();
         let mut measure = Measure::start("orderbook bench");
@@ -304,7 +694,7 @@ mod tests {
             let b_buy: bool = (rng.gen::<u32>() & 1) != 0;
             qty %= 1000;
             qty += 1;
-            if let Some(ord) = pool.new_order(1, b_buy, price, qty) {
+            if let Ok(ord) = pool.new_order(1, b_buy, price, qty) {
                 orb.insert(b_buy, ord);
             } else {
                 error!("OrderPool new_order failed");
@@ -318,7 +708,7 @@ mod tests {
                  measure.as_us(), orb.bids.len(), orb.asks.len());
         println!("orderBook insert cost {} ns per Op", ns_ops);
         let mut measure = Measure::start("orderbook bench");
-        let valid = orb.validate();
+        let valid = orb.validate(&pool);
         measure.stop();
         let ns_ops = measure.as_ns() / (N as u64);
         assert!(ns_ops < 10_000);
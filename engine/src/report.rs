@@ -0,0 +1,123 @@
+use std::sync::mpsc::{Sender, SyncSender};
+use match_base::{Order, EngineError};
+
+// order lifecycle transition
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[repr(u8)]
+pub enum ExecType {
+    New=0,
+    Rejected,       // order not accepted, oid 0
+    PartialFill,
+    Filled,
+    Canceled,       // canceled by request, or immediate order remain
+    Expired,        // day order expired at market stop
+    Replaced,       // amended in place, priority kept
+}
+
+// execution report, last price/qty set for fills only
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct ExecReport {
+    oid:    u64,
+    sym:    u32,
+    exec_type: ExecType,
+    cum_qty: u32,
+    leaves_qty: u32,
+    last_price: i32,
+    last_qty: u32,
+    reason: Option<EngineError>,
+}
+
+// receiver of execution reports, called in engine thread
+pub trait ReportSink: Send {
+    fn report(&mut self, rep: &ExecReport);
+}
+
+impl ExecType {
+    pub const fn from_u8(v: u8) -> Option<ExecType> {
+        match v {
+            0 => Some(ExecType::New),
+            1 => Some(ExecType::Rejected),
+            2 => Some(ExecType::PartialFill),
+            3 => Some(ExecType::Filled),
+            4 => Some(ExecType::Canceled),
+            5 => Some(ExecType::Expired),
+            6 => Some(ExecType::Replaced),
+            _ => None,
+        }
+    }
+}
+
+impl ExecReport {
+    pub fn order(ord: &Order, exec_type: ExecType) -> ExecReport {
+        let leaves_qty = if ord.is_canceled() { 0 } else { ord.remain_qty() };
+        ExecReport { oid: ord.oid(), sym: ord.symbol(), exec_type,
+                     cum_qty: ord.filled(), leaves_qty, last_price: 0,
+                     last_qty: 0, reason: None }
+    }
+    pub fn fill(ord: &Order, cum_qty: u32, last_price: i32, last_qty: u32)
+    -> ExecReport {
+        let leaves_qty = ord.qty() - cum_qty;
+        let exec_type = if leaves_qty == 0 {
+            ExecType::Filled
+        } else {
+            ExecType::PartialFill
+        };
+        ExecReport { oid: ord.oid(), sym: ord.symbol(), exec_type, cum_qty,
+                     leaves_qty, last_price, last_qty, reason: None }
+    }
+    // order accepted, before any fill
+    pub fn accept(ord: &Order) -> ExecReport {
+        ExecReport { oid: ord.oid(), sym: ord.symbol(),
+                     exec_type: ExecType::New, cum_qty: 0,
+                     leaves_qty: ord.qty(), last_price: 0, last_qty: 0,
+                     reason: None }
+    }
+    pub fn reject(sym: u32, reason: EngineError) -> ExecReport {
+        ExecReport { oid: 0, sym, exec_type: ExecType::Rejected, cum_qty: 0,
+                     leaves_qty: 0, last_price: 0, last_qty: 0,
+                     reason: Some(reason) }
+    }
+    // cancel/amend request rejected, order kept as is
+    pub fn reject_request(oid: u64, sym: u32, reason: EngineError)
+    -> ExecReport {
+        ExecReport { oid, ..ExecReport::reject(sym, reason) }
+    }
+    pub fn oid(&self) -> u64 {
+        self.oid
+    }
+    pub fn symbol(&self) -> u32 {
+        self.sym
+    }
+    pub fn exec_type(&self) -> ExecType {
+        self.exec_type
+    }
+    pub fn cum_qty(&self) -> u32 {
+        self.cum_qty
+    }
+    pub fn leaves_qty(&self) -> u32 {
+        self.leaves_qty
+    }
+    pub fn last_price(&self) -> i32 {
+        self.last_price
+    }
+    pub fn last_qty(&self) -> u32 {
+        self.last_qty
+    }
+    pub fn reason(&self) -> Option<EngineError> {
+        self.reason
+    }
+}
+
+// reports dropped once receiver gone
+impl ReportSink for Sender<ExecReport> {
+    fn report(&mut self, rep: &ExecReport) {
+        let _ = self.send(*rep);
+    }
+}
+
+// bounded queue, engine blocks while queue full
+impl ReportSink for SyncSender<ExecReport> {
+    fn report(&mut self, rep: &ExecReport) {
+        let _ = self.send(*rep);
+    }
+}
@@ -0,0 +1,367 @@
+use std::sync::Arc;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+use log::{error, info};
+use match_base::{Deal, OrderType, TimeInForce, EngineError};
+use crate::{state::State, auction::PriceRules};
+use crate::engine::{MatchEngine, TradePrice};
+use crate::command::{Command, Reply};
+
+// per symbol sharded matching, each shard thread owns a MatchEngine
+// (books, orders and deal sequence), ShardRouter dispatches by sym_idx.
+// global oid = local oid * shards + shard, deals merged by
+// (command seq, shard, local deal no) so result is independent of threads
+pub struct ShardRouter {
+    shards: Vec<Shard>,
+    replies: Receiver<ShardReply>,
+    seq:    u64,
+    pending: usize,
+    deal_no: u64,
+}
+
+struct Shard {
+    tx:     Option<Sender<(u64, Command)>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+// reply from shard for command seq, with deals generated by the command
+pub struct ShardReply {
+    seq:    u64,
+    shard:  usize,
+    reply:  Reply,
+    deals:  Vec<Deal>,
+}
+
+// deal with global deal no and global oid/contra, deal() as of shard
+pub struct ShardDeal {
+    no:     u64,
+    shard:  usize,
+    oid:    u64,
+    contra: u64,
+    deal:   Deal,
+}
+
+impl ShardReply {
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+    pub fn shard(&self) -> usize {
+        self.shard
+    }
+    pub fn reply(&self) -> Reply {
+        self.reply
+    }
+}
+
+impl ShardDeal {
+    pub fn no(&self) -> u64 {
+        self.no
+    }
+    pub fn shard(&self) -> usize {
+        self.shard
+    }
+    pub fn oid(&self) -> u64 {
+        self.oid
+    }
+    pub fn contra(&self) -> u64 {
+        self.contra
+    }
+    pub fn deal(&self) -> &Deal {
+        &self.deal
+    }
+}
+
+fn run_shard(shard: usize, mut me: MatchEngine, rx: Receiver<(u64, Command)>,
+             tx: Sender<ShardReply>)
+{
+    while let Ok((seq, cmd)) = rx.recv() {
+        let last_no = me.deals().deal_no();
+        let reply = me.execute(&cmd);
+        let deals = (last_no+1..=me.deals().deal_no())
+            .filter_map(|no| me.deals().get(no).cloned()).collect();
+        if tx.send(ShardReply { seq, shard, reply, deals }).is_err() {
+            break
+        }
+    }
+    info!("shard {} exit", shard);
+}
+
+impl ShardRouter {
+    pub fn new(n_shards: usize) -> ShardRouter {
+        ShardRouter::with_engines(n_shards, |_| MatchEngine::new())
+    }
+    // engine of shard built on shard thread by factory(shard), e.g.
+    // symbols of instrument master and journal per shard
+    pub fn with_engines<F>(n_shards: usize, factory: F) -> ShardRouter
+    where F: Fn(usize) -> MatchEngine + Send + Sync + 'static
+    {
+        assert!(n_shards > 0);
+        let factory = Arc::new(factory);
+        let (rtx, replies) = channel();
+        let shards = (0..n_shards).map(|shard| {
+            let (tx, rx) = channel();
+            let rtx = rtx.clone();
+            let factory = factory.clone();
+            let handle = thread::spawn(move || {
+                run_shard(shard, factory(shard), rx, rtx)
+            });
+            Shard { tx: Some(tx), handle: Some(handle) }
+        }).collect();
+        ShardRouter { shards, replies, seq: 0, pending: 0, deal_no: 0 }
+    }
+    pub fn shards(&self) -> usize {
+        self.shards.len()
+    }
+    pub fn shard_of(&self, sym_idx: u32) -> usize {
+        sym_idx as usize % self.shards.len()
+    }
+    pub fn global_oid(&self, shard: usize, oid: u64) -> u64 {
+        oid * self.shards.len() as u64 + shard as u64
+    }
+    // (shard, local oid) of global oid
+    pub fn local_oid(&self, oid: u64) -> (usize, u64) {
+        let n = self.shards.len() as u64;
+        ((oid % n) as usize, oid / n)
+    }
+    fn dispatch(&mut self, shard: usize, cmd: Command) -> u64 {
+        self.seq += 1;
+        if let Some(tx) = self.shards[shard].tx.as_ref() {
+            if tx.send((self.seq, cmd)).is_ok() {
+                self.pending += 1;
+            } else {
+                error!("shard {} gone, drop command {}", shard, self.seq);
+            }
+        }
+        self.seq
+    }
+    // returns command seq, reply via flush
+    pub fn send_order(&mut self, sym_idx: u32, buy: bool, price: i32, qty: u32)
+    -> u64 {
+        self.send_order_type(sym_idx, buy, price, qty, OrderType::Limit,
+                             TimeInForce::Day)
+    }
+    pub fn send_order_type(&mut self, sym: u32, buy: bool, price: i32,
+                           qty: u32, ord_type: OrderType, tif: TimeInForce)
+    -> u64 {
+        let shard = self.shard_of(sym);
+        self.dispatch(shard, Command::NewOrder { sym, buy, price, qty,
+                                                 ord_type, tif })
+    }
+    pub fn cancel_order(&mut self, oid: u64) -> u64 {
+        let (shard, oid) = self.local_oid(oid);
+        self.dispatch(shard, Command::Cancel { oid })
+    }
+    pub fn amend_order(&mut self, oid: u64, price: i32, qty: u32) -> u64 {
+        let (shard, oid) = self.local_oid(oid);
+        self.dispatch(shard, Command::Amend { oid, price, qty })
+    }
+    pub fn set_ref_price(&mut self, sym: u32, price: i32) -> u64 {
+        let shard = self.shard_of(sym);
+        self.dispatch(shard, Command::RefPrice { sym, price })
+    }
+    pub fn reopen_symbol(&mut self, sym: u32) -> u64 {
+        let shard = self.shard_of(sym);
+        self.dispatch(shard, Command::Reopen { sym })
+    }
+    pub fn set_price_limits(&mut self, sym: u32, limit_down: i32,
+                            limit_up: i32) -> u64 {
+        let shard = self.shard_of(sym);
+        self.dispatch(shard, Command::PriceLimit { sym, limit_down, limit_up })
+    }
+    pub fn change_symbol_state(&mut self, sym: u32, state: State) -> u64 {
+        let shard = self.shard_of(sym);
+        self.dispatch(shard, Command::SymbolState { sym, state })
+    }
+    pub fn set_symbol_auction_rules(&mut self, sym: u32,
+                                    rules: Option<&PriceRules>)
+    -> Result<u64, EngineError> {
+        let rules = match rules {
+            Some(rules) => Some(rules.pack().ok_or(EngineError::InvalidRules)?),
+            None => None,
+        };
+        let shard = self.shard_of(sym);
+        Ok(self.dispatch(shard, Command::SymbolRules { sym, rules }))
+    }
+    pub fn set_volatility_band(&mut self, sym: u32, bps: Option<u32>) -> u64 {
+        let shard = self.shard_of(sym);
+        self.dispatch(shard, Command::VolBand { sym, bps })
+    }
+    // broadcast to all shards, one reply per shard
+    fn broadcast(&mut self, cmd: Command) -> u64 {
+        self.seq += 1;
+        for shard in self.shards.iter() {
+            if let Some(tx) = shard.tx.as_ref() {
+                if tx.send((self.seq, cmd)).is_ok() {
+                    self.pending += 1;
+                }
+            }
+        }
+        self.seq
+    }
+    pub fn change_state(&mut self, new_state: State) -> u64 {
+        self.broadcast(Command::ChangeState(new_state))
+    }
+    pub fn set_trade_price(&mut self, rule: TradePrice) -> u64 {
+        self.broadcast(Command::TradePrice(rule))
+    }
+    pub fn set_auction_rules(&mut self, rules: &PriceRules)
+    -> Result<u64, EngineError> {
+        let rules = rules.pack().ok_or(EngineError::InvalidRules)?;
+        Ok(self.broadcast(Command::AuctionRules { rules }))
+    }
+    // wait all pending replies, returns replies ordered by (seq, shard)
+    // with oids mapped to global and deals merged with global deal no
+    pub fn flush(&mut self) -> (Vec<ShardReply>, Vec<ShardDeal>) {
+        let mut res = Vec::<ShardReply>::with_capacity(self.pending);
+        while self.pending > 0 {
+            if let Ok(rep) = self.replies.recv() {
+                res.push(rep);
+                self.pending -= 1;
+            } else {
+                error!("all shards gone, {} replies lost", self.pending);
+                self.pending = 0;
+            }
+        }
+        res.sort_by_key(|r| (r.seq, r.shard));
+        let mut deals = Vec::<ShardDeal>::new();
+        for rep in res.iter_mut() {
+            rep.reply = match rep.reply {
+                Reply::NewOrder(Ok(oid)) => {
+                    Reply::NewOrder(Ok(self.global_oid(rep.shard, oid)))
+                },
+                Reply::Amend(Ok(oid)) => {
+                    Reply::Amend(Ok(self.global_oid(rep.shard, oid)))
+                },
+                r => r,
+            };
+            for deal in rep.deals.drain(..) {
+                self.deal_no += 1;
+                let oid = self.global_oid(rep.shard, deal.oid() as u64);
+                let contra = self.global_oid(rep.shard, deal.contra() as u64);
+                deals.push(ShardDeal { no: self.deal_no, shard: rep.shard,
+                                       oid, contra, deal });
+            }
+        }
+        (res, deals)
+    }
+}
+
+impl Drop for ShardRouter {
+    fn drop(&mut self) {
+        for shard in self.shards.iter_mut() {
+            shard.tx.take();
+        }
+        for shard in self.shards.iter_mut() {
+            if let Some(h) = shard.handle.take() {
+                let _ = h.join();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::{warn, LevelFilter};
+    use simple_logger::SimpleLogger;
+    use match_base::Reject;
+
+    #[test]
+    fn test_shards() {
+        if let Err(s) = SimpleLogger::new().init() {
+            warn!("SimpleLogger init: {}", s);
+        }
+        log::set_max_level(LevelFilter::Info);
+        let mut router = ShardRouter::new(2);
+        assert_eq!(router.shard_of(1), 1);
+        assert_eq!(router.shard_of(2), 0);
+        router.change_state(State::StateStart);
+        router.change_state(State::StateTrading);
+        router.send_order(1, true, 44000, 50);
+        router.send_order(2, true, 43500, 45);
+        router.send_order(2, false, 43000, 20);
+        router.send_order(1, false, 43000, 5);
+        let (replies, deals) = router.flush();
+        assert_eq!(replies.len(), 8);
+        assert!(replies[..4].iter()
+                .all(|r| r.reply() == Reply::ChangeState(Ok(()))));
+        let oids: Vec<Reply> = replies[4..].iter().map(|r| r.reply()).collect();
+        // local oid 1,1,2,2 on shard 1,0,0,1
+        assert_eq!(oids, vec![Reply::NewOrder(Ok(3)), Reply::NewOrder(Ok(2)),
+                              Reply::NewOrder(Ok(4)), Reply::NewOrder(Ok(5))]);
+        // merged by command seq, shard 0 deals first
+        assert_eq!(deals.len(), 4);
+        assert_eq!(deals.iter().map(|d| d.no()).collect::<Vec<u64>>(),
+                   vec![1, 2, 3, 4]);
+        assert_eq!(deals.iter().map(|d| d.shard()).collect::<Vec<usize>>(),
+                   vec![0, 0, 1, 1]);
+        assert_eq!(deals[0].deal().qty(), 20);
+        assert_eq!(deals[2].deal().price(), 44000);
+        assert_eq!(deals[2].deal().qty(), 5);
+        // deal oids as replied
+        let deal_oids: Vec<(u64, u64)> = deals.iter()
+            .map(|d| (d.oid(), d.contra())).collect();
+        assert_eq!(deal_oids, vec![(2, 4), (4, 2), (3, 5), (5, 3)]);
+        // cancel routed by global oid
+        router.cancel_order(3);
+        router.cancel_order(2);
+        router.cancel_order(7);
+        let (replies, deals) = router.flush();
+        assert!(deals.is_empty());
+        let res: Vec<Reply> = replies.iter().map(|r| r.reply()).collect();
+        assert_eq!(res, vec![Reply::Cancel(Ok(45)), Reply::Cancel(Ok(25)),
+                             Reply::Cancel(Err(EngineError::UnknownOrder))]);
+    }
+
+    #[test]
+    fn test_shard_config() {
+        use match_base::Symbols;
+        if let Err(s) = SimpleLogger::new().init() {
+            warn!("SimpleLogger init: {}", s);
+        }
+        log::set_max_level(LevelFilter::Info);
+        // instrument master of each shard
+        let csv = "ag2506,0,10,1,1,1000\nau2506,0,20,1,1,1000\n";
+        let mut router = ShardRouter::with_engines(2, move |_| {
+            MatchEngine::with_symbols(Symbols::read_csv(csv.as_bytes())
+                                        .unwrap())
+        });
+        router.change_state(State::StateStart);
+        router.change_state(State::StateTrading);
+        router.set_trade_price(TradePrice::Aggressor);
+        assert!(router.set_auction_rules(&PriceRules::standard()).is_ok());
+        assert_eq!(router.set_auction_rules(&PriceRules::Chain(
+                        vec![crate::auction::TieBreak::RefPrice; 8])),
+                   Err(EngineError::InvalidRules));
+        assert!(router.set_symbol_auction_rules(2, None).is_ok());
+        router.set_ref_price(1, 5000);
+        router.set_volatility_band(1, Some(100));
+        let (replies, _) = router.flush();
+        assert_eq!(replies.len(), 11);
+        assert!(replies.iter().all(|r| match r.reply() {
+            Reply::ChangeState(res) | Reply::TradePrice(res)
+            | Reply::AuctionRules(res) | Reply::SymbolRules(res)
+            | Reply::RefPrice(res) | Reply::VolBand(res) => res.is_ok(),
+            _ => false,
+        }));
+        // au2506 tick 20, cu19xx unknown
+        router.send_order(2, false, 8010, 5);
+        router.send_order(3, false, 8000, 5);
+        router.send_order(2, false, 8000, 5);
+        router.send_order(2, true, 8100, 5);
+        let (replies, deals) = router.flush();
+        let res: Vec<Reply> = replies.iter().map(|r| r.reply()).collect();
+        assert_eq!(res[..2].to_vec(), vec![
+            Reply::NewOrder(Err(EngineError::Rejected(Reject::PriceTick))),
+            Reply::NewOrder(Err(EngineError::Rejected(
+                                Reject::UnknownSymbol)))]);
+        // aggressor price broadcast
+        assert_eq!(deals.len(), 2);
+        assert_eq!(deals[0].deal().price(), 8100);
+        // band of ag2506 4950 .. 5050, out of band trade to auction
+        router.send_order(1, false, 5100, 5);
+        router.send_order(1, true, 5100, 5);
+        let (_, deals) = router.flush();
+        assert!(deals.is_empty());
+    }
+}
@@ -0,0 +1,82 @@
+use std::io::{self, Read, Write};
+
+// little endian field writer/reader for engine snapshot
+pub struct SnapWriter<W: Write> {
+    w:  W,
+}
+
+pub struct SnapReader<R: Read> {
+    r:  R,
+}
+
+pub const SNAP_MAGIC: &[u8; 8] = b"MESNAP08";
+
+pub fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+impl<W: Write> SnapWriter<W> {
+    pub fn new(w: W) -> SnapWriter<W> {
+        SnapWriter { w }
+    }
+    pub fn put_bytes(&mut self, v: &[u8]) -> io::Result<()> {
+        self.w.write_all(v)
+    }
+    pub fn put_u8(&mut self, v: u8) -> io::Result<()> {
+        self.w.write_all(&[v])
+    }
+    pub fn put_u32(&mut self, v: u32) -> io::Result<()> {
+        self.w.write_all(&v.to_le_bytes())
+    }
+    pub fn put_i32(&mut self, v: i32) -> io::Result<()> {
+        self.w.write_all(&v.to_le_bytes())
+    }
+    pub fn put_u64(&mut self, v: u64) -> io::Result<()> {
+        self.w.write_all(&v.to_le_bytes())
+    }
+    pub fn put_i64(&mut self, v: i64) -> io::Result<()> {
+        self.w.write_all(&v.to_le_bytes())
+    }
+    pub fn put_str(&mut self, v: &str) -> io::Result<()> {
+        self.put_u32(v.len() as u32)?;
+        self.w.write_all(v.as_bytes())
+    }
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.w.flush()
+    }
+}
+
+impl<R: Read> SnapReader<R> {
+    pub fn new(r: R) -> SnapReader<R> {
+        SnapReader { r }
+    }
+    pub fn get_bytes<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        let mut buf = [0u8; N];
+        self.r.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+    pub fn get_u8(&mut self) -> io::Result<u8> {
+        Ok(self.get_bytes::<1>()?[0])
+    }
+    pub fn get_bool(&mut self) -> io::Result<bool> {
+        Ok(self.get_u8()? != 0)
+    }
+    pub fn get_u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.get_bytes()?))
+    }
+    pub fn get_i32(&mut self) -> io::Result<i32> {
+        Ok(i32::from_le_bytes(self.get_bytes()?))
+    }
+    pub fn get_u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.get_bytes()?))
+    }
+    pub fn get_i64(&mut self) -> io::Result<i64> {
+        Ok(i64::from_le_bytes(self.get_bytes()?))
+    }
+    pub fn get_str(&mut self) -> io::Result<String> {
+        let len = self.get_u32()? as usize;
+        let mut buf = vec![0u8; len];
+        self.r.read_exact(&mut buf)?;
+        String::from_utf8(buf).map_err(|_| invalid_data("bad utf8 string"))
+    }
+}
@@ -1,6 +1,6 @@
 use std::fmt;
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone, Copy, Debug)]
 #[repr(u8)]
 pub enum State {
     StateIdle=0,
@@ -37,6 +37,20 @@ impl fmt::Display for State {
 }
 
 impl State {
+    pub const fn from_u8(v: u8) -> Option<State> {
+        match v {
+            0 => Some(StateIdle),
+            1 => Some(StateStart),
+            2 => Some(StatePreAuction),
+            3 => Some(StateCallAuction),
+            4 => Some(StateTrading),
+            5 => Some(StatePause),
+            6 => Some(StateBreak),
+            7 => Some(StateStop),
+            8 => Some(StateEnd),
+            _ => None,
+        }
+    }
     pub fn review(&self, new_state: &Self) -> bool {
         match new_state {
             State::StateIdle => {
@@ -78,9 +92,11 @@ impl State {
                     false
                 }
             },
+            // callAuction to stop for closing auction
             State::StateStop => {
                 match *self {
-                StateTrading | StateBreak | StatePreAuction => true,
+                StateTrading | StateBreak | StatePreAuction |
+                StateCallAuction => true,
                 _ => false
                 }
             },
@@ -100,6 +116,17 @@ impl State {
     pub fn can_book(&self) -> bool {
         *self == StatePreAuction || *self == StateTrading
     }
+    // effective symbol state, symbol state only while market trading
+    pub fn bound(&self, sym_state: Option<State>) -> State {
+        match sym_state {
+            Some(st) if self.is_tc() => st,
+            _ => *self,
+        }
+    }
+    // can cancel order in orderBook
+    pub fn can_cancel(&self) -> bool {
+        self.can_book() || *self == StatePause
+    }
 }
 
 #[cfg(test)]
@@ -114,6 +141,7 @@ mod tests {
         state = State::StateStart;
         assert!(!state.is_tc());
         assert!(!state.can_book());
+        assert!(!state.can_cancel());
         assert!(state.review(&State::StatePreAuction));
         assert!(!state.review(&State::StateCallAuction));
         assert!(state.review(&State::StateTrading));
@@ -126,6 +154,7 @@ mod tests {
         assert!(!state.is_tc());
         assert!(!state.can_book());
         assert!(state.review(&State::StateTrading));
+        assert!(state.review(&State::StateStop));
         state = State::StateTrading;
         assert!(state.review(&State::StatePause));
         assert!(state.review(&State::StateBreak));
@@ -134,5 +163,12 @@ mod tests {
         assert!(!state.review(&State::StatePreAuction));
         assert!(state.is_tc());
         assert!(state.can_book());
+        assert!(state.can_cancel());
+        assert!(state.bound(Some(State::StatePause)) == State::StatePause);
+        assert!(state.bound(None) == State::StateTrading);
+        state = State::StatePause;
+        assert!(!state.can_book());
+        assert!(state.can_cancel());
+        assert!(state.bound(Some(State::StatePreAuction)) == State::StatePause);
     }
 }
@@ -0,0 +1,317 @@
+use std::fmt::Display;
+use std::io::{self, Read};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// FIX 4.4 tag=value messages, fields separated by SOH. 8/9 prepended and
+// 10 appended on encode, decode keeps fields from 35 on
+pub const SOH: u8 = 0x01;
+pub const BEGIN_STRING: &str = "FIX.4.4";
+
+pub const TAG_AVG_PX: u32 = 6;
+pub const TAG_BEGIN_SEQ_NO: u32 = 7;
+pub const TAG_CL_ORD_ID: u32 = 11;
+pub const TAG_CUM_QTY: u32 = 14;
+pub const TAG_END_SEQ_NO: u32 = 16;
+pub const TAG_EXEC_ID: u32 = 17;
+pub const TAG_LAST_PX: u32 = 31;
+pub const TAG_LAST_QTY: u32 = 32;
+pub const TAG_MSG_SEQ_NUM: u32 = 34;
+pub const TAG_MSG_TYPE: u32 = 35;
+pub const TAG_NEW_SEQ_NO: u32 = 36;
+pub const TAG_ORDER_ID: u32 = 37;
+pub const TAG_ORDER_QTY: u32 = 38;
+pub const TAG_ORD_STATUS: u32 = 39;
+pub const TAG_ORD_TYPE: u32 = 40;
+pub const TAG_ORIG_CL_ORD_ID: u32 = 41;
+pub const TAG_POSS_DUP_FLAG: u32 = 43;
+pub const TAG_PRICE: u32 = 44;
+pub const TAG_REF_SEQ_NUM: u32 = 45;
+pub const TAG_SENDER_COMP_ID: u32 = 49;
+pub const TAG_SENDING_TIME: u32 = 52;
+pub const TAG_SIDE: u32 = 54;
+pub const TAG_SYMBOL: u32 = 55;
+pub const TAG_TARGET_COMP_ID: u32 = 56;
+pub const TAG_TEXT: u32 = 58;
+pub const TAG_TIME_IN_FORCE: u32 = 59;
+pub const TAG_ENCRYPT_METHOD: u32 = 98;
+pub const TAG_CXL_REJ_REASON: u32 = 102;
+pub const TAG_ORD_REJ_REASON: u32 = 103;
+pub const TAG_HEART_BT_INT: u32 = 108;
+pub const TAG_TEST_REQ_ID: u32 = 112;
+pub const TAG_ORIG_SENDING_TIME: u32 = 122;
+pub const TAG_GAP_FILL_FLAG: u32 = 123;
+pub const TAG_RESET_SEQ_NUM_FLAG: u32 = 141;
+pub const TAG_EXEC_TYPE: u32 = 150;
+pub const TAG_LEAVES_QTY: u32 = 151;
+pub const TAG_REF_TAG_ID: u32 = 371;
+pub const TAG_REF_MSG_TYPE: u32 = 372;
+pub const TAG_SESSION_REJECT_REASON: u32 = 373;
+pub const TAG_CXL_REJ_RESPONSE_TO: u32 = 434;
+
+pub const MSG_HEARTBEAT: &str = "0";
+pub const MSG_TEST_REQUEST: &str = "1";
+pub const MSG_RESEND_REQUEST: &str = "2";
+pub const MSG_REJECT: &str = "3";
+pub const MSG_SEQUENCE_RESET: &str = "4";
+pub const MSG_LOGOUT: &str = "5";
+pub const MSG_EXECUTION_REPORT: &str = "8";
+pub const MSG_ORDER_CANCEL_REJECT: &str = "9";
+pub const MSG_LOGON: &str = "A";
+pub const MSG_NEW_ORDER_SINGLE: &str = "D";
+pub const MSG_ORDER_CANCEL_REQUEST: &str = "F";
+pub const MSG_ORDER_CANCEL_REPLACE: &str = "G";
+
+// standard header fields, stamped by session on send
+const HEADER_TAGS: [u32; 6] = [TAG_SENDER_COMP_ID, TAG_TARGET_COMP_ID,
+                               TAG_MSG_SEQ_NUM, TAG_SENDING_TIME,
+                               TAG_POSS_DUP_FLAG, TAG_ORIG_SENDING_TIME];
+// body length bound of inbound message
+const MAX_BODY_LEN: usize = 4096;
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct FixMsg {
+    fields: Vec<(u32, String)>,
+}
+
+impl FixMsg {
+    pub fn new(msg_type: &str) -> FixMsg {
+        FixMsg { fields: vec![(TAG_MSG_TYPE, msg_type.to_string())] }
+    }
+    pub fn msg_type(&self) -> &str {
+        self.get(TAG_MSG_TYPE).unwrap_or("")
+    }
+    // session level message, gap filled on resend
+    pub fn is_admin(&self) -> bool {
+        matches!(self.msg_type(), MSG_HEARTBEAT | MSG_TEST_REQUEST |
+                 MSG_RESEND_REQUEST | MSG_REJECT | MSG_SEQUENCE_RESET |
+                 MSG_LOGOUT | MSG_LOGON)
+    }
+    // first occurrence of tag
+    pub fn get(&self, tag: u32) -> Option<&str> {
+        self.fields.iter().find(|f| f.0 == tag).map(|f| f.1.as_str())
+    }
+    pub fn get_u32(&self, tag: u32) -> Option<u32> {
+        self.get(tag)?.parse().ok()
+    }
+    pub fn get_u64(&self, tag: u32) -> Option<u64> {
+        self.get(tag)?.parse().ok()
+    }
+    pub fn is_set(&self, tag: u32) -> bool {
+        self.get(tag) == Some("Y")
+    }
+    pub fn push<T: Display>(&mut self, tag: u32, val: T) {
+        self.fields.push((tag, val.to_string()));
+    }
+    pub fn with<T: Display>(mut self, tag: u32, val: T) -> FixMsg {
+        self.push(tag, val);
+        self
+    }
+    // replace header fields, put right after msg type
+    pub fn stamp(&mut self, hdr: &[(u32, String)]) {
+        self.fields.retain(|f| !HEADER_TAGS.contains(&f.0));
+        let at = self.fields.len().min(1);
+        self.fields.splice(at..at, hdr.iter().cloned());
+    }
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        let mut body = Vec::<u8>::with_capacity(256);
+        for (tag, val) in self.fields.iter() {
+            body.extend_from_slice(tag.to_string().as_bytes());
+            body.push(b'=');
+            body.extend_from_slice(val.as_bytes());
+            body.push(SOH);
+        }
+        let start = buf.len();
+        buf.extend_from_slice(format!("8={}\x019={}\x01", BEGIN_STRING,
+                                      body.len()).as_bytes());
+        buf.extend_from_slice(&body);
+        let sum = checksum(&buf[start..]);
+        buf.extend_from_slice(format!("10={:03}\x01", sum).as_bytes());
+    }
+}
+
+fn checksum(buf: &[u8]) -> u8 {
+    buf.iter().fold(0u8, |s, b| s.wrapping_add(*b))
+}
+
+// fields of message, begin string/body length/checksum checked
+// None for garbled message
+pub fn decode(buf: &[u8]) -> Option<FixMsg> {
+    if buf.len() < 7 || buf[buf.len()-1] != SOH { return None }
+    let tail = buf.len() - 7;
+    if &buf[tail..tail+3] != b"10=" { return None }
+    let sum: u8 = std::str::from_utf8(&buf[tail+3..tail+6]).ok()?
+                    .parse().ok()?;
+    if sum != checksum(&buf[..tail]) { return None }
+    let text = std::str::from_utf8(&buf[..tail]).ok()?;
+    let (begin, rest) = text.strip_prefix("8=")?.split_once('\x01')?;
+    if begin != BEGIN_STRING { return None }
+    let (blen, body) = rest.strip_prefix("9=")?.split_once('\x01')?;
+    if blen.parse::<usize>().ok()? != body.len() { return None }
+    let mut fields = Vec::<(u32, String)>::new();
+    for fld in body.split_terminator('\x01') {
+        let (tag, val) = fld.split_once('=')?;
+        fields.push((tag.parse().ok()?, val.to_string()));
+    }
+    if fields.first()?.0 != TAG_MSG_TYPE { return None }
+    Some(FixMsg { fields })
+}
+
+// messages of stream, partial message kept across read timeout
+pub struct FixReader<R: Read> {
+    r:      R,
+    buf:    Vec<u8>,
+}
+
+impl<R: Read> FixReader<R> {
+    pub fn new(r: R) -> FixReader<R> {
+        FixReader { r, buf: Vec::<u8>::with_capacity(MAX_BODY_LEN) }
+    }
+    // next message, Ok(None) on orderly close. garbled message dropped
+    // with InvalidData, next read resyncs on begin string
+    pub fn read_msg(&mut self) -> io::Result<Option<FixMsg>> {
+        loop {
+            if let Some(rlen) = self.frame_len()? {
+                let frame: Vec<u8> = self.buf.drain(..rlen).collect();
+                return decode(&frame).map(Some)
+                            .ok_or_else(|| bad_msg("garbled message"))
+            }
+            let mut tmp = [0u8; 1024];
+            let n = self.r.read(&mut tmp)?;
+            if n == 0 { return Ok(None) }
+            self.buf.extend_from_slice(&tmp[..n]);
+        }
+    }
+    // length of buffered message, None if incomplete
+    fn frame_len(&mut self) -> io::Result<Option<usize>> {
+        if self.buf.len() < 2 { return Ok(None) }
+        if !self.buf.starts_with(b"8=") {
+            let skip = find(&self.buf[1..], b"8=")
+                        .map_or(self.buf.len() - 1, |p| p + 1);
+            self.buf.drain(..skip);
+            return Err(bad_msg("bad begin string"))
+        }
+        let hdr = match find(&self.buf, b"\x019=") {
+            Some(p) => p + 3,
+            None => return Ok(None),
+        };
+        let end = match self.buf[hdr..].iter().position(|b| *b == SOH) {
+            Some(p) => hdr + p,
+            None => return Ok(None),
+        };
+        let blen = std::str::from_utf8(&self.buf[hdr..end]).ok()
+                        .and_then(|s| s.parse::<usize>().ok())
+                        .filter(|n| *n <= MAX_BODY_LEN);
+        let blen = match blen {
+            Some(blen) => blen,
+            None => {
+                self.buf.drain(..1);
+                return Err(bad_msg("bad body length"))
+            },
+        };
+        let rlen = end + 1 + blen + 7;
+        if self.buf.len() < rlen { return Ok(None) }
+        Ok(Some(rlen))
+    }
+}
+
+fn find(buf: &[u8], pat: &[u8]) -> Option<usize> {
+    buf.windows(pat.len()).position(|w| w == pat)
+}
+
+fn bad_msg(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// decimal price of FIX to engine price w/ digits decimals
+pub fn parse_price(s: &str, digits: i8) -> Option<i32> {
+    let digits = digits.max(0) as usize;
+    let (int, frac) = s.split_once('.').unwrap_or((s, ""));
+    let frac = frac.trim_end_matches('0');
+    if frac.len() > digits || !frac.bytes().all(|b| b.is_ascii_digit()) {
+        return None
+    }
+    let neg = int.starts_with('-');
+    let int: i64 = int.parse().ok()?;
+    let mut v = int.abs();
+    for i in 0..digits {
+        let d = frac.as_bytes().get(i).map_or(0, |b| (b - b'0') as i64);
+        v = v * 10 + d;
+    }
+    let v = if neg { -v } else { v };
+    i32::try_from(v).ok()
+}
+
+pub fn format_price(price: i64, digits: i8) -> String {
+    if digits <= 0 { return price.to_string() }
+    let scale = 10i64.pow(digits as u32);
+    let sign = if price < 0 { "-" } else { "" };
+    format!("{}{}.{:0w$}", sign, price.abs() / scale, price.abs() % scale,
+            w = digits as usize)
+}
+
+// UTCTimestamp of now, YYYYMMDD-HH:MM:SS.sss
+pub fn utc_now() -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = now.as_secs() as i64;
+    let (days, tod) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+    // civil date of days since epoch
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    format!("{:04}{:02}{:02}-{:02}:{:02}:{:02}.{:03}", year, month, day,
+            tod / 3600, tod % 3600 / 60, tod % 60, now.subsec_millis())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fix_codec() {
+        let mut msg = FixMsg::new(MSG_NEW_ORDER_SINGLE)
+                        .with(TAG_CL_ORD_ID, "c1").with(TAG_SYMBOL, "cu1906")
+                        .with(TAG_SIDE, 1).with(TAG_ORDER_QTY, 10);
+        msg.stamp(&[(TAG_SENDER_COMP_ID, "B1".to_string()),
+                    (TAG_MSG_SEQ_NUM, "7".to_string())]);
+        assert_eq!(msg.fields[1], (TAG_SENDER_COMP_ID, "B1".to_string()));
+        let mut buf = Vec::<u8>::new();
+        msg.encode(&mut buf);
+        assert!(buf.starts_with(b"8=FIX.4.4\x019="));
+        assert_eq!(decode(&buf).as_ref(), Some(&msg));
+        assert_eq!(msg.get_u32(TAG_MSG_SEQ_NUM), Some(7));
+        assert!(!msg.is_admin());
+        // restamp keeps single header
+        let mut msg2 = msg.clone();
+        msg2.stamp(&[(TAG_MSG_SEQ_NUM, "8".to_string())]);
+        assert_eq!(msg2.get(TAG_SENDER_COMP_ID), None);
+        assert_eq!(msg2.get_u32(TAG_MSG_SEQ_NUM), Some(8));
+        // bad checksum
+        let mut bad = buf.clone();
+        let n = bad.len();
+        bad[n-2] = if bad[n-2] == b'0' { b'1' } else { b'0' };
+        assert!(decode(&bad).is_none());
+        // garbage, bad message, split message then good one
+        let mut stream = b"xx".to_vec();
+        stream.extend_from_slice(&bad);
+        stream.extend_from_slice(&buf);
+        let mut rdr = FixReader::new(stream.as_slice());
+        assert!(rdr.read_msg().is_err());
+        assert!(rdr.read_msg().is_err());
+        assert_eq!(rdr.read_msg().unwrap(), Some(msg));
+        assert!(rdr.read_msg().unwrap().is_none());
+        assert_eq!(parse_price("440.5", 2), Some(44050));
+        assert_eq!(parse_price("-1.25", 2), Some(-125));
+        assert_eq!(parse_price("44000", 0), Some(44000));
+        assert_eq!(parse_price("1.001", 2), None);
+        assert_eq!(format_price(44050, 2), "440.50");
+        assert_eq!(format_price(-5, 2), "-0.05");
+        assert_eq!(format_price(44000, 0), "44000");
+        assert_eq!(utc_now().len(), 21);
+    }
+}
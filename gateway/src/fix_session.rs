@@ -0,0 +1,967 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Duration;
+use log::{info, warn};
+use engine::{MatchEngine, Command, ExecType, EngineError};
+use match_base::{OrderType, TimeInForce, Reject};
+use crate::fix::*;
+use crate::fix_store::FixStore;
+use crate::matcher::{Request, Event, EventSink, next_session};
+
+// logon expected within
+const LOGON_TIMEOUT: Duration = Duration::from_secs(10);
+
+static EXEC_ID: AtomicU64 = AtomicU64::new(1);
+
+// symbol name and price digits of engine symbols
+pub struct FixSymbols {
+    ids:    HashMap<String, u32>,
+    digits: HashMap<u32, i8>,
+}
+
+// FIX acceptor of comp_id, counterparty known by SenderCompID of logon.
+// session id and orders of counterparty kept across reconnect
+pub struct FixAcceptor {
+    comp_id:    String,
+    store_dir:  PathBuf,
+    symbols:    FixSymbols,
+    parties:    Mutex<HashMap<String, Party>>,
+}
+
+struct Party {
+    session:    u32,
+    orders: Arc<Mutex<Orders>>,
+    active: bool,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+enum ReqKind {
+    New,
+    Cancel,
+    Replace,
+}
+
+// order entry request awaiting engine response
+struct Pending {
+    kind:   ReqKind,
+    cl:     String,
+    orig:   String,     // OrigClOrdID of cancel/replace
+    oid:    u64,        // order of cancel/replace
+    symbol: String,
+    digits: i8,
+    buy:    bool,
+    prior_cum:  u32,    // filled of order re-keyed by amend
+}
+
+// live order of counterparty
+struct OrdState {
+    cl:     String,
+    symbol: String,
+    digits: i8,
+    buy:    bool,
+    base_cum:   u32,    // filled before amend re-keyed the order
+    cum:    u32,
+    notional:   i64,    // sum of fill price * qty
+}
+
+// engine cl_ord_id of request -> pending request, oid -> live order
+#[derive(Default)]
+struct Orders {
+    keys:   u64,
+    pending:    HashMap<u64, Pending>,
+    live:   HashMap<u64, OrdState>,
+    cl_oids:    HashMap<String, u64>,
+}
+
+// to session writer
+enum Out {
+    Msg(FixMsg),
+    // resend sent messages begin..=end, end 0 for all
+    Resend(u32, u32),
+}
+
+// reports of matcher thread as execution reports
+struct FixSink {
+    tx:     Sender<Out>,
+    orders: Arc<Mutex<Orders>>,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+enum SeqCheck {
+    Next,
+    Gap,        // seq too high, resend requested
+    Dup,        // possdup already seen
+}
+
+struct FixSession<'a> {
+    id:     u32,
+    acc:    &'a FixAcceptor,
+    tx:     Sender<Out>,
+    store:  Arc<Mutex<FixStore>>,
+    orders: Arc<Mutex<Orders>>,
+    next_in:    u32,
+    gap_to: Option<u32>,    // highest seq seen while resend pending
+}
+
+impl FixSymbols {
+    pub fn new(me: &MatchEngine) -> FixSymbols {
+        let mut syms = FixSymbols { ids: HashMap::new(),
+                                    digits: HashMap::new() };
+        let mut idx = 1;
+        while let Some(sym) = me.symbol(idx) {
+            syms.ids.insert(sym.name().to_string(), idx);
+            syms.digits.insert(idx, sym.digits());
+            idx += 1;
+        }
+        syms
+    }
+    fn get(&self, name: &str) -> Option<(u32, i8)> {
+        let sym = *self.ids.get(name)?;
+        Some((sym, self.digits.get(&sym).copied().unwrap_or(0)))
+    }
+}
+
+impl FixAcceptor {
+    pub fn new(comp_id: &str, store_dir: &Path, symbols: FixSymbols)
+    -> FixAcceptor {
+        FixAcceptor { comp_id: comp_id.to_string(),
+                      store_dir: store_dir.to_path_buf(), symbols,
+                      parties: Mutex::new(HashMap::new()) }
+    }
+    // session id and orders of counterparty, None if logged on already
+    fn logon(&self, target: &str) -> Option<(u32, Arc<Mutex<Orders>>)> {
+        let mut parties = self.parties.lock().unwrap();
+        let party = parties.entry(target.to_string()).or_insert_with(|| {
+            Party { session: next_session(), orders: Default::default(),
+                    active: false }
+        });
+        if party.active { return None }
+        party.active = true;
+        Some((party.session, party.orders.clone()))
+    }
+    fn logout(&self, target: &str) {
+        if let Some(party) = self.parties.lock().unwrap().get_mut(target) {
+            party.active = false;
+        }
+    }
+}
+
+fn next_exec_id() -> u64 {
+    EXEC_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+fn side(buy: bool) -> &'static str {
+    if buy { "1" } else { "2" }
+}
+
+fn ord_rej_reason(e: EngineError) -> u32 {
+    match e {
+        EngineError::UnknownSymbol |
+        EngineError::Rejected(Reject::UnknownSymbol) => 1,
+        EngineError::InvalidState => 2,
+        EngineError::DuplicateOrder => 6,
+        EngineError::InvalidQty | EngineError::Rejected(Reject::QtyMin) |
+        EngineError::Rejected(Reject::QtyMax) |
+        EngineError::Rejected(Reject::QtyLot) => 13,
+        _ => 99,
+    }
+}
+
+fn cxl_rej_reason(e: EngineError) -> u32 {
+    match e {
+        EngineError::OrderFilled | EngineError::OrderCanceled => 0,
+        EngineError::UnknownOrder => 1,
+        _ => 99,
+    }
+}
+
+// rejected new order
+fn order_reject(cl: &str, symbol: &str, buy: bool, reason: u32, text: &str)
+-> FixMsg {
+    FixMsg::new(MSG_EXECUTION_REPORT).with(TAG_ORDER_ID, "NONE")
+        .with(TAG_CL_ORD_ID, cl).with(TAG_EXEC_ID, next_exec_id())
+        .with(TAG_EXEC_TYPE, "8").with(TAG_ORD_STATUS, "8")
+        .with(TAG_SYMBOL, symbol).with(TAG_SIDE, side(buy))
+        .with(TAG_LEAVES_QTY, 0).with(TAG_CUM_QTY, 0).with(TAG_AVG_PX, 0)
+        .with(TAG_ORD_REJ_REASON, reason).with(TAG_TEXT, text)
+}
+
+// rejected cancel/replace, order status as before the request
+fn cancel_reject(req: &Pending, status: &str, reason: u32, text: &str)
+-> FixMsg {
+    let oid = if req.oid == 0 {
+        "NONE".to_string()
+    } else {
+        req.oid.to_string()
+    };
+    let to = if req.kind == ReqKind::Cancel { 1 } else { 2 };
+    FixMsg::new(MSG_ORDER_CANCEL_REJECT).with(TAG_ORDER_ID, oid)
+        .with(TAG_CL_ORD_ID, &req.cl).with(TAG_ORIG_CL_ORD_ID, &req.orig)
+        .with(TAG_ORD_STATUS, status).with(TAG_CXL_REJ_RESPONSE_TO, to)
+        .with(TAG_CXL_REJ_REASON, reason).with(TAG_TEXT, text)
+}
+
+impl Orders {
+    fn request(&mut self, req: Pending) -> u64 {
+        self.keys += 1;
+        self.pending.insert(self.keys, req);
+        self.keys
+    }
+    // order of cancel/replace by OrderID, or by OrigClOrdID if not given
+    fn resolve(&self, msg: &FixMsg) -> Option<u64> {
+        match msg.get_u64(TAG_ORDER_ID) {
+            Some(oid) => Some(oid),
+            None => self.cl_oids.get(msg.get(TAG_ORIG_CL_ORD_ID)?).copied(),
+        }
+    }
+    fn status(&self, oid: u64) -> &'static str {
+        match self.live.get(&oid) {
+            Some(st) if st.cum > 0 => "1",
+            Some(_) => "0",
+            None => "8",
+        }
+    }
+    // execution report or cancel reject of engine report, None if
+    // report not for client, e.g. cancel of order re-keyed by amend
+    fn report(&mut self, ev: &Event) -> Option<FixMsg> {
+        let (key, rep) = (ev.cl_ord_id(), ev.report());
+        let oid = rep.oid();
+        match rep.exec_type() {
+            ExecType::Rejected => {
+                let req = self.pending.remove(&key)?;
+                let e = rep.reason().unwrap_or(EngineError::InvalidState);
+                let text = e.to_string();
+                if oid == 0 {
+                    return Some(order_reject(&req.cl, &req.symbol, req.buy,
+                                             ord_rej_reason(e), &text))
+                }
+                return Some(cancel_reject(&req, self.status(oid),
+                                          cxl_rej_reason(e), &text))
+            },
+            ExecType::Canceled => {
+                if let Some(req) = self.pending.get_mut(&key) {
+                    if req.kind == ReqKind::Replace && req.oid == oid {
+                        req.prior_cum = rep.cum_qty();
+                        return None
+                    }
+                }
+            },
+            _ => {},
+        }
+        // first report answers request, later fills by oid
+        let req = self.pending.remove(&key);
+        if let Some(req) = &req {
+            if req.kind == ReqKind::New {
+                let st = OrdState { cl: req.cl.clone(),
+                    symbol: req.symbol.clone(), digits: req.digits,
+                    buy: req.buy, base_cum: 0, cum: 0, notional: 0 };
+                self.live.insert(oid, st);
+            } else {
+                let mut st = self.live.remove(&req.oid)?;
+                self.cl_oids.remove(&st.cl);
+                if oid != req.oid {
+                    st.base_cum += req.prior_cum;
+                }
+                st.cl = req.cl.clone();
+                self.live.insert(oid, st);
+            }
+            self.cl_oids.insert(req.cl.clone(), oid);
+        }
+        let st = self.live.get_mut(&oid)?;
+        st.cum = st.base_cum + rep.cum_qty();
+        st.notional += rep.last_price() as i64 * rep.last_qty() as i64;
+        let working = if st.cum > 0 { "1" } else { "0" };
+        let replaced = req.as_ref().map(|r| r.kind) == Some(ReqKind::Replace);
+        let (exec_type, status) = match rep.exec_type() {
+            ExecType::New if replaced => ("5", working),
+            ExecType::New => ("0", "0"),
+            ExecType::PartialFill => ("F", "1"),
+            ExecType::Filled => ("F", "2"),
+            ExecType::Canceled => ("4", "4"),
+            ExecType::Expired => ("C", "C"),
+            ExecType::Replaced => ("5", working),
+            ExecType::Rejected => ("8", "8"),
+        };
+        let avg_px = if st.cum == 0 {
+            0
+        } else {
+            st.notional / st.cum as i64
+        };
+        let mut msg = FixMsg::new(MSG_EXECUTION_REPORT)
+            .with(TAG_ORDER_ID, oid).with(TAG_CL_ORD_ID, &st.cl);
+        if let Some(req) = req.as_ref().filter(|r| r.kind != ReqKind::New) {
+            msg.push(TAG_ORIG_CL_ORD_ID, &req.orig);
+        }
+        msg = msg.with(TAG_EXEC_ID, next_exec_id())
+            .with(TAG_EXEC_TYPE, exec_type).with(TAG_ORD_STATUS, status)
+            .with(TAG_SYMBOL, &st.symbol).with(TAG_SIDE, side(st.buy))
+            .with(TAG_ORDER_QTY, st.cum + rep.leaves_qty())
+            .with(TAG_LEAVES_QTY, rep.leaves_qty())
+            .with(TAG_CUM_QTY, st.cum)
+            .with(TAG_AVG_PX, format_price(avg_px, st.digits));
+        if rep.last_qty() > 0 {
+            msg = msg.with(TAG_LAST_PX,
+                           format_price(rep.last_price() as i64, st.digits))
+                     .with(TAG_LAST_QTY, rep.last_qty());
+        }
+        // filled, canceled or expired
+        if rep.leaves_qty() == 0 {
+            let cl = st.cl.clone();
+            self.cl_oids.remove(&cl);
+            self.live.remove(&oid);
+        }
+        Some(msg)
+    }
+}
+
+impl EventSink for FixSink {
+    fn event(&mut self, ev: &Event) -> bool {
+        match self.orders.lock().unwrap().report(ev) {
+            Some(msg) => self.tx.send(Out::Msg(msg)).is_ok(),
+            None => true,
+        }
+    }
+}
+
+// accept FIX sessions, each session in own thread
+pub fn serve(listener: TcpListener, matcher: Sender<Request>,
+             acc: Arc<FixAcceptor>) {
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!("accept failed: {}", e);
+                continue
+            },
+        };
+        let (matcher, acc) = (matcher.clone(), acc.clone());
+        thread::spawn(move || {
+            if let Err(e) = run_session(stream, &matcher, &acc) {
+                warn!("FIX session closed: {}", e);
+            }
+        });
+    }
+}
+
+fn header(sender: &str, target: &str, seq: u32, orig_time: Option<&str>)
+-> Vec<(u32, String)> {
+    let mut hdr = vec![(TAG_SENDER_COMP_ID, sender.to_string()),
+                       (TAG_TARGET_COMP_ID, target.to_string()),
+                       (TAG_MSG_SEQ_NUM, seq.to_string()),
+                       (TAG_SENDING_TIME, utc_now())];
+    if let Some(orig) = orig_time {
+        hdr.push((TAG_POSS_DUP_FLAG, "Y".to_string()));
+        hdr.push((TAG_ORIG_SENDING_TIME, orig.to_string()));
+    }
+    hdr
+}
+
+// sent messages begin..=end again w/ PossDupFlag, admin messages and
+// messages not kept replaced by gap fill
+fn resend(st: &FixStore, begin: u32, end: u32, sender: &str, target: &str,
+          buf: &mut Vec<u8>) {
+    let last = st.next_out() - 1;
+    let end = if end == 0 || end > last { last } else { end };
+    let mut gap: Option<u32> = None;
+    for seq in begin.max(1)..=end {
+        let msg = st.get(seq).and_then(decode).filter(|m| !m.is_admin());
+        let mut msg = match msg {
+            Some(msg) => msg,
+            None => {
+                gap.get_or_insert(seq);
+                continue
+            },
+        };
+        if let Some(from) = gap.take() {
+            gap_fill(from, seq, sender, target, buf);
+        }
+        let orig = msg.get(TAG_SENDING_TIME).unwrap_or("").to_string();
+        msg.stamp(&header(sender, target, seq, Some(&orig)));
+        msg.encode(buf);
+    }
+    if let Some(from) = gap {
+        gap_fill(from, end + 1, sender, target, buf);
+    }
+}
+
+fn gap_fill(seq: u32, new_seq: u32, sender: &str, target: &str,
+            buf: &mut Vec<u8>) {
+    let mut msg = FixMsg::new(MSG_SEQUENCE_RESET)
+                    .with(TAG_GAP_FILL_FLAG, "Y").with(TAG_NEW_SEQ_NO, new_seq);
+    msg.stamp(&header(sender, target, seq, Some(&utc_now())));
+    msg.encode(buf);
+}
+
+// outbound messages sequenced and stored, heartbeat if idle for hb.
+// stops after logout
+fn write_loop(mut stream: TcpStream, rx: Receiver<Out>, sender: String,
+              target: String, store: Arc<Mutex<FixStore>>, hb: Duration) {
+    let mut buf = Vec::<u8>::with_capacity(1024);
+    loop {
+        let out = match rx.recv_timeout(hb) {
+            Ok(out) => out,
+            Err(RecvTimeoutError::Timeout) => {
+                Out::Msg(FixMsg::new(MSG_HEARTBEAT))
+            },
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        buf.clear();
+        let mut st = store.lock().unwrap();
+        let logout = match out {
+            Out::Msg(mut msg) => {
+                msg.stamp(&header(&sender, &target, st.next_out(), None));
+                msg.encode(&mut buf);
+                if let Err(e) = st.sent(&buf) {
+                    warn!("FIX {} store failed: {}", target, e);
+                    break
+                }
+                msg.msg_type() == MSG_LOGOUT
+            },
+            Out::Resend(begin, end) => {
+                resend(&st, begin, end, &sender, &target, &mut buf);
+                false
+            },
+        };
+        drop(st);
+        if stream.write_all(&buf).is_err() || logout { break }
+    }
+}
+
+// logon first, TargetCompID of logon must be comp_id of acceptor
+pub fn run_session(stream: TcpStream, matcher: &Sender<Request>,
+                   acc: &FixAcceptor) -> io::Result<()> {
+    let mut rdr = FixReader::new(stream.try_clone()?);
+    stream.set_read_timeout(Some(LOGON_TIMEOUT))?;
+    let logon = match rdr.read_msg()? {
+        Some(msg) if msg.msg_type() == MSG_LOGON => msg,
+        Some(msg) => {
+            warn!("FIX {} before logon", msg.msg_type());
+            return Ok(())
+        },
+        None => return Ok(()),
+    };
+    let target = match logon.get(TAG_SENDER_COMP_ID) {
+        Some(target) if logon.get(TAG_TARGET_COMP_ID) ==
+                            Some(acc.comp_id.as_str()) => target.to_string(),
+        _ => {
+            warn!("FIX logon of unknown comp id from {}", stream.peer_addr()?);
+            return Ok(())
+        },
+    };
+    let (session, orders) = match acc.logon(&target) {
+        Some(party) => party,
+        None => {
+            warn!("FIX {} logged on already", target);
+            return Ok(())
+        },
+    };
+    let res = logged_on(&stream, rdr, matcher, acc, session, orders,
+                        &target, &logon);
+    // closed once counterparty may logon again
+    acc.logout(&target);
+    let _ = stream.shutdown(Shutdown::Both);
+    res
+}
+
+#[allow(clippy::too_many_arguments)]
+fn logged_on(stream: &TcpStream, mut rdr: FixReader<TcpStream>,
+             matcher: &Sender<Request>, acc: &FixAcceptor, session: u32,
+             orders: Arc<Mutex<Orders>>, target: &str, logon: &FixMsg)
+-> io::Result<()> {
+    let mut store = FixStore::open(&acc.store_dir, &acc.comp_id, target)?;
+    let reset = logon.is_set(TAG_RESET_SEQ_NUM_FLAG);
+    if reset {
+        store.reset()?;
+    }
+    let next_in = store.next_in();
+    let store = Arc::new(Mutex::new(store));
+    let hb_secs = logon.get_u32(TAG_HEART_BT_INT).unwrap_or(30).max(1);
+    let hb = Duration::from_secs(hb_secs as u64);
+    let (tx, rx) = channel::<Out>();
+    let (w, st) = (stream.try_clone()?, store.clone());
+    let (sender, tgt) = (acc.comp_id.clone(), target.to_string());
+    let writer = thread::spawn(move || write_loop(w, rx, sender, tgt, st, hb));
+    let mut sess = FixSession { id: session, acc, tx, store, orders,
+                                next_in, gap_to: None };
+    let seq = logon.get_u32(TAG_MSG_SEQ_NUM).unwrap_or(0);
+    if seq < next_in {
+        warn!("FIX {} logon seq {} expected {}", target, seq, next_in);
+        sess.logout(&format!("MsgSeqNum too low, expecting {} but \
+                              received {}", next_in, seq));
+    } else {
+        info!("FIX {} session {} logon from {}", target, session,
+              stream.peer_addr()?);
+        let mut ack = FixMsg::new(MSG_LOGON).with(TAG_ENCRYPT_METHOD, 0)
+                        .with(TAG_HEART_BT_INT, hb_secs);
+        if reset {
+            ack.push(TAG_RESET_SEQ_NUM_FLAG, "Y");
+        }
+        sess.send(ack);
+        let sink = FixSink { tx: sess.tx.clone(),
+                             orders: sess.orders.clone() };
+        // party session kept across reconnects, owns the orders
+        let _ = matcher.send(Request::Logon { session, account: None,
+                                             sink: Box::new(sink) });
+        sess.check_seq(logon)?;
+        stream.set_read_timeout(Some(hb + hb / 5))?;
+        sess.run(&mut rdr, matcher)?;
+        let _ = matcher.send(Request::Logout { session });
+    }
+    drop(sess);
+    let _ = writer.join();
+    info!("FIX {} session {} closed", target, session);
+    Ok(())
+}
+
+impl FixSession<'_> {
+    fn send(&self, msg: FixMsg) {
+        let _ = self.tx.send(Out::Msg(msg));
+    }
+    fn logout(&self, text: &str) {
+        self.send(FixMsg::new(MSG_LOGOUT).with(TAG_TEXT, text));
+    }
+    fn set_next_in(&mut self, seq: u32) -> io::Result<()> {
+        self.next_in = seq;
+        if self.gap_to.is_some_and(|to| seq > to) {
+            self.gap_to = None;
+        }
+        self.store.lock().unwrap().set_next_in(seq)
+    }
+    fn check_seq(&mut self, msg: &FixMsg) -> io::Result<SeqCheck> {
+        let seq = msg.get_u32(TAG_MSG_SEQ_NUM).unwrap_or(0);
+        if seq < self.next_in {
+            if msg.is_set(TAG_POSS_DUP_FLAG) { return Ok(SeqCheck::Dup) }
+            let text = format!("MsgSeqNum too low, expecting {} but \
+                                received {}", self.next_in, seq);
+            return Err(io::Error::new(io::ErrorKind::InvalidData, text))
+        }
+        if seq > self.next_in {
+            // messages after gap dropped until resent
+            if self.gap_to.is_none() {
+                self.send(FixMsg::new(MSG_RESEND_REQUEST)
+                            .with(TAG_BEGIN_SEQ_NO, self.next_in)
+                            .with(TAG_END_SEQ_NO, 0));
+            }
+            self.gap_to = Some(seq.max(self.gap_to.unwrap_or(0)));
+            return Ok(SeqCheck::Gap)
+        }
+        self.set_next_in(seq + 1)?;
+        Ok(SeqCheck::Next)
+    }
+    // session closed after two heartbeat intervals w/o inbound message
+    fn run(&mut self, rdr: &mut FixReader<TcpStream>,
+           matcher: &Sender<Request>) -> io::Result<()> {
+        let mut idle = 0;
+        loop {
+            let msg = match rdr.read_msg() {
+                Ok(Some(msg)) => msg,
+                Ok(None) => return Ok(()),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock ||
+                          e.kind() == io::ErrorKind::TimedOut => {
+                    idle += 1;
+                    if idle < 2 {
+                        self.send(FixMsg::new(MSG_TEST_REQUEST)
+                                    .with(TAG_TEST_REQ_ID, "TEST"));
+                        continue
+                    }
+                    self.logout("heartbeat timeout");
+                    return Ok(())
+                },
+                Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                    warn!("FIX session {} garbled: {}", self.id, e);
+                    continue
+                },
+                Err(e) => return Err(e),
+            };
+            idle = 0;
+            // sequence reset mode ignores seq
+            if msg.msg_type() == MSG_SEQUENCE_RESET &&
+                !msg.is_set(TAG_GAP_FILL_FLAG) {
+                if let Some(seq) = msg.get_u32(TAG_NEW_SEQ_NO) {
+                    self.set_next_in(seq)?;
+                }
+                continue
+            }
+            let seq = match self.check_seq(&msg) {
+                Ok(seq) => seq,
+                Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                    self.logout(&e.to_string());
+                    return Ok(())
+                },
+                Err(e) => return Err(e),
+            };
+            let ty = msg.msg_type();
+            // resend request and logout served within gap
+            if seq == SeqCheck::Dup || (seq == SeqCheck::Gap &&
+                    ty != MSG_RESEND_REQUEST && ty != MSG_LOGOUT) {
+                continue
+            }
+            match ty {
+                MSG_HEARTBEAT | MSG_REJECT => {},
+                MSG_TEST_REQUEST => {
+                    let id = msg.get(TAG_TEST_REQ_ID).unwrap_or("");
+                    self.send(FixMsg::new(MSG_HEARTBEAT)
+                                .with(TAG_TEST_REQ_ID, id));
+                },
+                MSG_RESEND_REQUEST => {
+                    let begin = self.parsed(&msg, TAG_BEGIN_SEQ_NO);
+                    let end = self.parsed(&msg, TAG_END_SEQ_NO);
+                    if let (Some(begin), Some(end)) = (begin, end) {
+                        let _ = self.tx.send(Out::Resend(begin, end));
+                    }
+                },
+                MSG_SEQUENCE_RESET => {
+                    if let Some(seq) = self.parsed(&msg, TAG_NEW_SEQ_NO) {
+                        if seq > self.next_in {
+                            self.set_next_in(seq)?;
+                        }
+                    }
+                },
+                MSG_LOGOUT => {
+                    self.logout("");
+                    return Ok(())
+                },
+                MSG_NEW_ORDER_SINGLE | MSG_ORDER_CANCEL_REQUEST |
+                MSG_ORDER_CANCEL_REPLACE => {
+                    if let Some((cl_ord_id, cmd)) = self.order_entry(&msg) {
+                        let req = Request::Order { session: self.id,
+                                                   cl_ord_id, cmd };
+                        if matcher.send(req).is_err() { return Ok(()) }
+                    }
+                },
+                _ => self.reject(&msg, TAG_MSG_TYPE, 11, "invalid MsgType"),
+            }
+        }
+    }
+    // session level reject of inbound message
+    fn reject(&self, msg: &FixMsg, tag: u32, reason: u32, text: &str) {
+        let seq = msg.get_u32(TAG_MSG_SEQ_NUM).unwrap_or(0);
+        self.send(FixMsg::new(MSG_REJECT).with(TAG_REF_SEQ_NUM, seq)
+                    .with(TAG_REF_TAG_ID, tag)
+                    .with(TAG_REF_MSG_TYPE, msg.msg_type())
+                    .with(TAG_SESSION_REJECT_REASON, reason)
+                    .with(TAG_TEXT, text));
+    }
+    fn required<'m>(&self, msg: &'m FixMsg, tag: u32) -> Option<&'m str> {
+        let v = msg.get(tag);
+        if v.is_none() {
+            self.reject(msg, tag, 1, "required tag missing");
+        }
+        v
+    }
+    fn parsed<T: FromStr>(&self, msg: &FixMsg, tag: u32) -> Option<T> {
+        let v = self.required(msg, tag)?.parse().ok();
+        if v.is_none() {
+            self.reject(msg, tag, 5, "value is incorrect for this tag");
+        }
+        v
+    }
+    fn side(&self, msg: &FixMsg) -> Option<bool> {
+        match self.required(msg, TAG_SIDE)? {
+            "1" => Some(true),
+            "2" => Some(false),
+            _ => {
+                self.reject(msg, TAG_SIDE, 5, "unsupported side");
+                None
+            },
+        }
+    }
+    fn price(&self, msg: &FixMsg, digits: i8) -> Option<i32> {
+        let price = parse_price(self.required(msg, TAG_PRICE)?, digits);
+        if price.is_none() {
+            self.reject(msg, TAG_PRICE, 5, "price off digits");
+        }
+        price
+    }
+    // engine command of order entry message w/ engine cl_ord_id of request
+    fn order_entry(&self, msg: &FixMsg) -> Option<(u64, Command)> {
+        let cl = self.required(msg, TAG_CL_ORD_ID)?.to_string();
+        let symbol = self.required(msg, TAG_SYMBOL)?.to_string();
+        let buy = self.side(msg)?;
+        let (sym, digits) = match self.acc.symbols.get(&symbol) {
+            Some(sym) => sym,
+            None if msg.msg_type() == MSG_NEW_ORDER_SINGLE => {
+                self.send(order_reject(&cl, &symbol, buy, 1,
+                                       "unknown symbol"));
+                return None
+            },
+            None => (0, 0),
+        };
+        if msg.msg_type() == MSG_NEW_ORDER_SINGLE {
+            let qty = self.parsed(msg, TAG_ORDER_QTY)?;
+            let ord_type = match self.required(msg, TAG_ORD_TYPE)? {
+                "1" => OrderType::Market,
+                "2" => OrderType::Limit,
+                _ => {
+                    self.reject(msg, TAG_ORD_TYPE, 5, "unsupported OrdType");
+                    return None
+                },
+            };
+            let tif = match msg.get(TAG_TIME_IN_FORCE).unwrap_or("0") {
+                "0" => TimeInForce::Day,
+                "1" => TimeInForce::GTC,
+                "3" => TimeInForce::IOC,
+                "4" => TimeInForce::FOK,
+                _ => {
+                    self.reject(msg, TAG_TIME_IN_FORCE, 5,
+                                "unsupported TimeInForce");
+                    return None
+                },
+            };
+            let price = if ord_type == OrderType::Limit {
+                self.price(msg, digits)?
+            } else {
+                0
+            };
+            let req = Pending { kind: ReqKind::New, cl, orig: String::new(),
+                                oid: 0, symbol, digits, buy, prior_cum: 0 };
+            let key = self.orders.lock().unwrap().request(req);
+            return Some((key, Command::NewOrder { sym, buy, price, qty,
+                                                  ord_type, tif }))
+        }
+        let orig = self.required(msg, TAG_ORIG_CL_ORD_ID)?.to_string();
+        let kind = if msg.msg_type() == MSG_ORDER_CANCEL_REQUEST {
+            ReqKind::Cancel
+        } else {
+            ReqKind::Replace
+        };
+        let amend = if kind == ReqKind::Replace {
+            let qty: u32 = self.parsed(msg, TAG_ORDER_QTY)?;
+            Some((self.price(msg, digits)?, qty))
+        } else {
+            None
+        };
+        let mut orders = self.orders.lock().unwrap();
+        let mut req = Pending { kind, cl, orig, oid: 0, symbol, digits, buy,
+                                prior_cum: 0 };
+        req.oid = match orders.resolve(msg) {
+            Some(oid) => oid,
+            None => {
+                self.send(cancel_reject(&req, "8", 1, "unknown order"));
+                return None
+            },
+        };
+        let oid = req.oid;
+        // OrderQty of FIX counts fills of orders re-keyed by amend
+        let base_cum = orders.live.get(&oid).map_or(0, |st| st.base_cum);
+        let key = orders.request(req);
+        let cmd = match amend {
+            Some((price, qty)) => {
+                Command::Amend { oid, price, qty: qty.saturating_sub(base_cum) }
+            },
+            None => Command::Cancel { oid },
+        };
+        Some((key, cmd))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::net::{TcpListener, TcpStream};
+    use std::path::Path;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+    use engine::MatchEngine;
+    use crate::fix::*;
+    use crate::matcher::Matcher;
+    use super::{serve, FixAcceptor, FixSymbols};
+
+    struct Initiator {
+        s:      TcpStream,
+        rdr:    FixReader<TcpStream>,
+        comp_id:    &'static str,
+        seq:    u32,
+    }
+
+    impl Initiator {
+        fn connect(addr: &str, comp_id: &'static str, seq: u32) -> Initiator {
+            let s = TcpStream::connect(addr).unwrap();
+            s.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            let rdr = FixReader::new(s.try_clone().unwrap());
+            Initiator { s, rdr, comp_id, seq }
+        }
+        fn send_seq(&mut self, seq: u32, mut msg: FixMsg) {
+            msg.stamp(&[(TAG_SENDER_COMP_ID, self.comp_id.to_string()),
+                        (TAG_TARGET_COMP_ID, "MATCH".to_string()),
+                        (TAG_MSG_SEQ_NUM, seq.to_string()),
+                        (TAG_SENDING_TIME, utc_now())]);
+            let mut buf = Vec::<u8>::new();
+            msg.encode(&mut buf);
+            self.s.write_all(&buf).unwrap();
+        }
+        fn send(&mut self, msg: FixMsg) {
+            self.seq += 1;
+            self.send_seq(self.seq, msg);
+        }
+        fn recv(&mut self) -> FixMsg {
+            self.rdr.read_msg().unwrap().unwrap()
+        }
+        fn logon(&mut self, hb: u32) -> FixMsg {
+            self.send(FixMsg::new(MSG_LOGON).with(TAG_ENCRYPT_METHOD, 0)
+                        .with(TAG_HEART_BT_INT, hb));
+            self.recv()
+        }
+        fn order(&mut self, cl: &str, side: u32, price: &str, qty: u32) {
+            self.send(FixMsg::new(MSG_NEW_ORDER_SINGLE)
+                .with(TAG_CL_ORD_ID, cl).with(TAG_SYMBOL, "cu1906")
+                .with(TAG_SIDE, side).with(TAG_ORDER_QTY, qty)
+                .with(TAG_ORD_TYPE, 2).with(TAG_PRICE, price));
+        }
+        fn replace(&mut self, cl: &str, orig: &str, price: &str, qty: u32) {
+            self.send(FixMsg::new(MSG_ORDER_CANCEL_REPLACE)
+                .with(TAG_CL_ORD_ID, cl).with(TAG_ORIG_CL_ORD_ID, orig)
+                .with(TAG_SYMBOL, "cu1906").with(TAG_SIDE, 2)
+                .with(TAG_ORDER_QTY, qty).with(TAG_ORD_TYPE, 2)
+                .with(TAG_PRICE, price));
+        }
+        fn cancel(&mut self, cl: &str, orig: &str) {
+            self.send(FixMsg::new(MSG_ORDER_CANCEL_REQUEST)
+                .with(TAG_CL_ORD_ID, cl).with(TAG_ORIG_CL_ORD_ID, orig)
+                .with(TAG_SYMBOL, "cu1906").with(TAG_SIDE, 2));
+        }
+    }
+
+    fn check(msg: &FixMsg, fields: &[(u32, &str)]) {
+        for (tag, val) in fields {
+            assert_eq!(msg.get(*tag), Some(*val), "tag {} of {:?}", tag, msg);
+        }
+    }
+
+    #[test]
+    fn test_fix_session() {
+        let dir = std::env::temp_dir()
+                    .join(format!("fix-session-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut me = MatchEngine::new();
+        assert!(me.begin_market().is_ok());
+        assert!(me.start_trading().is_ok());
+        let acc = FixAcceptor::new("MATCH", Path::new(&dir),
+                                   FixSymbols::new(&me));
+        let (matcher, _) = Matcher::new(me).spawn();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || serve(listener, matcher, Arc::new(acc)));
+        let mut b1 = Initiator::connect(&addr, "B1", 0);
+        check(&b1.logon(30), &[(TAG_MSG_TYPE, MSG_LOGON),
+                               (TAG_MSG_SEQ_NUM, "1"),
+                               (TAG_SENDER_COMP_ID, "MATCH"),
+                               (TAG_TARGET_COMP_ID, "B1"),
+                               (TAG_HEART_BT_INT, "30")]);
+        let mut b2 = Initiator::connect(&addr, "B2", 0);
+        b2.logon(30);
+        b1.order("S1", 2, "44000", 10);
+        check(&b1.recv(), &[(TAG_MSG_TYPE, MSG_EXECUTION_REPORT),
+                            (TAG_MSG_SEQ_NUM, "2"), (TAG_ORDER_ID, "1"),
+                            (TAG_CL_ORD_ID, "S1"), (TAG_EXEC_TYPE, "0"),
+                            (TAG_ORD_STATUS, "0"), (TAG_LEAVES_QTY, "10")]);
+        b2.order("P1", 1, "44000", 4);
+        check(&b2.recv(), &[(TAG_EXEC_TYPE, "0"), (TAG_ORDER_ID, "2")]);
+        check(&b2.recv(), &[(TAG_EXEC_TYPE, "F"), (TAG_ORD_STATUS, "2"),
+                            (TAG_CUM_QTY, "4"), (TAG_LEAVES_QTY, "0")]);
+        check(&b1.recv(), &[(TAG_EXEC_TYPE, "F"), (TAG_ORD_STATUS, "1"),
+                            (TAG_CUM_QTY, "4"), (TAG_LEAVES_QTY, "6"),
+                            (TAG_LAST_PX, "44000"), (TAG_LAST_QTY, "4"),
+                            (TAG_AVG_PX, "44000"), (TAG_SIDE, "2")]);
+        // qty down in place, then price change re-keys the order
+        b1.replace("S2", "S1", "44000", 8);
+        check(&b1.recv(), &[(TAG_EXEC_TYPE, "5"), (TAG_ORD_STATUS, "1"),
+                            (TAG_ORDER_ID, "1"), (TAG_CL_ORD_ID, "S2"),
+                            (TAG_ORIG_CL_ORD_ID, "S1"), (TAG_ORDER_QTY, "8"),
+                            (TAG_CUM_QTY, "4"), (TAG_LEAVES_QTY, "4")]);
+        b1.replace("S3", "S2", "44010", 8);
+        check(&b1.recv(), &[(TAG_EXEC_TYPE, "5"), (TAG_ORDER_ID, "3"),
+                            (TAG_CL_ORD_ID, "S3"), (TAG_ORIG_CL_ORD_ID, "S2"),
+                            (TAG_ORDER_QTY, "8"), (TAG_CUM_QTY, "4"),
+                            (TAG_LEAVES_QTY, "4"), (TAG_AVG_PX, "44000")]);
+        b1.cancel("S4", "S3");
+        check(&b1.recv(), &[(TAG_EXEC_TYPE, "4"), (TAG_ORD_STATUS, "4"),
+                            (TAG_ORDER_ID, "3"), (TAG_CL_ORD_ID, "S4"),
+                            (TAG_ORIG_CL_ORD_ID, "S3"), (TAG_LEAVES_QTY, "0")]);
+        b1.cancel("S5", "S3");
+        check(&b1.recv(), &[(TAG_MSG_TYPE, MSG_ORDER_CANCEL_REJECT),
+                            (TAG_MSG_SEQ_NUM, "7"), (TAG_ORD_STATUS, "8"),
+                            (TAG_CXL_REJ_RESPONSE_TO, "1"),
+                            (TAG_CXL_REJ_REASON, "1")]);
+        b1.send(FixMsg::new(MSG_TEST_REQUEST).with(TAG_TEST_REQ_ID, "T1"));
+        check(&b1.recv(), &[(TAG_MSG_TYPE, MSG_HEARTBEAT),
+                            (TAG_TEST_REQ_ID, "T1")]);
+        // app messages resent as possdup, heartbeat gap filled
+        b1.send(FixMsg::new(MSG_RESEND_REQUEST).with(TAG_BEGIN_SEQ_NO, 2)
+                    .with(TAG_END_SEQ_NO, 0));
+        for seq in 2..=7 {
+            let msg = b1.recv();
+            check(&msg, &[(TAG_MSG_SEQ_NUM, &seq.to_string()),
+                          (TAG_POSS_DUP_FLAG, "Y")]);
+            assert!(msg.get(TAG_ORIG_SENDING_TIME).is_some());
+        }
+        check(&b1.recv(), &[(TAG_MSG_TYPE, MSG_SEQUENCE_RESET),
+                            (TAG_MSG_SEQ_NUM, "8"), (TAG_GAP_FILL_FLAG, "Y"),
+                            (TAG_NEW_SEQ_NO, "9")]);
+        b1.send(FixMsg::new(MSG_LOGOUT));
+        check(&b1.recv(), &[(TAG_MSG_TYPE, MSG_LOGOUT),
+                            (TAG_MSG_SEQ_NUM, "9")]);
+        assert!(b1.rdr.read_msg().unwrap().is_none());
+        // reconnect w/ seqs kept, inbound gap resent by gap fill
+        let mut b1 = Initiator::connect(&addr, "B1", b1.seq + 2);
+        check(&b1.logon(30), &[(TAG_MSG_TYPE, MSG_LOGON),
+                               (TAG_MSG_SEQ_NUM, "10")]);
+        check(&b1.recv(), &[(TAG_MSG_TYPE, MSG_RESEND_REQUEST),
+                            (TAG_BEGIN_SEQ_NO, "10"), (TAG_END_SEQ_NO, "0")]);
+        b1.send_seq(10, FixMsg::new(MSG_SEQUENCE_RESET)
+                    .with(TAG_GAP_FILL_FLAG, "Y")
+                    .with(TAG_NEW_SEQ_NO, b1.seq + 1));
+        b1.send(FixMsg::new(MSG_NEW_ORDER_SINGLE).with(TAG_CL_ORD_ID, "S6")
+                    .with(TAG_SYMBOL, "xx").with(TAG_SIDE, 1)
+                    .with(TAG_ORDER_QTY, 1).with(TAG_ORD_TYPE, 2)
+                    .with(TAG_PRICE, "1"));
+        check(&b1.recv(), &[(TAG_MSG_SEQ_NUM, "12"), (TAG_EXEC_TYPE, "8"),
+                            (TAG_ORD_STATUS, "8"), (TAG_ORD_REJ_REASON, "1")]);
+        b1.order("S7", 2, "44100", 1);
+        check(&b1.recv(), &[(TAG_EXEC_TYPE, "0"), (TAG_ORDER_ID, "4")]);
+        b1.send(FixMsg::new(MSG_ORDER_CANCEL_REQUEST));
+        check(&b1.recv(), &[(TAG_MSG_TYPE, MSG_REJECT),
+                            (TAG_REF_TAG_ID, "11"),
+                            (TAG_SESSION_REJECT_REASON, "1")]);
+        // one session per counterparty
+        let mut dup = Initiator::connect(&addr, "B1", 100);
+        dup.send(FixMsg::new(MSG_LOGON).with(TAG_HEART_BT_INT, 30));
+        assert!(dup.rdr.read_msg().unwrap().is_none());
+        b1.send(FixMsg::new(MSG_LOGOUT));
+        check(&b1.recv(), &[(TAG_MSG_TYPE, MSG_LOGOUT)]);
+        assert!(b1.rdr.read_msg().unwrap().is_none());
+        // seq too low
+        let mut b1 = Initiator::connect(&addr, "B1", 0);
+        let msg = b1.logon(30);
+        assert_eq!(msg.msg_type(), MSG_LOGOUT);
+        assert!(msg.get(TAG_TEXT).unwrap().contains("too low"));
+        assert!(b1.rdr.read_msg().unwrap().is_none());
+        // seqs reset by logon
+        let mut b1 = Initiator::connect(&addr, "B1", 0);
+        b1.send(FixMsg::new(MSG_LOGON).with(TAG_HEART_BT_INT, 1)
+                    .with(TAG_RESET_SEQ_NUM_FLAG, "Y"));
+        check(&b1.recv(), &[(TAG_MSG_TYPE, MSG_LOGON), (TAG_MSG_SEQ_NUM, "1"),
+                            (TAG_RESET_SEQ_NUM_FLAG, "Y")]);
+        // idle session gets heartbeat and test request
+        let mut types = vec![b1.recv().msg_type().to_string(),
+                             b1.recv().msg_type().to_string()];
+        types.sort();
+        assert_eq!(types, vec![MSG_HEARTBEAT, MSG_TEST_REQUEST]);
+        b1.send(FixMsg::new(MSG_HEARTBEAT).with(TAG_TEST_REQ_ID, "TEST"));
+        b1.send(FixMsg::new(MSG_LOGOUT));
+        let mut msg = b1.recv();
+        while msg.msg_type() == MSG_HEARTBEAT {
+            msg = b1.recv();
+        }
+        assert_eq!(msg.msg_type(), MSG_LOGOUT);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,145 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use log::warn;
+use crate::fix::{FixReader, TAG_MSG_SEQ_NUM};
+
+// seq numbers and sent messages of FIX session, kept across reconnect and
+// restart. <dir>/<sender>-<target>.seqs holds next inbound/outbound seq,
+// .msgs appends sent messages for resend. seqs replaced by rename, bad
+// seqs rebuilt from .msgs and inbound gap recovered by resend request
+pub struct FixStore {
+    seqs_path:  PathBuf,
+    msgs:   File,
+    next_in:    u32,
+    next_out:   u32,
+    sent:   BTreeMap<u32, Vec<u8>>,
+}
+
+impl FixStore {
+    pub fn open(dir: &Path, sender: &str, target: &str)
+    -> io::Result<FixStore> {
+        fs::create_dir_all(dir)?;
+        let base = dir.join(format!("{}-{}", sender, target));
+        let seqs_path = base.with_extension("seqs");
+        let msgs_path = base.with_extension("msgs");
+        let seqs = match fs::read_to_string(&seqs_path) {
+            Ok(s) => {
+                let mut it = s.split_whitespace().map(|v| v.parse::<u32>());
+                match (it.next(), it.next()) {
+                    (Some(Ok(i)), Some(Ok(o))) => Some((i, o)),
+                    _ => None,
+                }
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Some((1, 1)),
+            Err(e) => return Err(e),
+        };
+        let mut sent = BTreeMap::<u32, Vec<u8>>::new();
+        if let Ok(log) = fs::read(&msgs_path) {
+            let mut rdr = FixReader::new(log.as_slice());
+            // torn tail of last write dropped
+            while let Ok(Some(msg)) = rdr.read_msg() {
+                if let Some(seq) = msg.get_u32(TAG_MSG_SEQ_NUM) {
+                    let mut buf = Vec::<u8>::new();
+                    msg.encode(&mut buf);
+                    sent.insert(seq, buf);
+                }
+            }
+        }
+        let msgs = OpenOptions::new().create(true).append(true)
+                        .open(&msgs_path)?;
+        let (next_in, next_out) = match seqs {
+            Some(seqs) => seqs,
+            None => {
+                let next_out = sent.keys().next_back().map_or(1, |s| s + 1);
+                warn!("bad seqs {:?}, next out {} from sent", seqs_path,
+                      next_out);
+                (1, next_out)
+            },
+        };
+        Ok(FixStore { seqs_path, msgs, next_in, next_out, sent })
+    }
+    pub fn next_in(&self) -> u32 {
+        self.next_in
+    }
+    pub fn next_out(&self) -> u32 {
+        self.next_out
+    }
+    pub fn set_next_in(&mut self, seq: u32) -> io::Result<()> {
+        self.next_in = seq;
+        self.save_seqs()
+    }
+    // message sent w/ next outbound seq
+    pub fn sent(&mut self, msg: &[u8]) -> io::Result<()> {
+        self.msgs.write_all(msg)?;
+        self.sent.insert(self.next_out, msg.to_vec());
+        self.next_out += 1;
+        self.save_seqs()
+    }
+    // sent message of seq, None if not kept
+    pub fn get(&self, seq: u32) -> Option<&[u8]> {
+        self.sent.get(&seq).map(|m| m.as_slice())
+    }
+    // new session, seqs from 1
+    pub fn reset(&mut self) -> io::Result<()> {
+        self.msgs.set_len(0)?;
+        self.sent.clear();
+        self.next_in = 1;
+        self.next_out = 1;
+        self.save_seqs()
+    }
+    // write temp and rename, seqs file never torn
+    fn save_seqs(&self) -> io::Result<()> {
+        let tmp = self.seqs_path.with_extension("seqs.tmp");
+        fs::write(&tmp, format!("{} {}\n", self.next_in, self.next_out))?;
+        fs::rename(&tmp, &self.seqs_path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use crate::fix::{FixMsg, MSG_HEARTBEAT, TAG_MSG_SEQ_NUM};
+    use super::FixStore;
+
+    #[test]
+    fn test_fix_store() {
+        let dir = std::env::temp_dir()
+                    .join(format!("fix-store-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let mut st = FixStore::open(&dir, "ME", "B1").unwrap();
+        assert_eq!((st.next_in(), st.next_out()), (1, 1));
+        let mut bufs = Vec::<Vec<u8>>::new();
+        for seq in 1..=3 {
+            let msg = FixMsg::new(MSG_HEARTBEAT).with(TAG_MSG_SEQ_NUM, seq);
+            let mut buf = Vec::<u8>::new();
+            msg.encode(&mut buf);
+            st.sent(&buf).unwrap();
+            bufs.push(buf);
+        }
+        st.set_next_in(5).unwrap();
+        drop(st);
+        // reopen after restart
+        let st = FixStore::open(&dir, "ME", "B1").unwrap();
+        assert_eq!((st.next_in(), st.next_out()), (5, 4));
+        assert_eq!(st.get(2), Some(bufs[1].as_slice()));
+        assert!(st.get(4).is_none());
+        drop(st);
+        // torn seqs, next out rebuilt from sent messages
+        let seqs = dir.join("ME-B1.seqs");
+        fs::write(&seqs, "5").unwrap();
+        let mut st = FixStore::open(&dir, "ME", "B1").unwrap();
+        assert_eq!((st.next_in(), st.next_out()), (1, 4));
+        assert_eq!(fs::read_to_string(&seqs).unwrap(), "5");
+        st.set_next_in(5).unwrap();
+        assert_eq!(fs::read_to_string(&seqs).unwrap(), "5 4\n");
+        assert!(!dir.join("ME-B1.seqs.tmp").exists());
+        st.reset().unwrap();
+        drop(st);
+        let st = FixStore::open(&dir, "ME", "B1").unwrap();
+        assert_eq!((st.next_in(), st.next_out()), (1, 1));
+        assert!(st.get(1).is_none());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
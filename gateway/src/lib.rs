@@ -0,0 +1,9 @@
+pub mod protocol;
+pub mod matcher;
+pub mod ring;
+pub mod session;
+pub mod fix;
+pub mod fix_store;
+pub mod fix_session;
+pub mod md_packet;
+pub mod md_publisher;
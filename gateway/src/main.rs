@@ -0,0 +1,119 @@
+use std::net::{TcpListener, ToSocketAddrs, UdpSocket};
+use std::sync::mpsc;
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+use clap::Parser;
+use simple_logger::SimpleLogger;
+use engine::{MatchEngine, MarketData};
+use match_base::Symbols;
+#[allow(unused_imports)]
+use log::{error, info, warn, LevelFilter};
+
+use gateway::matcher::Matcher;
+use gateway::{session, fix_session, md_publisher};
+use gateway::md_publisher::MdPublisher;
+use gateway::fix_session::{FixAcceptor, FixSymbols};
+
+/// Order entry gateway, binary protocol and FIX 4.4 over TCP, market data
+/// over UDP
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct Args {
+    /// Listen address of binary sessions
+    #[clap(short, long, default_value = "127.0.0.1:7001")]
+    listen: String,
+
+    /// Listen address of FIX sessions, FIX disabled if not given
+    #[clap(long)]
+    fix_listen: Option<String>,
+
+    /// SenderCompID of FIX acceptor
+    #[clap(long, default_value = "MATCH")]
+    fix_comp_id: String,
+
+    /// Directory of FIX seq numbers and sent messages
+    #[clap(long, default_value = "fix-store")]
+    fix_store: String,
+
+    /// UDP destination of market data, multicast group or unicast address
+    #[clap(long)]
+    md_dest: Option<String>,
+
+    /// Listen address of market data retransmission
+    #[clap(long, default_value = "127.0.0.1:7101")]
+    md_retrans: String,
+
+    /// Market data packets kept for retransmission
+    #[clap(long, default_value_t = 65536)]
+    md_window: usize,
+
+    /// Slots of lock-free command/event rings of matcher, channel if not
+    /// given
+    #[clap(long)]
+    ring: Option<usize>,
+
+    /// Instrument master csv
+    #[clap(short, long)]
+    symbols: Option<String>,
+}
+
+fn main() {
+    let args = Args::parse();
+    SimpleLogger::new().init().unwrap();
+    log::set_max_level(LevelFilter::Info);
+    let mut me = if let Some(filen) = &args.symbols {
+        let syms = Symbols::load_csv(filen).unwrap_or_else(|e| {
+            panic!("load symbols {} failed: {}", filen, e)
+        });
+        MatchEngine::with_symbols(syms)
+    } else {
+        MatchEngine::new()
+    };
+    if let Some(dest) = &args.md_dest {
+        start_md(&mut me, dest, &args);
+    }
+    me.begin_market().unwrap();
+    me.start_trading().unwrap();
+    let fix_syms = FixSymbols::new(&me);
+    let matcher = match args.ring {
+        Some(cap) => Matcher::new(me).spawn_ring(cap).0,
+        None => Matcher::new(me).spawn().0,
+    };
+    if let Some(addr) = &args.fix_listen {
+        let listener = TcpListener::bind(addr).unwrap_or_else(|e| {
+            panic!("listen on {} failed: {}", addr, e)
+        });
+        info!("FIX acceptor {} listen on {}", args.fix_comp_id, addr);
+        let acc = FixAcceptor::new(&args.fix_comp_id,
+                                   Path::new(&args.fix_store), fix_syms);
+        let matcher = matcher.clone();
+        thread::spawn(move || fix_session::serve(listener, matcher,
+                                                 Arc::new(acc)));
+    }
+    let listener = TcpListener::bind(&args.listen).unwrap_or_else(|e| {
+        panic!("listen on {} failed: {}", args.listen, e)
+    });
+    info!("gateway listen on {}", args.listen);
+    session::serve(listener, matcher);
+}
+
+// UDP publisher of engine market data w/ retransmission service
+fn start_md(me: &mut MatchEngine, dest: &str, args: &Args) {
+    let dest = dest.to_socket_addrs().ok().and_then(|mut it| it.next())
+                    .unwrap_or_else(|| panic!("bad md dest {}", dest));
+    let sock = UdpSocket::bind("0.0.0.0:0").unwrap();
+    if dest.ip().is_multicast() {
+        let _ = sock.set_multicast_ttl_v4(1);
+    }
+    let publ = MdPublisher::new(sock, dest, args.md_window);
+    let listener = TcpListener::bind(&args.md_retrans).unwrap_or_else(|e| {
+        panic!("listen on {} failed: {}", args.md_retrans, e)
+    });
+    info!("market data to {}, retransmission on {}", dest, args.md_retrans);
+    let window = publ.window();
+    thread::spawn(move || md_publisher::serve_retrans(listener, window));
+    let (tx, rx) = mpsc::channel::<MarketData>();
+    me.set_md_sink(Some(Box::new(tx)));
+    thread::spawn(move || publ.run(rx));
+}
@@ -0,0 +1,380 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+use log::info;
+use engine::{MatchEngine, Command, Reply, ExecReport, EngineError};
+// latency stamp, tsc where available
+#[cfg(target_arch = "x86_64")]
+use measure::MeasureTsc as Stamp;
+#[cfg(not(target_arch = "x86_64"))]
+use measure::Measure as Stamp;
+use crate::ring::{self, Producer, Consumer};
+
+// request of session to matcher thread. orders owned by account across
+// sessions, by session if w/o account
+pub enum Request {
+    Logon { session: u32, account: Option<u32>, sink: Box<dyn EventSink> },
+    // order entry command, new order/cancel/amend
+    Order { session: u32, cl_ord_id: u64, cmd: Command },
+    Logout { session: u32 },
+}
+
+// execution report of session order, cl_ord_id of the order or of the
+// rejected request
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Event {
+    cl_ord_id:  u64,
+    rep:    ExecReport,
+}
+
+// outbound of session, false once session gone
+pub trait EventSink: Send {
+    fn event(&mut self, ev: &Event) -> bool;
+}
+
+// request on command ring, stamped on enqueue for queueing latency
+pub struct Queued {
+    req:    Request,
+    stamp:  Stamp,
+}
+
+// outbound of matcher on event ring, session sinks owned by dispatcher
+pub enum Outbound {
+    Logon { session: u32, sink: Box<dyn EventSink> },
+    Event { session: u32, ev: Event },
+    Logout { session: u32 },
+}
+
+// queueing (enqueue to dequeue) and matching latency of ring matcher
+#[derive(Default, Clone, Copy, Debug)]
+pub struct Latency {
+    count:  u64,
+    queue_ns:   u64,
+    match_ns:   u64,
+}
+
+// owner of orders, account kept across reconnects
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+enum Owner {
+    Account(u32),
+    Session(u32),
+}
+
+// owns MatchEngine, executes commands of all sessions in arrival order
+// and routes execution reports to the last logged on session of the
+// order owner
+pub struct Matcher {
+    me:     MatchEngine,
+    reports: Receiver<ExecReport>,
+    sessions: HashMap<u32, Box<dyn EventSink>>,
+    logons: HashMap<u32, Owner>,    // session -> owner
+    active: HashMap<Owner, u32>,    // owner -> last logon session
+    owners: HashMap<u64, (Owner, u64)>, // oid -> (owner, cl_ord_id)
+    out:    Option<Producer<Outbound>>, // event ring of ring matcher
+}
+
+// session id unique across listeners
+static SESSION_ID: AtomicU32 = AtomicU32::new(1);
+
+pub fn next_session() -> u32 {
+    SESSION_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+impl Event {
+    pub fn cl_ord_id(&self) -> u64 {
+        self.cl_ord_id
+    }
+    pub fn report(&self) -> &ExecReport {
+        &self.rep
+    }
+}
+
+impl Queued {
+    pub fn new(req: Request) -> Queued {
+        Queued { req, stamp: Stamp::start("queue") }
+    }
+}
+
+impl Latency {
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+    // mean ns per request
+    pub fn queue_ns(&self) -> u64 {
+        if self.count == 0 { return 0 }
+        self.queue_ns / self.count
+    }
+    pub fn match_ns(&self) -> u64 {
+        if self.count == 0 { return 0 }
+        self.match_ns / self.count
+    }
+    fn add(&mut self, queue_ns: u64, match_ns: u64) {
+        self.count += 1;
+        self.queue_ns += queue_ns;
+        self.match_ns += match_ns;
+    }
+}
+
+impl fmt::Display for Latency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} requests, queueing {} ns/op, matching {} ns/op",
+               self.count, self.queue_ns(), self.match_ns())
+    }
+}
+
+impl Matcher {
+    pub fn new(mut me: MatchEngine) -> Matcher {
+        let (tx, reports) = channel::<ExecReport>();
+        me.set_report_sink(Some(Box::new(tx)));
+        Matcher { me, reports, sessions: HashMap::new(),
+                  logons: HashMap::new(), active: HashMap::new(),
+                  owners: HashMap::new(), out: None }
+    }
+    // matcher thread, engine returned once all request senders dropped
+    pub fn spawn(self) -> (Sender<Request>, JoinHandle<MatchEngine>) {
+        let (tx, rx) = channel::<Request>();
+        let handle = thread::spawn(move || self.run(rx));
+        (tx, handle)
+    }
+    pub fn run(mut self, rx: Receiver<Request>) -> MatchEngine {
+        for req in rx.iter() {
+            self.handle(req);
+        }
+        self.me.set_report_sink(None);
+        self.me
+    }
+    // matcher, sequencer and dispatcher threads over command/event rings
+    // of cap slots. sessions' requests put on command ring by sequencer,
+    // engine and latency returned once all request senders dropped
+    pub fn spawn_ring(self, cap: usize)
+    -> (Sender<Request>, JoinHandle<(MatchEngine, Latency)>) {
+        let (tx, rx) = channel::<Request>();
+        let (cmd_tx, cmd_rx) = ring::channel::<Queued>(cap);
+        let (ev_tx, ev_rx) = ring::channel::<Outbound>(cap);
+        thread::spawn(move || sequence(rx, cmd_tx));
+        thread::spawn(move || dispatch(ev_rx));
+        let handle = thread::spawn(move || self.run_ring(cmd_rx, ev_tx));
+        (tx, handle)
+    }
+    // busy polls command ring until producer dropped, no lock on the
+    // matching path. matching latency includes put on event ring
+    pub fn run_ring(mut self, mut rx: Consumer<Queued>,
+                    tx: Producer<Outbound>) -> (MatchEngine, Latency) {
+        self.out = Some(tx);
+        let mut lat = Latency::default();
+        while let Some(Queued { req, mut stamp }) = rx.recv() {
+            stamp.stop();
+            let mut m = Stamp::start("match");
+            self.handle(req);
+            m.stop();
+            lat.add(stamp.as_ns(), m.as_ns());
+        }
+        // event ring closed, dispatcher stops once drained
+        self.out = None;
+        self.me.set_report_sink(None);
+        info!("matcher stopped, {}", lat);
+        (self.me, lat)
+    }
+    fn handle(&mut self, req: Request) {
+        match req {
+            Request::Logon { session, account, sink } => {
+                info!("session {} logon", session);
+                let owner = account.map_or(Owner::Session(session),
+                                           Owner::Account);
+                self.logons.insert(session, owner);
+                self.active.insert(owner, session);
+                self.output(Outbound::Logon { session, sink });
+            },
+            Request::Order { session, cl_ord_id, cmd } => {
+                self.execute(session, cl_ord_id, cmd);
+            },
+            Request::Logout { session } => {
+                info!("session {} logout", session);
+                if let Some(owner) = self.logons.remove(&session) {
+                    if self.active.get(&owner) == Some(&session) {
+                        self.active.remove(&owner);
+                    }
+                }
+                self.output(Outbound::Logout { session });
+            },
+        }
+    }
+    fn execute(&mut self, session: u32, cl_ord_id: u64, cmd: Command) {
+        // cancel/amend orders of own account only
+        let owner = self.owner(session);
+        if let Command::Cancel { oid } | Command::Amend { oid, .. } = cmd {
+            if self.owners.get(&oid).map(|o| o.0) != Some(owner) {
+                let e = EngineError::UnknownOrder;
+                self.reject(session, cl_ord_id, oid, e);
+                return
+            }
+        }
+        let reply = self.me.execute(&cmd);
+        match reply {
+            Reply::NewOrder(Ok(oid)) => {
+                self.owners.insert(oid, (owner, cl_ord_id));
+            },
+            // canceled/replaced report w/ cl_ord_id of request, amend may
+            // re-key the order as new oid
+            Reply::Cancel(Ok(_)) | Reply::Amend(Ok(_)) => {
+                if let Command::Cancel { oid } | Command::Amend { oid, .. } =
+                    cmd {
+                    self.owners.insert(oid, (owner, cl_ord_id));
+                }
+                if let Reply::Amend(Ok(oid)) = reply {
+                    self.owners.insert(oid, (owner, cl_ord_id));
+                }
+            },
+            Reply::Cancel(Err(e)) | Reply::Amend(Err(e)) => {
+                if let Command::Cancel { oid } | Command::Amend { oid, .. } =
+                    cmd {
+                    self.reject(session, cl_ord_id, oid, e);
+                }
+            },
+            _ => {},
+        }
+        self.route(session, cl_ord_id);
+    }
+    fn reject(&mut self, session: u32, cl_ord_id: u64, oid: u64,
+              reason: EngineError) {
+        let sym = self.me.order(oid).map_or(0, |ord| ord.symbol());
+        let rep = ExecReport::reject_request(oid, sym, reason);
+        self.send(session, Event { cl_ord_id, rep });
+    }
+    fn owner(&self, session: u32) -> Owner {
+        self.logons.get(&session).copied().unwrap_or(Owner::Session(session))
+    }
+    // reports of last command, rejected new order to requesting session.
+    // reports of owner logged off dropped
+    fn route(&mut self, session: u32, cl_ord_id: u64) {
+        while let Ok(rep) = self.reports.try_recv() {
+            if rep.oid() == 0 {
+                self.send(session, Event { cl_ord_id, rep });
+                continue
+            }
+            let (owner, cl_ord_id) = match self.owners.get(&rep.oid()) {
+                Some(owner) => *owner,
+                None => continue,
+            };
+            // filled, canceled or expired
+            if rep.leaves_qty() == 0 {
+                self.owners.remove(&rep.oid());
+            }
+            if let Some(&sess) = self.active.get(&owner) {
+                self.send(sess, Event { cl_ord_id, rep });
+            }
+        }
+    }
+    fn send(&mut self, session: u32, ev: Event) {
+        self.output(Outbound::Event { session, ev });
+    }
+    fn output(&mut self, out: Outbound) {
+        match &mut self.out {
+            // dropped once dispatcher gone
+            Some(ring) => { let _ = ring.send(out); },
+            None => deliver(&mut self.sessions, out),
+        }
+    }
+}
+
+// requests of sessions onto command ring, sole producer of ring
+fn sequence(rx: Receiver<Request>, mut tx: Producer<Queued>) {
+    for req in rx.iter() {
+        if tx.send(Queued::new(req)).is_err() { break }
+    }
+}
+
+// delivers outbound of event ring to session sinks until ring closed
+pub fn dispatch(mut rx: Consumer<Outbound>) {
+    let mut sinks = HashMap::<u32, Box<dyn EventSink>>::new();
+    while let Some(out) = rx.recv() {
+        deliver(&mut sinks, out);
+    }
+}
+
+fn deliver(sinks: &mut HashMap<u32, Box<dyn EventSink>>, out: Outbound) {
+    match out {
+        Outbound::Logon { session, sink } => {
+            sinks.insert(session, sink);
+        },
+        Outbound::Event { session, ev } => {
+            if let Some(sink) = sinks.get_mut(&session) {
+                if !sink.event(&ev) {
+                    sinks.remove(&session);
+                }
+            }
+        },
+        Outbound::Logout { session } => {
+            sinks.remove(&session);
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::{channel, Sender};
+    use std::thread;
+    use engine::{MatchEngine, Command, ExecType};
+    use match_base::{OrderType, TimeInForce};
+    use crate::ring;
+    use super::*;
+
+    impl EventSink for Sender<Event> {
+        fn event(&mut self, ev: &Event) -> bool {
+            self.send(*ev).is_ok()
+        }
+    }
+
+    fn order(session: u32, cl_ord_id: u64, buy: bool, qty: u32) -> Queued {
+        let cmd = Command::NewOrder { sym: 1, buy, price: 44000, qty,
+            ord_type: OrderType::Limit, tif: TimeInForce::Day };
+        Queued::new(Request::Order { session, cl_ord_id, cmd })
+    }
+
+    #[test]
+    fn test_ring_matcher() {
+        let mut me = MatchEngine::new();
+        assert!(me.begin_market().is_ok());
+        assert!(me.start_trading().is_ok());
+        let (mut cmd_tx, cmd_rx) = ring::channel::<Queued>(4);
+        let (ev_tx, ev_rx) = ring::channel::<Outbound>(4);
+        let handle = thread::spawn(move || {
+            Matcher::new(me).run_ring(cmd_rx, ev_tx)
+        });
+        let disp = thread::spawn(move || dispatch(ev_rx));
+        let (tx1, rx1) = channel::<Event>();
+        let (tx2, rx2) = channel::<Event>();
+        for (session, sink) in [(1, tx1), (2, tx2)] {
+            let req = Request::Logon { session, account: None,
+                                       sink: Box::new(sink) };
+            assert!(cmd_tx.send(Queued::new(req)).is_ok());
+        }
+        // more requests than ring slots
+        for cl in 1..=10 {
+            assert!(cmd_tx.send(order(1, cl, false, 1)).is_ok());
+        }
+        assert!(cmd_tx.send(order(2, 100, true, 10)).is_ok());
+        let req = Request::Order { session: 2, cl_ord_id: 101,
+                                   cmd: Command::Cancel { oid: 1 } };
+        assert!(cmd_tx.send(Queued::new(req)).is_ok());
+        drop(cmd_tx);
+        let (_, lat) = handle.join().unwrap();
+        disp.join().unwrap();
+        assert_eq!(lat.count(), 14);
+        let evs: Vec<(u64, ExecType)> = rx1.try_iter()
+            .map(|ev| (ev.cl_ord_id(), ev.report().exec_type())).collect();
+        assert_eq!(evs.len(), 20);
+        assert_eq!(evs[..2], [(1, ExecType::New), (2, ExecType::New)]);
+        assert_eq!(evs[10..12], [(1, ExecType::Filled), (2, ExecType::Filled)]);
+        let evs: Vec<(u64, ExecType, u32)> = rx2.try_iter()
+            .map(|ev| { let rep = ev.report();
+                        (ev.cl_ord_id(), rep.exec_type(), rep.cum_qty()) })
+            .collect();
+        assert_eq!(evs.first(), Some(&(100, ExecType::New, 0)));
+        assert_eq!(evs[10], (100, ExecType::Filled, 10));
+        // order of other session
+        assert_eq!(evs[11].1, ExecType::Rejected);
+    }
+}
@@ -0,0 +1,241 @@
+use engine::{MarketData, LevelAction};
+
+// UDP packet of market data, little endian: seq u64 and count u16 of
+// messages, then messages of tag u8 and fields per tag. packet seq from 1,
+// heartbeat w/o message carries next seq
+pub const HEADER_LEN: usize = 10;
+// fits ethernet MTU w/ IP/UDP headers
+pub const MAX_PACKET: usize = 1400;
+// depth snapshot levels per side carried, deeper levels omitted
+pub const MAX_SNAP_LEVELS: usize = 80;
+
+const TAG_LEVEL: u8 = 1;
+const TAG_LEVEL1: u8 = 2;
+const TAG_TRADE: u8 = 3;
+const TAG_SNAPSHOT: u8 = 4;
+
+// market data message of packet, seq of L2 update/snapshot per symbol.
+// L1 side w/o quote as (0, 0)
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum MdMsg {
+    Level {
+        sym:    u32,
+        seq:    u64,
+        buy:    bool,
+        action: LevelAction,
+        price:  i32,
+        qty:    u32,
+    },
+    Level1 {
+        sym:    u32,
+        bid:    (i32, u32),
+        ask:    (i32, u32),
+        last:   i32,
+        last_qty:   u32,
+        volume: u64,
+        turnover:   i64,
+        open:   i32,
+        high:   i32,
+        low:    i32,
+    },
+    Trade { sym: u32, deal_no: u64, buy: bool, price: i32, qty: u32 },
+    Snapshot {
+        sym:    u32,
+        seq:    u64,
+        bids:   Vec<(i32, u32)>,
+        asks:   Vec<(i32, u32)>,
+    },
+}
+
+impl MdMsg {
+    // message of engine feed, None for L3 feed
+    pub fn from_md(md: &MarketData) -> Option<MdMsg> {
+        let msg = match md {
+            MarketData::Level(up) => MdMsg::Level { sym: up.symbol(),
+                seq: up.seq(), buy: up.is_buy(), action: up.action(),
+                price: up.price(), qty: up.qty() },
+            MarketData::Level1(l1) => MdMsg::Level1 { sym: l1.symbol(),
+                bid: l1.bid().unwrap_or((0, 0)),
+                ask: l1.ask().unwrap_or((0, 0)), last: l1.last(),
+                last_qty: l1.last_qty(), volume: l1.volume(),
+                turnover: l1.turnover(), open: l1.open(), high: l1.high(),
+                low: l1.low() },
+            MarketData::Trade(t) => MdMsg::Trade { sym: t.symbol(),
+                deal_no: t.deal_no(), buy: t.is_buy(), price: t.price(),
+                qty: t.qty() },
+            MarketData::Snapshot(snap) => {
+                let n = MAX_SNAP_LEVELS;
+                MdMsg::Snapshot { sym: snap.symbol(), seq: snap.seq(),
+                    bids: snap.bids().iter().take(n).copied().collect(),
+                    asks: snap.asks().iter().take(n).copied().collect() }
+            },
+            _ => return None,
+        };
+        Some(msg)
+    }
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            MdMsg::Level { sym, seq, buy, action, price, qty } => {
+                buf.push(TAG_LEVEL);
+                buf.extend_from_slice(&sym.to_le_bytes());
+                buf.extend_from_slice(&seq.to_le_bytes());
+                buf.push(*buy as u8);
+                buf.push(*action as u8);
+                buf.extend_from_slice(&price.to_le_bytes());
+                buf.extend_from_slice(&qty.to_le_bytes());
+            },
+            MdMsg::Level1 { sym, bid, ask, last, last_qty, volume, turnover,
+                            open, high, low } => {
+                buf.push(TAG_LEVEL1);
+                buf.extend_from_slice(&sym.to_le_bytes());
+                for (price, qty) in [bid, ask] {
+                    buf.extend_from_slice(&price.to_le_bytes());
+                    buf.extend_from_slice(&qty.to_le_bytes());
+                }
+                buf.extend_from_slice(&last.to_le_bytes());
+                buf.extend_from_slice(&last_qty.to_le_bytes());
+                buf.extend_from_slice(&volume.to_le_bytes());
+                buf.extend_from_slice(&turnover.to_le_bytes());
+                buf.extend_from_slice(&open.to_le_bytes());
+                buf.extend_from_slice(&high.to_le_bytes());
+                buf.extend_from_slice(&low.to_le_bytes());
+            },
+            MdMsg::Trade { sym, deal_no, buy, price, qty } => {
+                buf.push(TAG_TRADE);
+                buf.extend_from_slice(&sym.to_le_bytes());
+                buf.extend_from_slice(&deal_no.to_le_bytes());
+                buf.push(*buy as u8);
+                buf.extend_from_slice(&price.to_le_bytes());
+                buf.extend_from_slice(&qty.to_le_bytes());
+            },
+            MdMsg::Snapshot { sym, seq, bids, asks } => {
+                buf.push(TAG_SNAPSHOT);
+                buf.extend_from_slice(&sym.to_le_bytes());
+                buf.extend_from_slice(&seq.to_le_bytes());
+                buf.extend_from_slice(&(bids.len() as u16).to_le_bytes());
+                buf.extend_from_slice(&(asks.len() as u16).to_le_bytes());
+                for (price, qty) in bids.iter().chain(asks.iter()) {
+                    buf.extend_from_slice(&price.to_le_bytes());
+                    buf.extend_from_slice(&qty.to_le_bytes());
+                }
+            },
+        }
+    }
+    // decode one message, returns message and length
+    pub fn decode(buf: &[u8]) -> Option<(MdMsg, usize)> {
+        let mut r = Cursor { buf, off: 1 };
+        let msg = match *buf.first()? {
+            TAG_LEVEL => MdMsg::Level { sym: r.u32()?, seq: r.u64()?,
+                buy: r.u8()? != 0, action: level_action(r.u8()?)?,
+                price: r.u32()? as i32, qty: r.u32()? },
+            TAG_LEVEL1 => MdMsg::Level1 { sym: r.u32()?,
+                bid: (r.u32()? as i32, r.u32()?),
+                ask: (r.u32()? as i32, r.u32()?), last: r.u32()? as i32,
+                last_qty: r.u32()?, volume: r.u64()?,
+                turnover: r.u64()? as i64, open: r.u32()? as i32,
+                high: r.u32()? as i32, low: r.u32()? as i32 },
+            TAG_TRADE => MdMsg::Trade { sym: r.u32()?, deal_no: r.u64()?,
+                buy: r.u8()? != 0, price: r.u32()? as i32, qty: r.u32()? },
+            TAG_SNAPSHOT => {
+                let (sym, seq) = (r.u32()?, r.u64()?);
+                let (nbid, nask) = (r.u16()? as usize, r.u16()? as usize);
+                let mut levels = Vec::<(i32, u32)>::with_capacity(nbid+nask);
+                for _ in 0..nbid + nask {
+                    levels.push((r.u32()? as i32, r.u32()?));
+                }
+                let asks = levels.split_off(nbid);
+                MdMsg::Snapshot { sym, seq, bids: levels, asks }
+            },
+            _ => return None,
+        };
+        Some((msg, r.off))
+    }
+}
+
+fn level_action(v: u8) -> Option<LevelAction> {
+    match v {
+        0 => Some(LevelAction::New),
+        1 => Some(LevelAction::Change),
+        2 => Some(LevelAction::Delete),
+        _ => None,
+    }
+}
+
+struct Cursor<'a> {
+    buf:    &'a [u8],
+    off:    usize,
+}
+
+impl Cursor<'_> {
+    fn take(&mut self, n: usize) -> Option<&[u8]> {
+        let v = self.buf.get(self.off..self.off + n)?;
+        self.off += n;
+        Some(v)
+    }
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+    fn u16(&mut self) -> Option<u16> {
+        Some(u16::from_le_bytes(self.take(2)?.try_into().ok()?))
+    }
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.take(8)?.try_into().ok()?))
+    }
+}
+
+pub fn put_header(buf: &mut [u8], seq: u64, count: u16) {
+    buf[..8].copy_from_slice(&seq.to_le_bytes());
+    buf[8..HEADER_LEN].copy_from_slice(&count.to_le_bytes());
+}
+
+// seq and messages of packet, None for bad packet
+pub fn decode_packet(buf: &[u8]) -> Option<(u64, Vec<MdMsg>)> {
+    let mut r = Cursor { buf, off: 0 };
+    let (seq, count) = (r.u64()?, r.u16()?);
+    let mut msgs = Vec::<MdMsg>::with_capacity(count as usize);
+    let mut off = HEADER_LEN;
+    for _ in 0..count {
+        let (msg, len) = MdMsg::decode(&buf[off..])?;
+        msgs.push(msg);
+        off += len;
+    }
+    if off != buf.len() { return None }
+    Some((seq, msgs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_md_packet() {
+        let msgs = vec![MdMsg::Level { sym: 1, seq: 3, buy: true,
+                            action: LevelAction::Delete, price: -5, qty: 0 },
+            MdMsg::Level1 { sym: 1, bid: (43000, 5), ask: (0, 0),
+                last: 43100, last_qty: 2, volume: 1 << 40, turnover: -7,
+                open: 43000, high: 43100, low: 42900 },
+            MdMsg::Trade { sym: 2, deal_no: 9, buy: false, price: 43000,
+                           qty: 4 },
+            MdMsg::Snapshot { sym: 1, seq: 8, bids: vec![(43000, 5)],
+                              asks: vec![(43100, 1), (43200, 2)] }];
+        let mut buf = vec![0u8; HEADER_LEN];
+        for msg in msgs.iter() {
+            msg.encode(&mut buf);
+        }
+        put_header(&mut buf, 7, msgs.len() as u16);
+        assert_eq!(decode_packet(&buf), Some((7, msgs.clone())));
+        assert!(decode_packet(&buf[..buf.len()-1]).is_none());
+        put_header(&mut buf[..HEADER_LEN], 8, 0);
+        assert_eq!(decode_packet(&buf[..HEADER_LEN]), Some((8, vec![])));
+        // largest snapshot fits packet
+        let levels = vec![(1, 1); MAX_SNAP_LEVELS];
+        let snap = MdMsg::Snapshot { sym: 1, seq: 1, bids: levels.clone(),
+                                     asks: levels };
+        let mut buf = vec![0u8; HEADER_LEN];
+        snap.encode(&mut buf);
+        assert!(buf.len() <= MAX_PACKET);
+    }
+}
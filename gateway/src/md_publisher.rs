@@ -0,0 +1,280 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+use log::{info, warn};
+use engine::MarketData;
+use crate::md_packet::{MdMsg, put_header, HEADER_LEN, MAX_PACKET};
+
+// heartbeat packet if idle for
+const HEARTBEAT: Duration = Duration::from_secs(1);
+// packets served per retransmission request
+pub const MAX_RETRANS: u64 = 1000;
+
+// sent packets kept for retransmission, oldest dropped beyond capacity
+pub struct PacketWindow {
+    first:  u64,    // seq of front packet
+    packets: VecDeque<Vec<u8>>,
+    cap:    usize,
+}
+
+// sequenced UDP packets of engine market data feed, messages of one
+// engine command batched while packet not full
+pub struct MdPublisher {
+    sock:   UdpSocket,
+    dest:   SocketAddr,
+    seq:    u64,    // seq of next packet
+    buf:    Vec<u8>,
+    count:  u16,
+    window: Arc<Mutex<PacketWindow>>,
+}
+
+impl PacketWindow {
+    pub fn new(cap: usize) -> PacketWindow {
+        PacketWindow { first: 1, packets: VecDeque::with_capacity(cap),
+                       cap: cap.max(1) }
+    }
+    fn push(&mut self, pkt: Vec<u8>) {
+        if self.packets.len() >= self.cap {
+            self.packets.pop_front();
+            self.first += 1;
+        }
+        self.packets.push_back(pkt);
+    }
+    // packets of seq begin..=end still kept, w/ seq of first returned
+    pub fn range(&self, begin: u64, end: u64) -> (u64, Vec<Vec<u8>>) {
+        let begin = begin.max(self.first);
+        let last = self.first + self.packets.len() as u64;
+        let end = end.min(last - 1).min(begin.saturating_add(MAX_RETRANS - 1));
+        if begin > end { return (begin, vec![]) }
+        let from = (begin - self.first) as usize;
+        let to = (end - self.first) as usize;
+        (begin, self.packets.range(from..=to).cloned().collect())
+    }
+}
+
+impl MdPublisher {
+    pub fn new(sock: UdpSocket, dest: SocketAddr, window: usize)
+    -> MdPublisher {
+        let mut buf = Vec::<u8>::with_capacity(MAX_PACKET);
+        buf.resize(HEADER_LEN, 0);
+        MdPublisher { sock, dest, seq: 1, buf, count: 0,
+                      window: Arc::new(Mutex::new(PacketWindow::new(window))) }
+    }
+    pub fn window(&self) -> Arc<Mutex<PacketWindow>> {
+        self.window.clone()
+    }
+    // publish until engine feed closed, heartbeat while idle
+    pub fn run(mut self, rx: Receiver<MarketData>) {
+        loop {
+            match rx.recv_timeout(HEARTBEAT) {
+                Ok(md) => self.publish(&md),
+                Err(RecvTimeoutError::Timeout) => {
+                    self.heartbeat();
+                    continue
+                },
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+            while let Ok(md) = rx.try_recv() {
+                self.publish(&md);
+            }
+            self.flush();
+        }
+        self.flush();
+        info!("md publisher stopped at seq {}", self.seq);
+    }
+    // add to packet, packet sent once full
+    pub fn publish(&mut self, md: &MarketData) {
+        let msg = match MdMsg::from_md(md) {
+            Some(msg) => msg,
+            None => return,
+        };
+        let len = self.buf.len();
+        msg.encode(&mut self.buf);
+        if self.buf.len() > MAX_PACKET {
+            let tail = self.buf.split_off(len);
+            self.flush();
+            self.buf.extend_from_slice(&tail);
+        }
+        self.count += 1;
+    }
+    pub fn flush(&mut self) {
+        if self.count == 0 { return }
+        put_header(&mut self.buf, self.seq, self.count);
+        if let Err(e) = self.sock.send_to(&self.buf, self.dest) {
+            warn!("md packet {} send failed: {}", self.seq, e);
+        }
+        self.window.lock().unwrap().push(self.buf.clone());
+        self.seq += 1;
+        self.buf.truncate(HEADER_LEN);
+        self.count = 0;
+    }
+    // seq of next packet, receiver detects loss of last packets
+    fn heartbeat(&mut self) {
+        let mut hb = [0u8; HEADER_LEN];
+        put_header(&mut hb, self.seq, 0);
+        let _ = self.sock.send_to(&hb, self.dest);
+    }
+}
+
+// retransmission of packets by seq range. request begin u64, end u64
+// inclusive, reply seq of first u64, count u32 then count packets of
+// len u16 and packet
+pub fn serve_retrans(listener: TcpListener, window: Arc<Mutex<PacketWindow>>) {
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!("accept failed: {}", e);
+                continue
+            },
+        };
+        let window = window.clone();
+        thread::spawn(move || {
+            if let Err(e) = retrans_session(stream, &window) {
+                warn!("retransmission session closed: {}", e);
+            }
+        });
+    }
+}
+
+fn retrans_session(mut stream: TcpStream, window: &Mutex<PacketWindow>)
+-> io::Result<()> {
+    let mut req = [0u8; 16];
+    loop {
+        match stream.read_exact(&mut req) {
+            Ok(()) => {},
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                return Ok(())
+            },
+            Err(e) => return Err(e),
+        }
+        let begin = u64::from_le_bytes(req[..8].try_into().unwrap());
+        let end = u64::from_le_bytes(req[8..].try_into().unwrap());
+        let (first, packets) = window.lock().unwrap().range(begin, end);
+        let mut buf = Vec::<u8>::with_capacity(12 + packets.len() * 256);
+        buf.extend_from_slice(&first.to_le_bytes());
+        buf.extend_from_slice(&(packets.len() as u32).to_le_bytes());
+        for pkt in packets.iter() {
+            buf.extend_from_slice(&(pkt.len() as u16).to_le_bytes());
+            buf.extend_from_slice(pkt);
+        }
+        stream.write_all(&buf)?;
+    }
+}
+
+// packets begin..=end of retransmission service w/ seq of first,
+// packets no longer kept skipped
+pub fn retransmit<A: ToSocketAddrs>(addr: A, begin: u64, end: u64)
+-> io::Result<(u64, Vec<Vec<u8>>)> {
+    let mut s = TcpStream::connect(addr)?;
+    let mut req = [0u8; 16];
+    req[..8].copy_from_slice(&begin.to_le_bytes());
+    req[8..].copy_from_slice(&end.to_le_bytes());
+    s.write_all(&req)?;
+    let mut hdr = [0u8; 12];
+    s.read_exact(&mut hdr)?;
+    let first = u64::from_le_bytes(hdr[..8].try_into().unwrap());
+    let count = u32::from_le_bytes(hdr[8..].try_into().unwrap());
+    let mut packets = Vec::<Vec<u8>>::with_capacity(count as usize);
+    for _ in 0..count {
+        let mut len = [0u8; 2];
+        s.read_exact(&mut len)?;
+        let mut pkt = vec![0u8; u16::from_le_bytes(len) as usize];
+        s.read_exact(&mut pkt)?;
+        packets.push(pkt);
+    }
+    Ok((first, packets))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{TcpListener, UdpSocket};
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+    use engine::{MatchEngine, MarketData, LevelAction};
+    use crate::md_packet::{MdMsg, decode_packet};
+    use super::*;
+
+    #[test]
+    fn test_md_publisher() {
+        let mut win = PacketWindow::new(2);
+        for seq in 1..=3u8 {
+            win.push(vec![seq]);
+        }
+        assert_eq!(win.range(1, 9), (2, vec![vec![2], vec![3]]));
+        assert_eq!(win.range(3, 3), (3, vec![vec![3]]));
+        assert_eq!(win.range(4, 9).1.len(), 0);
+        let rcv = UdpSocket::bind("127.0.0.1:0").unwrap();
+        rcv.set_read_timeout(Some(Duration::from_millis(200))).unwrap();
+        let sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let publ = MdPublisher::new(sock, rcv.local_addr().unwrap(), 1024);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let window = publ.window();
+        thread::spawn(move || serve_retrans(listener, window));
+        let mut me = MatchEngine::new();
+        let (tx, rx) = mpsc::channel::<MarketData>();
+        me.set_md_sink(Some(Box::new(tx)));
+        let handle = thread::spawn(move || publ.run(rx));
+        assert!(me.begin_market().is_ok());
+        assert!(me.start_trading().is_ok());
+        assert!(me.send_order(1, true, 43000, 10).is_ok());
+        assert!(me.send_order(1, true, 43000, 5).is_ok());
+        assert!(me.send_order(1, false, 43100, 7).is_ok());
+        assert!(me.send_order(1, false, 42900, 12).is_ok());
+        // feed closed, publisher flushed
+        drop(me);
+        handle.join().unwrap();
+        let mut packets = Vec::<Vec<u8>>::new();
+        let mut buf = [0u8; 2048];
+        while let Ok(n) = rcv.recv(&mut buf) {
+            // heartbeat w/o message
+            if n > HEADER_LEN {
+                packets.push(buf[..n].to_vec());
+            }
+        }
+        let mut msgs = Vec::<MdMsg>::new();
+        for (i, pkt) in packets.iter().enumerate() {
+            let (seq, mut m) = decode_packet(pkt).unwrap();
+            assert_eq!(seq, i as u64 + 1);
+            msgs.append(&mut m);
+        }
+        let levels: Vec<(u64, LevelAction, u32)> = msgs.iter()
+            .filter_map(|msg| match msg {
+                MdMsg::Level { seq, action, qty, .. } => {
+                    Some((*seq, *action, *qty))
+                },
+                _ => None,
+            }).collect();
+        assert_eq!(levels, vec![(1, LevelAction::New, 10),
+                                (2, LevelAction::Change, 15),
+                                (3, LevelAction::New, 7),
+                                (4, LevelAction::Change, 3)]);
+        let trades: Vec<(bool, i32, u32)> = msgs.iter()
+            .filter_map(|msg| match msg {
+                MdMsg::Trade { buy, price, qty, .. } => {
+                    Some((*buy, *price, *qty))
+                },
+                _ => None,
+            }).collect();
+        assert_eq!(trades, vec![(false, 43000, 10), (false, 43000, 2)]);
+        match msgs.last() {
+            Some(MdMsg::Level1 { bid, ask, last, volume, .. }) => {
+                assert_eq!((*bid, *ask, *last, *volume),
+                           ((43000, 3), (43100, 7), 43000, 12));
+            },
+            msg => panic!("L1 expected, got {:?}", msg),
+        }
+        // gap filled by retransmission
+        let n = packets.len() as u64;
+        assert_eq!(retransmit(addr, 1, n).unwrap(), (1, packets.clone()));
+        assert_eq!(retransmit(addr, n, n + 5).unwrap(),
+                   (n, vec![packets[n as usize - 1].clone()]));
+        assert_eq!(retransmit(addr, n + 1, n + 5).unwrap().1.len(), 0);
+    }
+}
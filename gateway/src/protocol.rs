@@ -0,0 +1,322 @@
+use std::io::{self, Read};
+use match_base::{OrderType, TimeInForce, EngineError};
+use engine::ExecType;
+
+// fixed length binary messages, little endian: tag u8, seq u32 and fixed
+// fields per tag. seq per direction of session, from 1 at logon
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Msg {
+    Logon { account: u32, hb_secs: u16 },
+    LogonAck { next_seq: u32, hb_secs: u16 },   // next inbound seq expected
+    Logout,
+    Heartbeat,
+    NewOrder {
+        cl_ord_id:  u64,
+        sym:    u32,
+        buy:    bool,
+        price:  i32,
+        qty:    u32,
+        ord_type:   OrderType,
+        tif:    TimeInForce,
+    },
+    Cancel { cl_ord_id: u64, oid: u64 },
+    Amend { cl_ord_id: u64, oid: u64, price: i32, qty: u32 },
+    // session level reject of inbound message ref_seq
+    Reject { ref_seq: u32, reason: SessionReject },
+    // reason is reject_code of EngineError, 0 if none
+    ExecReport {
+        cl_ord_id:  u64,
+        oid:    u64,
+        sym:    u32,
+        exec_type:  ExecType,
+        reason: u8,
+        cum_qty:    u32,
+        leaves_qty: u32,
+        last_price: i32,
+        last_qty:   u32,
+    },
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[repr(u8)]
+pub enum SessionReject {
+    NotLoggedOn=1,
+    BadSeq,         // seq gap, session closed
+    BadMessage,
+    Unsupported,    // message not for inbound
+}
+
+const TAG_LOGON: u8 = 1;
+const TAG_LOGON_ACK: u8 = 2;
+const TAG_LOGOUT: u8 = 3;
+const TAG_HEARTBEAT: u8 = 4;
+const TAG_NEW_ORDER: u8 = 5;
+const TAG_CANCEL: u8 = 6;
+const TAG_AMEND: u8 = 7;
+const TAG_REJECT: u8 = 8;
+const TAG_EXEC_REPORT: u8 = 9;
+// largest message
+pub const MAX_MSG_LEN: usize = 43;
+
+impl SessionReject {
+    pub const fn from_u8(v: u8) -> Option<SessionReject> {
+        match v {
+            1 => Some(SessionReject::NotLoggedOn),
+            2 => Some(SessionReject::BadSeq),
+            3 => Some(SessionReject::BadMessage),
+            4 => Some(SessionReject::Unsupported),
+            _ => None,
+        }
+    }
+}
+
+// wire code of engine error, reject reasons of symbol from 32
+pub const fn reject_code(e: EngineError) -> u8 {
+    use EngineError::*;
+    match e {
+        InvalidState => 1,
+        UnknownOrder => 2,
+        OrderFilled => 3,
+        OrderCanceled => 4,
+        InvalidQty => 5,
+        PoolFull => 6,
+        JournalFail => 7,
+        NotFillable => 8,
+        UnknownSymbol => 9,
+        DuplicateSymbol => 10,
+        SymbolsFull => 11,
+        InvalidLimits => 12,
+        DuplicateOrder => 13,
+        DealPoolFull => 14,
+        NoBook => 15,
+        UncrossShort => 16,
+        IoFail => 17,
+        BadData => 18,
+        InvalidRules => 19,
+        Rejected(reason) => 32 + reason as u8,
+    }
+}
+
+// message length of tag, None for unknown tag
+pub fn msg_len(tag: u8) -> Option<usize> {
+    let rlen = match tag {
+        TAG_LOGON | TAG_LOGON_ACK => 11,
+        TAG_LOGOUT | TAG_HEARTBEAT => 5,
+        TAG_NEW_ORDER => 28,
+        TAG_CANCEL => 21,
+        TAG_AMEND => 29,
+        TAG_REJECT => 10,
+        TAG_EXEC_REPORT => 43,
+        _ => return None,
+    };
+    Some(rlen)
+}
+
+pub fn encode(seq: u32, msg: &Msg, buf: &mut Vec<u8>) {
+    let tag = match *msg {
+        Msg::Logon { .. } => TAG_LOGON,
+        Msg::LogonAck { .. } => TAG_LOGON_ACK,
+        Msg::Logout => TAG_LOGOUT,
+        Msg::Heartbeat => TAG_HEARTBEAT,
+        Msg::NewOrder { .. } => TAG_NEW_ORDER,
+        Msg::Cancel { .. } => TAG_CANCEL,
+        Msg::Amend { .. } => TAG_AMEND,
+        Msg::Reject { .. } => TAG_REJECT,
+        Msg::ExecReport { .. } => TAG_EXEC_REPORT,
+    };
+    buf.push(tag);
+    buf.extend_from_slice(&seq.to_le_bytes());
+    match *msg {
+        Msg::Logon { account, hb_secs } => {
+            buf.extend_from_slice(&account.to_le_bytes());
+            buf.extend_from_slice(&hb_secs.to_le_bytes());
+        },
+        Msg::LogonAck { next_seq, hb_secs } => {
+            buf.extend_from_slice(&next_seq.to_le_bytes());
+            buf.extend_from_slice(&hb_secs.to_le_bytes());
+        },
+        Msg::Logout | Msg::Heartbeat => {},
+        Msg::NewOrder { cl_ord_id, sym, buy, price, qty, ord_type, tif } => {
+            buf.extend_from_slice(&cl_ord_id.to_le_bytes());
+            buf.extend_from_slice(&sym.to_le_bytes());
+            buf.push(buy as u8);
+            buf.extend_from_slice(&price.to_le_bytes());
+            buf.extend_from_slice(&qty.to_le_bytes());
+            buf.push(ord_type as u8);
+            buf.push(tif as u8);
+        },
+        Msg::Cancel { cl_ord_id, oid } => {
+            buf.extend_from_slice(&cl_ord_id.to_le_bytes());
+            buf.extend_from_slice(&oid.to_le_bytes());
+        },
+        Msg::Amend { cl_ord_id, oid, price, qty } => {
+            buf.extend_from_slice(&cl_ord_id.to_le_bytes());
+            buf.extend_from_slice(&oid.to_le_bytes());
+            buf.extend_from_slice(&price.to_le_bytes());
+            buf.extend_from_slice(&qty.to_le_bytes());
+        },
+        Msg::Reject { ref_seq, reason } => {
+            buf.extend_from_slice(&ref_seq.to_le_bytes());
+            buf.push(reason as u8);
+        },
+        Msg::ExecReport { cl_ord_id, oid, sym, exec_type, reason, cum_qty,
+                          leaves_qty, last_price, last_qty } => {
+            buf.extend_from_slice(&cl_ord_id.to_le_bytes());
+            buf.extend_from_slice(&oid.to_le_bytes());
+            buf.extend_from_slice(&sym.to_le_bytes());
+            buf.push(exec_type as u8);
+            buf.push(reason);
+            buf.extend_from_slice(&cum_qty.to_le_bytes());
+            buf.extend_from_slice(&leaves_qty.to_le_bytes());
+            buf.extend_from_slice(&last_price.to_le_bytes());
+            buf.extend_from_slice(&last_qty.to_le_bytes());
+        },
+    }
+}
+
+#[inline]
+fn get_u16(buf: &[u8], off: usize) -> u16 {
+    u16::from_le_bytes(buf[off..off+2].try_into().unwrap())
+}
+
+#[inline]
+fn get_u32(buf: &[u8], off: usize) -> u32 {
+    u32::from_le_bytes(buf[off..off+4].try_into().unwrap())
+}
+
+#[inline]
+fn get_u64(buf: &[u8], off: usize) -> u64 {
+    u64::from_le_bytes(buf[off..off+8].try_into().unwrap())
+}
+
+// decode one message, returns seq, message and length
+// None for truncated or bad message
+pub fn decode(buf: &[u8]) -> Option<(u32, Msg, usize)> {
+    let tag = *buf.first()?;
+    let rlen = msg_len(tag)?;
+    if buf.len() < rlen { return None }
+    let msg = match tag {
+        TAG_LOGON => Msg::Logon {
+            account: get_u32(buf, 5),
+            hb_secs: get_u16(buf, 9),
+        },
+        TAG_LOGON_ACK => Msg::LogonAck {
+            next_seq: get_u32(buf, 5),
+            hb_secs: get_u16(buf, 9),
+        },
+        TAG_LOGOUT => Msg::Logout,
+        TAG_HEARTBEAT => Msg::Heartbeat,
+        TAG_NEW_ORDER => Msg::NewOrder {
+            cl_ord_id: get_u64(buf, 5),
+            sym: get_u32(buf, 13),
+            buy: buf[17] != 0,
+            price: get_u32(buf, 18) as i32,
+            qty: get_u32(buf, 22),
+            ord_type: OrderType::from_u8(buf[26])?,
+            tif: TimeInForce::from_u8(buf[27])?,
+        },
+        TAG_CANCEL => Msg::Cancel {
+            cl_ord_id: get_u64(buf, 5),
+            oid: get_u64(buf, 13),
+        },
+        TAG_AMEND => Msg::Amend {
+            cl_ord_id: get_u64(buf, 5),
+            oid: get_u64(buf, 13),
+            price: get_u32(buf, 21) as i32,
+            qty: get_u32(buf, 25),
+        },
+        TAG_REJECT => Msg::Reject {
+            ref_seq: get_u32(buf, 5),
+            reason: SessionReject::from_u8(buf[9])?,
+        },
+        _ => Msg::ExecReport {
+            cl_ord_id: get_u64(buf, 5),
+            oid: get_u64(buf, 13),
+            sym: get_u32(buf, 21),
+            exec_type: ExecType::from_u8(buf[25])?,
+            reason: buf[26],
+            cum_qty: get_u32(buf, 27),
+            leaves_qty: get_u32(buf, 31),
+            last_price: get_u32(buf, 35) as i32,
+            last_qty: get_u32(buf, 39),
+        },
+    };
+    Some((get_u32(buf, 1), msg, rlen))
+}
+
+// frames of stream, partial message kept across read timeout
+pub struct FrameReader<R: Read> {
+    r:      R,
+    buf:    Vec<u8>,
+}
+
+impl<R: Read> FrameReader<R> {
+    pub fn new(r: R) -> FrameReader<R> {
+        FrameReader { r, buf: Vec::<u8>::with_capacity(MAX_MSG_LEN * 4) }
+    }
+    // next message, Ok(None) on orderly close, InvalidData for bad message
+    pub fn read_msg(&mut self) -> io::Result<Option<(u32, Msg)>> {
+        loop {
+            if let Some(&tag) = self.buf.first() {
+                let rlen = msg_len(tag).ok_or_else(|| bad_msg("bad tag"))?;
+                if self.buf.len() >= rlen {
+                    let (seq, msg, _) = decode(&self.buf)
+                                    .ok_or_else(|| bad_msg("bad fields"))?;
+                    self.buf.drain(..rlen);
+                    return Ok(Some((seq, msg)))
+                }
+            }
+            let mut tmp = [0u8; MAX_MSG_LEN * 4];
+            let n = self.r.read(&mut tmp)?;
+            if n == 0 { return Ok(None) }
+            self.buf.extend_from_slice(&tmp[..n]);
+        }
+    }
+}
+
+fn bad_msg(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use match_base::Reject;
+    use super::*;
+
+    #[test]
+    fn test_protocol() {
+        let msgs = vec![Msg::Logon { account: 7, hb_secs: 30 },
+            Msg::LogonAck { next_seq: 2, hb_secs: 30 },
+            Msg::Logout, Msg::Heartbeat,
+            Msg::NewOrder { cl_ord_id: 1 << 40, sym: 1, buy: true,
+                price: -100, qty: 5, ord_type: OrderType::Market,
+                tif: TimeInForce::FOK },
+            Msg::Cancel { cl_ord_id: 2, oid: 3 },
+            Msg::Amend { cl_ord_id: 3, oid: 3, price: 43000, qty: 7 },
+            Msg::Reject { ref_seq: 9, reason: SessionReject::BadSeq },
+            Msg::ExecReport { cl_ord_id: 1, oid: 2, sym: 1,
+                exec_type: ExecType::PartialFill, reason: 0, cum_qty: 4,
+                leaves_qty: 6, last_price: 44000, last_qty: 4 }];
+        let mut buf = Vec::<u8>::new();
+        let mut stream = Vec::<u8>::new();
+        for (seq, msg) in msgs.iter().enumerate() {
+            buf.clear();
+            encode(seq as u32 + 1, msg, &mut buf);
+            assert_eq!(msg_len(buf[0]), Some(buf.len()));
+            assert!(buf.len() <= MAX_MSG_LEN);
+            assert_eq!(decode(&buf), Some((seq as u32 + 1, *msg, buf.len())));
+            assert!(decode(&buf[..buf.len()-1]).is_none());
+            stream.extend_from_slice(&buf);
+        }
+        // frames of byte stream
+        let mut rdr = FrameReader::new(stream.as_slice());
+        for (seq, msg) in msgs.iter().enumerate() {
+            assert_eq!(rdr.read_msg().unwrap(), Some((seq as u32 + 1, *msg)));
+        }
+        assert!(rdr.read_msg().unwrap().is_none());
+        let mut rdr = FrameReader::new(&[0xffu8, 0, 0, 0, 0][..]);
+        assert!(rdr.read_msg().is_err());
+        assert_eq!(reject_code(EngineError::UnknownOrder), 2);
+        assert_eq!(reject_code(EngineError::Rejected(Reject::QtyLot)), 36);
+    }
+}
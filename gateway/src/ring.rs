@@ -0,0 +1,203 @@
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+
+// spins before yielding while ring empty/full
+const SPINS: u32 = 100;
+
+// cache line of producer/consumer cursor, no false sharing
+#[repr(align(64))]
+struct Padded<T>(T);
+
+// bounded lock-free ring of one producer and one consumer, disruptor
+// style. cursors are free running seqs, slot of seq at seq & mask
+struct Ring<T> {
+    head:   Padded<AtomicUsize>,    // next seq to pop, by consumer
+    tail:   Padded<AtomicUsize>,    // next seq to push, by producer
+    closed: AtomicBool,     // producer or consumer dropped
+    mask:   usize,
+    slots:  Box<[UnsafeCell<MaybeUninit<T>>]>,
+}
+
+// slots of seq head..tail owned by consumer, others by producer
+unsafe impl<T: Send> Sync for Ring<T> {}
+unsafe impl<T: Send> Send for Ring<T> {}
+
+pub struct Producer<T> {
+    ring:   Arc<Ring<T>>,
+    tail:   usize,
+    head:   usize,      // last seen head of consumer
+}
+
+pub struct Consumer<T> {
+    ring:   Arc<Ring<T>>,
+    head:   usize,
+    tail:   usize,      // last seen tail of producer
+}
+
+// ring of at least cap slots, rounded up to power of 2
+pub fn channel<T: Send>(cap: usize) -> (Producer<T>, Consumer<T>) {
+    let cap = cap.max(1).next_power_of_two();
+    let slots = (0..cap).map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+                    .collect();
+    let ring = Arc::new(Ring { head: Padded(AtomicUsize::new(0)),
+                               tail: Padded(AtomicUsize::new(0)),
+                               closed: AtomicBool::new(false),
+                               mask: cap - 1, slots });
+    (Producer { ring: ring.clone(), tail: 0, head: 0 },
+     Consumer { ring, head: 0, tail: 0 })
+}
+
+impl<T> Ring<T> {
+    fn capacity(&self) -> usize {
+        self.mask + 1
+    }
+}
+
+impl<T> Drop for Ring<T> {
+    fn drop(&mut self) {
+        let tail = *self.tail.0.get_mut();
+        let mut head = *self.head.0.get_mut();
+        while head != tail {
+            let slot = self.slots[head & self.mask].get_mut();
+            unsafe { slot.assume_init_drop() }
+            head = head.wrapping_add(1);
+        }
+    }
+}
+
+impl<T> Producer<T> {
+    pub fn capacity(&self) -> usize {
+        self.ring.capacity()
+    }
+    // true once consumer dropped
+    pub fn is_closed(&self) -> bool {
+        self.ring.closed.load(Ordering::Acquire)
+    }
+    // push w/o wait, value returned if ring full
+    pub fn push(&mut self, v: T) -> Result<(), T> {
+        let ring = &*self.ring;
+        if self.tail.wrapping_sub(self.head) == ring.capacity() {
+            self.head = ring.head.0.load(Ordering::Acquire);
+            if self.tail.wrapping_sub(self.head) == ring.capacity() {
+                return Err(v)
+            }
+        }
+        unsafe { (*ring.slots[self.tail & ring.mask].get()).write(v); }
+        self.tail = self.tail.wrapping_add(1);
+        ring.tail.0.store(self.tail, Ordering::Release);
+        Ok(())
+    }
+    // push, spin while full. value returned if consumer dropped
+    pub fn send(&mut self, mut v: T) -> Result<(), T> {
+        let mut spins = 0u32;
+        loop {
+            if self.is_closed() { return Err(v) }
+            v = match self.push(v) {
+                Ok(()) => return Ok(()),
+                Err(v) => v,
+            };
+            backoff(&mut spins);
+        }
+    }
+}
+
+impl<T> Drop for Producer<T> {
+    fn drop(&mut self) {
+        self.ring.closed.store(true, Ordering::Release);
+    }
+}
+
+impl<T> Consumer<T> {
+    pub fn capacity(&self) -> usize {
+        self.ring.capacity()
+    }
+    // pop w/o wait, None if ring empty
+    pub fn pop(&mut self) -> Option<T> {
+        let ring = &*self.ring;
+        if self.head == self.tail {
+            self.tail = ring.tail.0.load(Ordering::Acquire);
+            if self.head == self.tail { return None }
+        }
+        let v = unsafe { (*ring.slots[self.head & ring.mask].get())
+                            .assume_init_read() };
+        self.head = self.head.wrapping_add(1);
+        ring.head.0.store(self.head, Ordering::Release);
+        Some(v)
+    }
+    // pop, spin while empty. None once producer dropped and ring drained
+    pub fn recv(&mut self) -> Option<T> {
+        let mut spins = 0u32;
+        loop {
+            // closed checked first, no push after producer dropped
+            let closed = self.ring.closed.load(Ordering::Acquire);
+            if let Some(v) = self.pop() { return Some(v) }
+            if closed { return None }
+            backoff(&mut spins);
+        }
+    }
+}
+
+impl<T> Drop for Consumer<T> {
+    fn drop(&mut self) {
+        self.ring.closed.store(true, Ordering::Release);
+    }
+}
+
+fn backoff(spins: &mut u32) {
+    if *spins < SPINS {
+        *spins += 1;
+        std::hint::spin_loop();
+    } else {
+        thread::yield_now();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+    use super::channel;
+
+    #[test]
+    fn test_ring() {
+        let (mut tx, mut rx) = channel::<u64>(3);
+        assert_eq!(tx.capacity(), 4);
+        assert_eq!(rx.pop(), None);
+        for v in 0..4 {
+            assert!(tx.push(v).is_ok());
+        }
+        assert_eq!(tx.push(4), Err(4));
+        assert_eq!(rx.pop(), Some(0));
+        assert!(tx.push(4).is_ok());
+        // in order across threads, wrapping ring many times
+        let n = 100_000u64;
+        let handle = thread::spawn(move || {
+            for v in 5..n {
+                assert!(tx.send(v).is_ok());
+            }
+        });
+        for v in 1..n {
+            assert_eq!(rx.recv(), Some(v));
+        }
+        assert_eq!(rx.recv(), None);
+        handle.join().unwrap();
+        // consumer gone
+        let (mut tx, rx) = channel::<u64>(2);
+        drop(rx);
+        assert!(tx.is_closed());
+        assert_eq!(tx.send(1), Err(1));
+        // values left in ring dropped
+        let v = Arc::new(0);
+        let (mut tx, mut rx) = channel::<Arc<i32>>(4);
+        for _ in 0..3 {
+            assert!(tx.push(v.clone()).is_ok());
+        }
+        drop(rx.pop());
+        drop(tx);
+        drop(rx);
+        assert_eq!(Arc::strong_count(&v), 1);
+    }
+}
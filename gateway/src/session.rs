@@ -0,0 +1,278 @@
+use std::io::{self, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Duration;
+use log::{info, warn};
+use engine::Command;
+use crate::matcher::{Request, Event, EventSink, next_session};
+use crate::protocol::{Msg, SessionReject, FrameReader, encode, reject_code,
+                      MAX_MSG_LEN};
+
+// logon expected within
+const LOGON_TIMEOUT: Duration = Duration::from_secs(10);
+
+impl EventSink for Sender<Msg> {
+    fn event(&mut self, ev: &Event) -> bool {
+        let rep = ev.report();
+        let msg = Msg::ExecReport { cl_ord_id: ev.cl_ord_id(),
+            oid: rep.oid(), sym: rep.symbol(), exec_type: rep.exec_type(),
+            reason: rep.reason().map_or(0, reject_code),
+            cum_qty: rep.cum_qty(), leaves_qty: rep.leaves_qty(),
+            last_price: rep.last_price(), last_qty: rep.last_qty() };
+        self.send(msg).is_ok()
+    }
+}
+
+// accept binary sessions, each session in own thread
+pub fn serve(listener: TcpListener, matcher: Sender<Request>) {
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!("accept failed: {}", e);
+                continue
+            },
+        };
+        let matcher = matcher.clone();
+        let session = next_session();
+        thread::spawn(move || {
+            if let Err(e) = run_session(stream, session, &matcher) {
+                warn!("session {} closed: {}", session, e);
+            }
+        });
+    }
+}
+
+// outbound messages in sequence, heartbeat if idle for hb
+fn write_loop(mut stream: TcpStream, rx: Receiver<Msg>, hb: Duration) {
+    let mut seq: u32 = 0;
+    let mut buf = Vec::<u8>::with_capacity(MAX_MSG_LEN);
+    loop {
+        let msg = match rx.recv_timeout(hb) {
+            Ok(msg) => msg,
+            Err(RecvTimeoutError::Timeout) => Msg::Heartbeat,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        seq += 1;
+        buf.clear();
+        encode(seq, &msg, &mut buf);
+        if stream.write_all(&buf).is_err() { break }
+        if msg == Msg::Logout {
+            let _ = stream.shutdown(Shutdown::Write);
+            break
+        }
+    }
+}
+
+// logon with seq 1 first, inbound seq checked for gap. session closed
+// after two heartbeat intervals w/o inbound message
+pub fn run_session(stream: TcpStream, session: u32,
+                   matcher: &Sender<Request>) -> io::Result<()> {
+    let mut rdr = FrameReader::new(stream.try_clone()?);
+    stream.set_read_timeout(Some(LOGON_TIMEOUT))?;
+    let (account, hb_secs) = match rdr.read_msg()? {
+        Some((1, Msg::Logon { account, hb_secs })) => {
+            info!("session {} account {} logon from {}", session, account,
+                  stream.peer_addr()?);
+            (account, hb_secs.max(1))
+        },
+        Some((seq, _)) => {
+            let mut buf = Vec::<u8>::with_capacity(MAX_MSG_LEN);
+            encode(1, &Msg::Reject { ref_seq: seq,
+                                     reason: SessionReject::NotLoggedOn },
+                   &mut buf);
+            return (&stream).write_all(&buf)
+        },
+        None => return Ok(()),
+    };
+    let hb = Duration::from_secs(hb_secs as u64);
+    let (tx, rx) = channel::<Msg>();
+    let w = stream.try_clone()?;
+    let writer = thread::spawn(move || write_loop(w, rx, hb));
+    let _ = tx.send(Msg::LogonAck { next_seq: 2, hb_secs });
+    // orders of account kept across reconnects
+    let _ = matcher.send(Request::Logon { session, account: Some(account),
+                                         sink: Box::new(tx.clone()) });
+    stream.set_read_timeout(Some(hb))?;
+    let mut expect: u32 = 2;
+    let mut idle = 0;
+    loop {
+        let (seq, msg) = match rdr.read_msg() {
+            Ok(Some(frame)) => frame,
+            Ok(None) => break,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock ||
+                      e.kind() == io::ErrorKind::TimedOut => {
+                idle += 1;
+                if idle < 2 { continue }
+                warn!("session {} heartbeat timeout", session);
+                let _ = tx.send(Msg::Logout);
+                break
+            },
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                warn!("session {} bad message: {}", session, e);
+                let _ = tx.send(Msg::Reject { ref_seq: expect,
+                                    reason: SessionReject::BadMessage });
+                let _ = tx.send(Msg::Logout);
+                break
+            },
+            Err(e) => {
+                warn!("session {} read failed: {}", session, e);
+                break
+            },
+        };
+        idle = 0;
+        if seq != expect {
+            warn!("session {} seq {} expected {}", session, seq, expect);
+            let _ = tx.send(Msg::Reject { ref_seq: seq,
+                                          reason: SessionReject::BadSeq });
+            let _ = tx.send(Msg::Logout);
+            break
+        }
+        expect += 1;
+        let (cl_ord_id, cmd) = match msg {
+            Msg::Heartbeat => continue,
+            Msg::Logout => {
+                let _ = tx.send(Msg::Logout);
+                break
+            },
+            Msg::NewOrder { cl_ord_id, sym, buy, price, qty, ord_type,
+                            tif } => {
+                (cl_ord_id, Command::NewOrder { sym, buy, price, qty,
+                                                ord_type, tif })
+            },
+            Msg::Cancel { cl_ord_id, oid } => {
+                (cl_ord_id, Command::Cancel { oid })
+            },
+            Msg::Amend { cl_ord_id, oid, price, qty } => {
+                (cl_ord_id, Command::Amend { oid, price, qty })
+            },
+            _ => {
+                let _ = tx.send(Msg::Reject { ref_seq: seq,
+                                    reason: SessionReject::Unsupported });
+                continue
+            },
+        };
+        if matcher.send(Request::Order { session, cl_ord_id, cmd })
+            .is_err() {
+            break
+        }
+    }
+    let _ = matcher.send(Request::Logout { session });
+    drop(tx);
+    let _ = writer.join();
+    info!("session {} closed", session);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+    use std::time::Duration;
+    use engine::{MatchEngine, ExecType, EngineError};
+    use match_base::{OrderType, TimeInForce};
+    use crate::matcher::Matcher;
+    use crate::protocol::{Msg, SessionReject, FrameReader, encode,
+                          reject_code};
+    use super::serve;
+
+    struct Client {
+        s:      TcpStream,
+        rdr:    FrameReader<TcpStream>,
+        seq:    u32,
+    }
+
+    impl Client {
+        fn connect(addr: &str) -> Client {
+            let s = TcpStream::connect(addr).unwrap();
+            s.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            let rdr = FrameReader::new(s.try_clone().unwrap());
+            Client { s, rdr, seq: 0 }
+        }
+        fn send(&mut self, msg: Msg) {
+            use std::io::Write;
+            self.seq += 1;
+            let mut buf = Vec::<u8>::new();
+            encode(self.seq, &msg, &mut buf);
+            self.s.write_all(&buf).unwrap();
+        }
+        fn recv(&mut self) -> Msg {
+            self.rdr.read_msg().unwrap().unwrap().1
+        }
+        fn order(&mut self, cl_ord_id: u64, buy: bool, price: i32, qty: u32) {
+            self.send(Msg::NewOrder { cl_ord_id, sym: 1, buy, price, qty,
+                ord_type: OrderType::Limit, tif: TimeInForce::Day });
+        }
+        // (cl_ord_id, oid, exec type, reason, cum qty, leaves qty)
+        fn report(&mut self) -> (u64, u64, ExecType, u8, u32, u32) {
+            match self.recv() {
+                Msg::ExecReport { cl_ord_id, oid, exec_type, reason,
+                                  cum_qty, leaves_qty, .. } => {
+                    (cl_ord_id, oid, exec_type, reason, cum_qty, leaves_qty)
+                },
+                msg => panic!("exec report expected, got {:?}", msg),
+            }
+        }
+    }
+
+    #[test]
+    fn test_gateway() {
+        let mut me = MatchEngine::new();
+        assert!(me.begin_market().is_ok());
+        assert!(me.start_trading().is_ok());
+        let (matcher, _) = Matcher::new(me).spawn();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || serve(listener, matcher));
+        // order before logon
+        let mut c0 = Client::connect(&addr);
+        c0.send(Msg::Heartbeat);
+        assert_eq!(c0.recv(), Msg::Reject { ref_seq: 1,
+                                    reason: SessionReject::NotLoggedOn });
+        let mut c1 = Client::connect(&addr);
+        c1.send(Msg::Logon { account: 1, hb_secs: 1 });
+        assert_eq!(c1.recv(), Msg::LogonAck { next_seq: 2, hb_secs: 1 });
+        let mut c2 = Client::connect(&addr);
+        c2.send(Msg::Logon { account: 2, hb_secs: 30 });
+        assert_eq!(c2.recv(), Msg::LogonAck { next_seq: 2, hb_secs: 30 });
+        c1.order(100, false, 44000, 10);
+        assert_eq!(c1.report(), (100, 1, ExecType::New, 0, 0, 10));
+        // fill routed to both sessions
+        c2.order(200, true, 44000, 4);
+        assert_eq!(c2.report(), (200, 2, ExecType::New, 0, 0, 4));
+        assert_eq!(c2.report(), (200, 2, ExecType::Filled, 0, 4, 0));
+        assert_eq!(c1.report(), (100, 1, ExecType::PartialFill, 0, 4, 6));
+        // order of other session
+        c2.send(Msg::Cancel { cl_ord_id: 201, oid: 1 });
+        let code = reject_code(EngineError::UnknownOrder);
+        assert_eq!(c2.report(), (201, 1, ExecType::Rejected, code, 0, 0));
+        c1.send(Msg::Amend { cl_ord_id: 101, oid: 1, price: 44000, qty: 8 });
+        assert_eq!(c1.report(), (101, 1, ExecType::Replaced, 0, 4, 4));
+        c1.send(Msg::Cancel { cl_ord_id: 102, oid: 1 });
+        assert_eq!(c1.report(), (102, 1, ExecType::Canceled, 0, 4, 0));
+        c1.send(Msg::Cancel { cl_ord_id: 103, oid: 1 });
+        assert_eq!(c1.report().2, ExecType::Rejected);
+        c1.order(104, false, 44500, 3);
+        assert_eq!(c1.report(), (104, 3, ExecType::New, 0, 0, 3));
+        // idle session gets heartbeat
+        thread::sleep(Duration::from_millis(1200));
+        assert_eq!(c1.recv(), Msg::Heartbeat);
+        c1.send(Msg::Logout);
+        assert_eq!(c1.recv(), Msg::Logout);
+        assert!(c1.rdr.read_msg().unwrap().is_none());
+        // order of account canceled after reconnect
+        let mut c3 = Client::connect(&addr);
+        c3.send(Msg::Logon { account: 1, hb_secs: 30 });
+        assert_eq!(c3.recv(), Msg::LogonAck { next_seq: 2, hb_secs: 30 });
+        c3.send(Msg::Cancel { cl_ord_id: 300, oid: 3 });
+        assert_eq!(c3.report(), (300, 3, ExecType::Canceled, 0, 0, 0));
+        // seq gap closes session
+        c2.seq += 1;
+        c2.send(Msg::Heartbeat);
+        assert_eq!(c2.recv(), Msg::Reject { ref_seq: 5,
+                                            reason: SessionReject::BadSeq });
+        assert_eq!(c2.recv(), Msg::Logout);
+        assert!(c2.rdr.read_msg().unwrap().is_none());
+    }
+}
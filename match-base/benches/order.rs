@@ -25,9 +25,9 @@ fn or_book(bench: &mut Bencher) {
 #[allow(dead_code)]
 fn or_pool_book(bench: &mut Bencher) {
     let mut or_maps = BTreeMap::<OidPrice, u64>::new();
-    let pool = OrderPool::new();
+    let mut pool = OrderPool::new();
     let mut rng = rand::thread_rng();
-    OrderPool::reserve(8_000_000);
+    pool.reserve(8_000_000);
     bench.iter(|| {
         let price = rng.gen::<i32>();
         let mut qty: u32 = rng.gen::<u32>();
@@ -42,9 +42,9 @@ fn or_pool_book(bench: &mut Bencher) {
 #[allow(dead_code)]
 fn or_pool_bookkey(bench: &mut Bencher) {
     let mut or_maps = BTreeMap::<OidPrice, OrderKey>::new();
-    let pool = OrderPool::new();
+    let mut pool = OrderPool::new();
     let mut rng = rand::thread_rng();
-    OrderPool::reserve(8_000_000);
+    pool.reserve(8_000_000);
     bench.iter(|| {
         let price = rng.gen::<i32>();
         let mut qty: u32 = rng.gen::<u32>();
@@ -1,11 +1,13 @@
 use {
     std::fmt,
-    std::sync::{Once, atomic},
-    log::{info, warn}
+    std::time::{SystemTime, UNIX_EPOCH},
+    log::{info, warn},
+    crate::{Order, EngineError},
 };
 
 // mid ... match id u32 as well
 // oid ... order id u32
+// contra ... counterparty order id, paired deals share same mid
 #[derive(Eq, Clone, Default)]
 #[non_exhaustive]
 pub struct Deal {
@@ -14,50 +16,56 @@ pub struct Deal {
     oid:    u32,
     price:  i32,
     qty:    u32,
+    sym_idx: u32,
+    contra: u32,
+    buy:    bool,
+    liquidity: Liquidity,
+    ts:     u64,    // ns since epoch
 }
 
-pub struct DealPool ();
-
-const MAX_DEALS: u32 = 30_000_000;
-static INIT: Once = Once::new();
-static mut DEAL_POOL: Vec<Deal> = Vec::new();
-static mut DEAL_NO: u64 = 0;
-static mut MATCH_NO: u32 = 0;
-static mut POOL_LOCK: atomic::AtomicBool = atomic::AtomicBool::new(false);
-//static mut DEAL_POOL: &mut [Deal] = &mut [];
-//unsafe { DEAL_POOL = std::slice::from_raw_parts_mut( data: *mut Order, len: usize) }
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+#[repr(u8)]
+pub enum Liquidity {
+    #[default]
+    Maker=0,    // passive, resting order
+    Taker,      // aggressor
+    Auction,    // call auction uncross
+}
 
-// init orders db
-fn init_deals() {
-    unsafe {
-        DEAL_POOL = Vec::<Deal>::with_capacity(2048);
-    }
+// deals db, deal no is base_no + index + 1
+pub struct DealPool {
+    deals:  Vec<Deal>,
+    match_no: u32,
+    base_no: u64,   // deal no before restore
+    limit:  usize,  // max deals kept
 }
 
-pub fn clear_deals() {
-    INIT.call_once(|| {
-        init_deals();
-    });
-    unsafe {
-        while POOL_LOCK.swap(true, atomic::Ordering::Acquire) {
-            std::thread::yield_now();
-        }
-        DEAL_NO = 0;
-        MATCH_NO = 0;
-        DEAL_POOL.clear();
-        POOL_LOCK.store(false, atomic::Ordering::Release);
+const MAX_DEALS: u32 = 30_000_000;
+
+fn now_ns() -> u64 {
+    if let Ok(d) = SystemTime::now().duration_since(UNIX_EPOCH) {
+        d.as_nanos() as u64
+    } else {
+        0
     }
 }
 
-
 impl Deal {
     pub const fn new(no: u64, mid: u32, oid: u32, price: i32, qty: u32)
     -> Deal {
-        Deal {no, mid, oid, price, qty}
+        Deal {no, mid, oid, price, qty, sym_idx: 0, contra: 0, buy: false,
+              liquidity: Liquidity::Maker, ts: 0}
+    }
+    pub const fn with_side(self, sym_idx: u32, buy: bool, contra: u32,
+                           liquidity: Liquidity) -> Deal {
+        Deal {sym_idx, buy, contra, liquidity, ..self}
     }
     pub fn no(&self) -> u64 {
         self.no
     }
+    pub fn mid(&self) -> u32 {
+        self.mid
+    }
     pub fn oid(&self) -> u32 {
         self.oid
     }
@@ -67,64 +75,75 @@ impl Deal {
     pub fn qty(&self) -> u32 {
         self.qty
     }
+    pub fn symbol(&self) -> u32 {
+        self.sym_idx
+    }
+    pub fn is_buy(&self) -> bool {
+        self.buy
+    }
+    pub fn contra(&self) -> u32 {
+        self.contra
+    }
+    pub fn liquidity(&self) -> Liquidity {
+        self.liquidity
+    }
+    pub fn ts(&self) -> u64 {
+        self.ts
+    }
 }
 
 impl DealPool {
     pub fn new() -> DealPool {
-        INIT.call_once(|| {
-            init_deals();
-        });
-        DealPool()
+        DealPool { deals: Vec::<Deal>::with_capacity(2048), match_no: 0,
+                   base_no: 0, limit: MAX_DEALS as usize }
     }
-    pub fn clear(&mut self) {
-        clear_deals();
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit.min(MAX_DEALS as usize);
     }
-    pub fn reserve(siz: usize) {
-        unsafe {
-                while POOL_LOCK.swap(true, atomic::Ordering::Acquire) {
-                    std::thread::yield_now();
-                }
-            DEAL_POOL.reserve(siz);
-            POOL_LOCK.store(false, atomic::Ordering::Release);
-        }
+    // room for n more deals
+    pub fn has_room(&self, n: usize) -> bool {
+        self.deals.len() + n <= self.limit
     }
-    pub fn new_match() {
-        unsafe {
-            MATCH_NO += 1;
-        }
-    }
-    pub fn push_deal(&self, oid: u32, price: i32, qty: u32) -> bool {
-        let  v_len: usize;
-        unsafe {
-            v_len = DEAL_POOL.len();
-        }
-        if v_len >= MAX_DEALS as usize {
-            false
+    pub fn clear(&mut self) {
+        self.deals.clear();
+        self.match_no = 0;
+        self.base_no = 0;
+    }
+    // continue deal/match no from snapshot, previous deals dropped
+    pub fn restore(&mut self, deal_no: u64, match_no: u32) {
+        self.deals.clear();
+        self.base_no = deal_no;
+        self.match_no = match_no;
+    }
+    pub fn reserve(&mut self, siz: usize) {
+        self.deals.reserve(siz);
+    }
+    pub fn new_match(&mut self) {
+        self.match_no += 1;
+    }
+    // last deal no
+    pub fn deal_no(&self) -> u64 {
+        self.base_no + self.deals.len() as u64
+    }
+    pub fn match_no(&self) -> u32 {
+        self.match_no
+    }
+    // push deal for order filled against contra order
+    pub fn push_deal(&mut self, ord: &Order, contra: u32, liquidity: Liquidity,
+                     price: i32, qty: u32) -> Result<(), EngineError> {
+        if !self.has_room(1) {
+            Err(EngineError::DealPoolFull)
         } else {
-            unsafe {
-                while POOL_LOCK.swap(true, atomic::Ordering::Acquire) {
-                    std::thread::yield_now();
-                }
-                let mid = MATCH_NO;
-                let v_len = DEAL_POOL.len() as u64;
-                DEAL_POOL.push(Deal::new(v_len+1, mid, oid, price, qty));
-                DEAL_NO = DEAL_POOL.len() as u64;
-                POOL_LOCK.store(false, atomic::Ordering::Release);
-            }
-            true
+            let deal = Deal::new(self.deal_no() + 1, self.match_no,
+                                 ord.oid() as u32, price, qty)
+                .with_side(ord.symbol(), ord.is_buy(), contra, liquidity);
+            self.deals.push(Deal { ts: now_ns(), ..deal });
+            Ok(())
         }
     }
-    pub fn get(&self, idx: u64) -> Option<&'static Deal> {
-        let  v_len: u64;
-        unsafe {
-            v_len = DEAL_NO;
-        }
-        if idx == 0 || idx > v_len { return None }
-        let ret: &'static Deal;
-        unsafe {
-            ret = &DEAL_POOL[idx as usize - 1];
-        }
-        Some(ret)
+    pub fn get(&self, idx: u64) -> Option<&Deal> {
+        if idx <= self.base_no { return None }
+        self.deals.get((idx - self.base_no) as usize - 1)
     }
     pub fn eq(&self, v2: &Vec<Deal>) -> bool {
         for adeal in v2 {
@@ -144,35 +163,73 @@ impl DealPool {
     }
 }
 
+impl Default for DealPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// timestamp not compared
 impl PartialEq for Deal {
     fn eq(&self, rhs: &Self) -> bool {
         self.no == rhs.no && self.oid == rhs.oid && self.mid == rhs.mid &&
-            self.price == rhs.price && self.qty == rhs.qty
+            self.price == rhs.price && self.qty == rhs.qty &&
+            self.sym_idx == rhs.sym_idx && self.buy == rhs.buy &&
+            self.contra == rhs.contra && self.liquidity == rhs.liquidity
     }
 }
 
 impl fmt::Display for Deal {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "No: {} Oid({}): qty({}) filled @{}", self.no, 
-               self.oid, self.qty, self.price)
+        write!(f, "No: {} Oid({}): {} qty({}) filled @{} contra({}) {:?}",
+               self.no, self.oid, if self.buy { "buy" } else { "sell" },
+               self.qty, self.price, self.contra, self.liquidity)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Deal, DealPool};
+    use super::{Deal, DealPool, Liquidity};
+    use crate::Order;
 
     #[test]
     fn test_dealv() {
-        let deals = DealPool::new();
-        let deals1 = vec![Deal::new(1, 0, 4, 43500, 45),
-                        Deal::new(2, 0, 8, 43500, 45),
-                        Deal::new(3, 0, 4, 43500, 5),
+        let mut deals = DealPool::new();
+        let deals1 = vec![Deal::new(1, 0, 4, 43500, 45)
+                            .with_side(1, true, 8, Liquidity::Maker),
+                        Deal::new(2, 0, 8, 43500, 45)
+                            .with_side(1, false, 4, Liquidity::Taker),
+                        Deal::new(3, 0, 4, 43500, 5)
+                            .with_side(1, true, 9, Liquidity::Auction),
                         Deal::new(0, 0, 0, 0, 0) ];
         for de in &deals1 {
             if de.no() == 0 { break }
-            deals.push_deal(de.oid(), de.price(), de.qty());
+            let ord = Order::new(de.oid() as u64, de.symbol(), de.is_buy(),
+                                 de.price(), 50);
+            assert!(deals.push_deal(&ord, de.contra(), de.liquidity(),
+                                    de.price(), de.qty()).is_ok());
         }
         assert!(deals.eq(&deals1));
+        let deal = deals.get(2).unwrap();
+        assert_eq!(deal.contra(), 4);
+        assert!(!deal.is_buy());
+        assert!(deal.ts() > 0);
+        assert!(*deal != Deal::new(2, 0, 8, 43500, 45));
+        assert_eq!(deals.deal_no(), 3);
+        deals.restore(10, 5);
+        assert!(deals.get(3).is_none());
+        let ord = Order::new(1, 1, true, 43500, 50);
+        assert!(deals.push_deal(&ord, 2, Liquidity::Maker, 43500, 5).is_ok());
+        assert_eq!(deals.deal_no(), 11);
+        assert_eq!(deals.get(11).unwrap().no(), 11);
+        assert_eq!(deals.match_no(), 5);
+        deals.set_limit(2);
+        assert!(deals.has_room(1));
+        assert!(!deals.has_room(2));
+        assert!(deals.push_deal(&ord, 2, Liquidity::Maker, 43500, 5).is_ok());
+        assert_eq!(deals.push_deal(&ord, 2, Liquidity::Maker, 43500, 5),
+                   Err(crate::EngineError::DealPoolFull));
+        deals.clear();
+        assert!(deals.get(1).is_none());
     }
 }
@@ -0,0 +1,55 @@
+use std::fmt;
+use crate::Reject;
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum EngineError {
+    InvalidState,
+    UnknownOrder,
+    OrderFilled,
+    OrderCanceled,
+    InvalidQty,
+    PoolFull,
+    JournalFail,
+    NotFillable,
+    Rejected(Reject),
+    UnknownSymbol,
+    DuplicateSymbol,
+    SymbolsFull,
+    InvalidLimits,
+    DuplicateOrder,
+    DealPoolFull,
+    NoBook,
+    UncrossShort,   // book can't fill uncross qty
+    IoFail,
+    BadData,
+    InvalidRules,
+}
+
+use EngineError::*;
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            InvalidState => write!(f, "invalid market state"),
+            UnknownOrder => write!(f, "unknown order"),
+            OrderFilled => write!(f, "order already filled"),
+            OrderCanceled => write!(f, "order already canceled"),
+            InvalidQty => write!(f, "invalid order quantity"),
+            PoolFull => write!(f, "order pool full"),
+            JournalFail => write!(f, "journal write failed"),
+            NotFillable => write!(f, "order not fully fillable"),
+            Rejected(reason) => write!(f, "order rejected: {}", reason),
+            UnknownSymbol => write!(f, "unknown symbol"),
+            DuplicateSymbol => write!(f, "symbol already exists"),
+            SymbolsFull => write!(f, "too many symbols"),
+            InvalidLimits => write!(f, "invalid price limits"),
+            DuplicateOrder => write!(f, "order already exists"),
+            DealPoolFull => write!(f, "deal pool full"),
+            NoBook => write!(f, "orderbook not found"),
+            UncrossShort => write!(f, "uncross qty not filled"),
+            IoFail => write!(f, "file read failed"),
+            BadData => write!(f, "bad file data"),
+            InvalidRules => write!(f, "invalid auction price rules"),
+        }
+    }
+}
@@ -1,7 +1,9 @@
 mod order;
 mod symbol;
 mod deal;
+mod error;
 
-pub use order::{Order, OrderKey, OrderPool, OidPrice};
-pub use symbol::{Symbol, Symbols};
-pub use deal::{Deal, DealPool};
+pub use order::{Order, OrderKey, OrderPool, OidPrice, OrderType, TimeInForce};
+pub use symbol::{Symbol, Symbols, Reject};
+pub use deal::{Deal, DealPool, Liquidity};
+pub use error::EngineError;
@@ -1,7 +1,7 @@
 use std::cmp::Ordering;
 use std::fmt;
-use std::sync::{Once, atomic};
 use log::warn;
+use crate::EngineError;
 #[cfg(not(target_os = "android"))]
 use tcmalloc::TCMalloc;
 
@@ -23,9 +23,31 @@ pub struct Order {
     buy:   bool,
     canceled:  bool,
     price_filled:i32,
+    ord_type:   OrderType,
+    tif:    TimeInForce,
 }
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+#[repr(u8)]
+pub enum OrderType {
+    #[default]
+    Limit=0,
+    Market,
+}
+
+// Day orders expire on market stop, GTC keep in orderBook
+// IOC cancel remain qty, FOK fill all or reject
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+#[repr(u8)]
+pub enum TimeInForce {
+    #[default]
+    Day=0,
+    GTC,
+    IOC,
+    FOK,
+}
+
+#[derive(PartialEq, Clone, Copy)]
 pub struct OrderKey(u32);
 
 // for use for orderBook order, bid high as best while ask low as best
@@ -36,44 +58,32 @@ pub struct OidPrice {
     price:  i32,
 }
 
-pub struct OrderPool ();
+// orders db, indexed by OrderKey for fast lookup
+pub struct OrderPool {
+    orders: Vec<Order>,
+}
 
 const MAX_ORDERS: u32 = 60_000_000;
-static INIT: Once = Once::new();
-static mut ORDER_POOL: Vec<Order> = Vec::new();
-static mut ORDER_NO: u64 = 0;
-static mut POOL_LOCK: atomic::AtomicBool = atomic::AtomicBool::new(false);
-//static mut ORDER_POOL: &mut [Order] = &mut [];
-//unsafe { ORDER_POOL = std::slice::from_raw_parts_mut( data: *mut Order, len: usize) }
 
-// init orders db
-fn init_orders() {
-    unsafe {
-        ORDER_POOL = Vec::<Order>::with_capacity(2048);
-    }
-}
-
-pub fn clear_orders() {
-    INIT.call_once(|| {
-        init_orders();
-    });
-    unsafe {
-        while POOL_LOCK.swap(true, atomic::Ordering::Acquire) {
-            std::thread::yield_now();
+impl OrderType {
+    pub const fn from_u8(v: u8) -> Option<OrderType> {
+        match v {
+            0 => Some(OrderType::Limit),
+            1 => Some(OrderType::Market),
+            _ => None,
         }
-        ORDER_NO = 0;
-        ORDER_POOL.clear();
-        POOL_LOCK.store(false, atomic::Ordering::Release);
     }
 }
 
-fn reserve_orders(siz: usize) {
-    unsafe {
-        while POOL_LOCK.swap(true, atomic::Ordering::Acquire) {
-            std::thread::yield_now();
+impl TimeInForce {
+    pub const fn from_u8(v: u8) -> Option<TimeInForce> {
+        match v {
+            0 => Some(TimeInForce::Day),
+            1 => Some(TimeInForce::GTC),
+            2 => Some(TimeInForce::IOC),
+            3 => Some(TimeInForce::FOK),
+            _ => None,
         }
-        ORDER_POOL.reserve(siz);
-        POOL_LOCK.store(false, atomic::Ordering::Release);
     }
 }
 
@@ -146,6 +156,31 @@ impl Order {
     pub fn qty(&self) -> u32 {
         self.qty
     }
+    pub fn filled(&self) -> u32 {
+        self.filled
+    }
+    // last fill price
+    pub fn price_filled(&self) -> i32 {
+        self.price_filled
+    }
+    pub fn ord_type(&self) -> OrderType {
+        self.ord_type
+    }
+    pub fn tif(&self) -> TimeInForce {
+        self.tif
+    }
+    pub fn is_market(&self) -> bool {
+        self.ord_type == OrderType::Market
+    }
+    // IOC/FOK/Market never rest in orderBook
+    pub fn is_immediate(&self) -> bool {
+        self.is_market() || self.tif == TimeInForce::IOC ||
+            self.tif == TimeInForce::FOK
+    }
+    pub fn set_type(&mut self, ord_type: OrderType, tif: TimeInForce) {
+        self.ord_type = ord_type;
+        self.tif = tif;
+    }
     pub fn fill(&mut self, vol: u32, price: i32) -> bool {
         if self.canceled || self.id == 0 {
             return false
@@ -162,6 +197,14 @@ impl Order {
     pub fn cancel(&mut self) {
         self.canceled = true
     }
+    // reduce order qty, keep priority
+    pub fn reduce_qty(&mut self, qty: u32) -> bool {
+        if self.canceled || qty <= self.filled || qty > self.qty {
+            return false
+        }
+        self.qty = qty;
+        true
+    }
 }
 
 impl PartialEq for Order {
@@ -170,6 +213,17 @@ impl PartialEq for Order {
     }
 }
 
+impl OidPrice {
+    // orderBook key of order id at price
+    pub fn new(buy: bool, price: i32, id: u32) -> OidPrice {
+        if buy {
+            OidPrice{id, price: - price}
+        } else {
+            OidPrice{id, price}
+        }
+    }
+}
+
 impl PartialEq for OidPrice {
     fn eq(&self, other: &Self) -> bool {
         self.id == other.id && self.price == other.price
@@ -218,78 +272,78 @@ impl OrderKey {
     pub fn key(&self) -> u32 {
         self.0
     }
-    pub fn get_mut(&self) -> Option<&'static mut Order> {
-        let  v_len: usize;
-        unsafe {
-            v_len = ORDER_NO as usize;
-        }
-        if self.0 == 0 || self.0 as usize > v_len {
-            None
+}
+
+impl OrderPool {
+    pub fn new() -> OrderPool {
+        OrderPool { orders: Vec::<Order>::with_capacity(2048) }
+    }
+    pub fn clear(&mut self) {
+        self.orders.clear();
+    }
+    pub fn reserve(&mut self, siz: usize) {
+        self.orders.reserve(siz);
+    }
+    pub fn len(&self) -> usize {
+        self.orders.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.orders.is_empty()
+    }
+    pub fn new_order(&mut self, sym_idx: u32, buy: bool, price: i32, qty: u32)
+    -> Result<&mut Order, EngineError> {
+        let v_len = self.orders.len();
+        if v_len >= MAX_ORDERS as usize {
+            Err(EngineError::PoolFull)
         } else {
-            let id = self.0 - 1;
-            let ret: &'static mut Order;
-            unsafe {
-                ret = &mut ORDER_POOL[id as usize];
-            }
-            Some(ret)
+            let oid = v_len as Oid + 1;
+            self.orders.push(Order::new(oid, sym_idx, buy, price, qty));
+            Ok(&mut self.orders[v_len])
         }
     }
-    pub fn get(&self) -> Option<&'static Order> {
-        let  v_len: usize;
-        unsafe {
-            v_len = ORDER_NO as usize;
+    // restore order from snapshot, oids not restored kept as canceled
+    pub fn restore_order(&mut self, ord: Order) -> Result<(), EngineError> {
+        let oid = ord.oid();
+        if oid > MAX_ORDERS as Oid {
+            return Err(EngineError::PoolFull)
+        }
+        if oid as usize <= self.orders.len() {
+            return Err(EngineError::DuplicateOrder)
+        }
+        self.pad_to(oid as usize - 1);
+        self.orders.push(ord);
+        Ok(())
+    }
+    pub fn pad_to(&mut self, siz: usize) {
+        while self.orders.len() < siz {
+            let mut ord = Order::new(self.orders.len() as Oid + 1, 0, false,
+                                     0, 0);
+            ord.cancel();
+            self.orders.push(ord);
         }
-        if self.0 == 0 || self.0 as usize > v_len {
+    }
+    pub fn get(&self, okey: &OrderKey) -> Option<&Order> {
+        if okey.0 == 0 {
             None
         } else {
-            let id = self.0 - 1;
-            let ret: &'static Order;
-            unsafe {
-                ret = &ORDER_POOL[id as usize];
-            }
-            Some(ret)
+            self.orders.get(okey.0 as usize - 1)
         }
     }
-}
-
-impl OrderPool {
-    pub fn new() -> OrderPool {
-        INIT.call_once(|| {
-            init_orders();
-        });
-        OrderPool()
-    }
-    pub fn clear(&self) {
-        clear_orders();
-    }
-    pub fn reserve(siz: usize) {
-        reserve_orders(siz);
-    }
-    pub fn new_order(&self, sym_idx: u32, buy: bool, price: i32, qty: u32)
-    -> Option<&'static mut Order> {
-        let  v_len: usize;
-        unsafe {
-            v_len = ORDER_NO as usize;
-        }
-        if v_len >= MAX_ORDERS as usize {
+    pub fn get_mut(&mut self, okey: &OrderKey) -> Option<&mut Order> {
+        if okey.0 == 0 {
             None
         } else {
-            let res: &'static mut Order;
-            unsafe {
-                while POOL_LOCK.swap(true, atomic::Ordering::Acquire) {
-                    std::thread::yield_now();
-                }
-                let v_len = ORDER_POOL.len() as u64;
-                ORDER_POOL.push(Order::new(v_len+1, sym_idx, buy, price, qty));
-                ORDER_NO = ORDER_POOL.len() as u64;
-                POOL_LOCK.store(false, atomic::Ordering::Release);
-                res = &mut ORDER_POOL[v_len as usize];
-            }
-            Some(res)
+            self.orders.get_mut(okey.0 as usize - 1)
         }
     }
 }
 
+impl Default for OrderPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::Oid;
@@ -297,6 +351,8 @@ mod tests {
     use super::Order;
     use super::OrderKey;
     use super::OrderPool;
+    use super::{OrderType, TimeInForce};
+    use crate::EngineError;
     use std::collections::BTreeMap;
     use std::cmp::Ordering;
     use std::mem;
@@ -337,9 +393,26 @@ mod tests {
         assert_eq!(or4.remain_qty(), 70);
         assert!(or4.fill(30, 12500));
         assert_eq!(or4.remain_qty(), 40);
+        assert_eq!(or4.filled(), 30);
+        assert_eq!(or4.price_filled(), 12500);
+        assert!(!or4.reduce_qty(80));
+        assert!(!or4.reduce_qty(30));
+        assert!(or4.reduce_qty(50));
+        assert_eq!(or4.remain_qty(), 20);
         or4.cancel();
+        assert!(!or4.reduce_qty(40));
         assert!(or4.is_canceled());
         assert_eq!(or4.remain_qty(), 0);
+        assert_eq!(or1.ord_type(), OrderType::Limit);
+        assert_eq!(or1.tif(), TimeInForce::Day);
+        assert!(!or1.is_immediate());
+        let mut or5=Order::new(5, 1, false, 0, 20);
+        or5.set_type(OrderType::Market, TimeInForce::GTC);
+        assert!(or5.is_market());
+        assert!(or5.is_immediate());
+        or5.set_type(OrderType::Limit, TimeInForce::IOC);
+        assert!(!or5.is_market());
+        assert!(or5.is_immediate());
         let okey: OrderKey = Default::default();
         assert!(okey.is_null());
         assert_eq!(okey.key(), 0);
@@ -405,24 +478,46 @@ mod tests {
 
     #[test]
     fn test_orderpool() {
-        let pool = OrderPool::new();
+        let mut pool = OrderPool::new();
         let or1=pool.new_order(1, true, 10000, 100).unwrap();
         let oid1 = or1.oid();
-        let ret = or1.key().get();
+        let okey = or1.key();
+        let ret = pool.get(&okey);
         assert!(ret != None);
         assert!(ret.unwrap().oid() == oid1);
         println!("oid: {}", oid1);
         let or1=pool.new_order(1, true, 10000, 100).unwrap();
         let oid2 = or1.oid();
-        let ret = or1.key().get();
+        let okey = or1.key();
+        let ret = pool.get(&okey);
         assert!(ret != None);
         assert!(ret.unwrap().oid() == oid2);
         println!("oid: {}", oid2);
+        assert_eq!((oid1, oid2), (1, 2));
+        assert!(pool.get(&OrderKey::default()).is_none());
+        assert!(pool.get(&OrderKey::from(3)).is_none());
+        // pools are independent
+        let mut pool2 = OrderPool::new();
+        assert_eq!(pool2.new_order(2, false, 10000, 10).unwrap().oid(), 1);
+        assert_eq!(pool.len(), 2);
+        assert_eq!(pool2.len(), 1);
+        pool.clear();
+        assert!(pool.is_empty());
+        assert_eq!(pool2.len(), 1);
+        // restore with gaps
+        assert!(pool.restore_order(Order::new(3, 1, true, 10000, 100)).is_ok());
+        assert_eq!(pool.restore_order(Order::new(2, 1, true, 10000, 100)),
+                   Err(EngineError::DuplicateOrder));
+        assert_eq!(pool.len(), 3);
+        assert!(pool.get(&OrderKey::from(2)).unwrap().is_canceled());
+        assert_eq!(pool.get(&OrderKey::from(3)).unwrap().remain_qty(), 100);
+        pool.pad_to(5);
+        assert_eq!(pool.new_order(1, true, 10000, 10).unwrap().oid(), 6);
     }
 
     #[test]
     fn test_orderpool_btree() {
-        let pool = OrderPool::new();
+        let mut pool = OrderPool::new();
         let mut or_maps = BTreeMap::<OidPrice, OrderKey>::new();
         let or1=pool.new_order(1, true, 10000, 100).unwrap();
         let oid1 = or1.oid();
@@ -439,16 +534,16 @@ mod tests {
         assert_eq!(or_maps.len(), 3);
         let mut it = or_maps.iter_mut();
         let (_, oid) = it.next().unwrap();
-        let ord = oid.get().unwrap();
+        let ord = pool.get(oid).unwrap();
         assert_eq!(ord.oid(), oid2);
         assert_eq!(ord.qty(), 50);
         let (_, oid) = it.next().unwrap();
-        let ord = oid.get().unwrap();
+        let ord = pool.get(oid).unwrap();
         assert_eq!(ord.oid(), oid1);
         let op1 = ord.to_OidPrice();
         assert_eq!(ord.qty(), 100);
         let (_, oid) = it.next().unwrap();
-        let ord = oid.get_mut().unwrap();
+        let ord = pool.get_mut(oid).unwrap();
         assert_eq!(ord.oid(), oid3);
         assert_eq!(ord.qty(), 30);
         assert!(ord.fill(10, 10000));
@@ -457,16 +552,16 @@ mod tests {
         assert!(or_maps.remove(&op1) != None);
         let mut it = or_maps.iter();
         let (_, oid) = it.next().unwrap();
-        let ord = oid.get().unwrap();
+        let ord = pool.get(oid).unwrap();
         assert_eq!(ord.oid(), oid2);
         let (_, oid) = it.next().unwrap();
-        let ord = oid.get().unwrap();
+        let ord = pool.get(oid).unwrap();
         assert!(! ord.is_filled() );
         assert_eq!(ord.oid(), oid3);
         assert_eq!(ord.remain_qty(), 20);
 
         for (_, oid) in or_maps.iter() {
-            let ord = oid.get().unwrap();
+            let ord = pool.get(oid).unwrap();
             println!("{}: {}", ord.oid(), ord)
         }
     }
@@ -498,8 +593,8 @@ mod tests {
     #[test]
     #[ignore]
     fn bench_orderpool() {
-        let pool = OrderPool::new();
-        OrderPool::reserve(2_000_000);
+        let mut pool = OrderPool::new();
+        pool.reserve(2_000_000);
         let mut or_maps = BTreeMap::<OidPrice, OrderKey>::new();
         let mut rng = rand::thread_rng();
         let mut measure = Measure::start("orderbook bench");
@@ -526,7 +621,7 @@ mod tests {
         let mut keys = Vec::<OidPrice>::new(); 
         {
             for key in or_maps.values() {
-                keys.push(key.get().unwrap().to_OidPrice());
+                keys.push(pool.get(key).unwrap().to_OidPrice());
             }
         }
         let mut measure = Measure::start("orderbook remove bench");
@@ -1,5 +1,8 @@
 use std::collections::HashMap;
 use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use crate::EngineError;
 
 #[derive(PartialEq)]
 #[non_exhaustive]
@@ -9,12 +12,26 @@ pub struct Symbol {
     market: u16,     // list on exchange market
     _state:  u8,
     digits: i8,
-    _vol_min:    u32,
-    _vol_max:    u32,
+    vol_min:    u32,
+    vol_max:    u32,
     lot_size:   u32,
     _vol_step:   u32,
     price_step: u32,
-    _turnover_mul:   u32,
+    turnover_mul:   u32,   // contract size, turnover per price unit
+    limit_down: i32,    // daily price limits around settlement price
+    limit_up:   i32,
+}
+
+// order reject reason of symbol reference data
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[repr(u8)]
+pub enum Reject {
+    UnknownSymbol,
+    PriceTick,      // price not multiple of price step
+    QtyMin,
+    QtyMax,
+    QtyLot,         // qty not multiple of lot size
+    PriceLimit,     // price outside limit down/up
 }
 
 pub struct Symbols {
@@ -24,6 +41,22 @@ pub struct Symbols {
 }
 
 impl Symbol {
+    // no trading limits w/o reference data, unlike default spec
+    pub fn new(name: &str) -> Symbol {
+        Symbol { name: name.to_string(), ..Default::default() }
+            .with_spec(0, 1, 1, 1, u32::MAX)
+    }
+    // price_step and lot_size must be non zero, order checks divide by
+    pub fn with_spec(self, digits: i8, price_step: u32, lot_size: u32,
+                     vol_min: u32, vol_max: u32) -> Symbol {
+        assert!(price_step > 0 && lot_size > 0,
+                "symbol {} zero price_step {} or lot_size {}", self.name,
+                price_step, lot_size);
+        Symbol { digits, price_step, lot_size, vol_min, vol_max, ..self }
+    }
+    pub fn with_limits(self, limit_down: i32, limit_up: i32) -> Symbol {
+        Symbol { limit_down, limit_up, ..self }
+    }
     pub fn name(&self) -> &str {
         &self.name
     }
@@ -33,13 +66,66 @@ impl Symbol {
     pub fn digits(&self) ->i8 {
         self.digits
     }
+    pub fn price_step(&self) -> u32 {
+        self.price_step
+    }
+    pub fn lot_size(&self) -> u32 {
+        self.lot_size
+    }
+    pub fn vol_min(&self) -> u32 {
+        self.vol_min
+    }
+    pub fn vol_max(&self) -> u32 {
+        self.vol_max
+    }
+    pub fn turnover_mul(&self) -> u32 {
+        self.turnover_mul
+    }
+    // (limit down, limit up)
+    pub fn limits(&self) -> (i32, i32) {
+        (self.limit_down, self.limit_up)
+    }
+    // price on tick grid, market order w/o price skip check
+    pub fn check_price(&self, price: i32) -> Result<(), Reject> {
+        if price % self.price_step as i32 != 0 {
+            return Err(Reject::PriceTick)
+        }
+        if price < self.limit_down || price > self.limit_up {
+            return Err(Reject::PriceLimit)
+        }
+        Ok(())
+    }
+    pub fn check_qty(&self, qty: u32) -> Result<(), Reject> {
+        if qty < self.vol_min {
+            Err(Reject::QtyMin)
+        } else if qty > self.vol_max {
+            Err(Reject::QtyMax)
+        } else if qty % self.lot_size != 0 {
+            Err(Reject::QtyLot)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl fmt::Display for Reject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Reject::UnknownSymbol => write!(f, "unknown symbol"),
+            Reject::PriceTick => write!(f, "price off tick"),
+            Reject::QtyMin => write!(f, "qty below minimum"),
+            Reject::QtyMax => write!(f, "qty above maximum"),
+            Reject::QtyLot => write!(f, "qty not multiple of lot"),
+            Reject::PriceLimit => write!(f, "price outside limits"),
+        }
+    }
 }
 
 impl fmt::Display for Symbol {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "name:{} id:{} market({}) digits({}) lot({}) price_step({})",
-                self.name, self.idx, self.market, self.digits,
-                self.lot_size, self.price_step)
+        write!(f, "name:{} id:{} market({}) digits({}) lot({}) price_step({}) \
+                vol({}..{})", self.name, self.idx, self.market, self.digits,
+                self.lot_size, self.price_step, self.vol_min, self.vol_max)
     }
 }
 
@@ -47,13 +133,22 @@ impl Default for Symbol {
     fn default() -> Self {
         Symbol {
             name: String::new(), idx: 0,
-            market: 1, _state: 1, digits: 0, _vol_min: 1,
-            _vol_max: 2000, lot_size: 5, _vol_step: 1,
-            price_step: 10, _turnover_mul: 5,
+            market: 1, _state: 1, digits: 0, vol_min: 1,
+            vol_max: 2000, lot_size: 5, _vol_step: 1,
+            price_step: 10, turnover_mul: 5,
+            limit_down: i32::MIN, limit_up: i32::MAX,
         }
     }
 }
 
+fn parse_field<T: std::str::FromStr>(v: Option<&str>, lno: usize, fld: &str)
+-> io::Result<T> {
+    v.and_then(|s| s.trim().parse().ok()).ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData,
+                       format!("line {}: bad {}", lno, fld))
+    })
+}
+
 const MAX_SYMBOLS: u32 = 1_000_000;
 
 impl Symbols {
@@ -74,29 +169,96 @@ impl Symbols {
     pub fn get_symbol(&self, idx: u32) -> Option<&Symbol> {
         self.id_map.get(&idx)
     }
-    pub fn add_symbol(&mut self, name: &str) {
-        if self.name_map.get(name) != None { return }
-        if self.ids >= MAX_SYMBOLS { return }
+    pub fn add_symbol(&mut self, name: &str) -> Result<u32, EngineError> {
+        self.add_spec(Symbol::new(name))
+    }
+    // add symbol with reference data, idx assigned in order
+    pub fn add_spec(&mut self, sym: Symbol) -> Result<u32, EngineError> {
+        if self.name_map.contains_key(&sym.name) {
+            return Err(EngineError::DuplicateSymbol)
+        }
+        if self.ids >= MAX_SYMBOLS {
+            return Err(EngineError::SymbolsFull)
+        }
         self.ids += 1;
-        let sym = Symbol { idx: self.ids,
-                    name: name.to_string(),
-                    ..Default::default() };
-        self.name_map.insert(name.to_string(), self.ids);
+        let sym = Symbol { idx: self.ids, ..sym };
+        self.name_map.insert(sym.name.clone(), self.ids);
         self.id_map.insert(self.ids, sym);
+        Ok(self.ids)
+    }
+    // update price limits intraday, e.g. limits widen after limit locked
+    pub fn set_limits(&mut self, idx: u32, limit_down: i32, limit_up: i32)
+    -> Result<(), EngineError> {
+        if limit_down > limit_up {
+            return Err(EngineError::InvalidLimits)
+        }
+        let sym = self.id_map.get_mut(&idx).ok_or(EngineError::UnknownSymbol)?;
+        sym.limit_down = limit_down;
+        sym.limit_up = limit_up;
+        Ok(())
+    }
+    // instrument master csv, header line and '#' comments skipped
+    // name,digits,price_step,lot_size,vol_min,vol_max[,limit_down,limit_up]
+    pub fn load_csv(filen: &str) -> io::Result<Symbols> {
+        Symbols::read_csv(BufReader::new(File::open(filen)?))
+    }
+    pub fn read_csv<R: BufRead>(rdr: R) -> io::Result<Symbols> {
+        let mut syms = Symbols::new();
+        for (lno, aline) in rdr.lines().enumerate() {
+            let aline = aline?;
+            let aline = aline.trim();
+            if aline.is_empty() || aline.starts_with('#') ||
+                aline.starts_with("name,") {
+                continue
+            }
+            let lno = lno + 1;
+            let mut it = aline.split(',');
+            let name = it.next().unwrap_or("").trim();
+            if name.is_empty() {
+                return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                format!("line {}: bad symbol name", lno)))
+            }
+            let digits = parse_field(it.next(), lno, "digits")?;
+            let price_step: u32 = parse_field(it.next(), lno, "price_step")?;
+            let lot_size: u32 = parse_field(it.next(), lno, "lot_size")?;
+            let vol_min = parse_field(it.next(), lno, "vol_min")?;
+            let vol_max = parse_field(it.next(), lno, "vol_max")?;
+            let (limit_down, limit_up) = match it.next() {
+                Some(v) => (parse_field(Some(v), lno, "limit_down")?,
+                            parse_field(it.next(), lno, "limit_up")?),
+                None => (i32::MIN, i32::MAX),
+            };
+            if price_step == 0 || lot_size == 0 || vol_min > vol_max ||
+                limit_down > limit_up {
+                return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                format!("line {}: bad trading limits", lno)))
+            }
+            let sym = Symbol::new(name).with_spec(digits, price_step, lot_size,
+                                                  vol_min, vol_max)
+                                       .with_limits(limit_down, limit_up);
+            syms.add_spec(sym).map_err(|e| {
+                io::Error::new(io::ErrorKind::InvalidData,
+                               format!("line {}: {} {}", lno, name, e))
+            })?;
+        }
+        Ok(syms)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Symbols;
+    use super::{Symbol, Symbols, Reject};
+    use crate::EngineError;
 
     #[test]
     fn symbols_test() {
         let mut syms = Symbols::new();
-        syms.add_symbol("cu1906");
-        syms.add_symbol("cu1909");
-        syms.add_symbol("cu1908");
-        syms.add_symbol("cu1912");
+        assert_eq!(syms.add_symbol("cu1906"), Ok(1));
+        assert_eq!(syms.add_symbol("cu1909"), Ok(2));
+        assert_eq!(syms.add_symbol("cu1908"), Ok(3));
+        assert_eq!(syms.add_symbol("cu1912"), Ok(4));
+        assert_eq!(syms.add_symbol("cu1908"),
+                   Err(EngineError::DuplicateSymbol));
         let idx = syms.get_idx("cu1906").unwrap();
         let res = syms.get_symbol(idx);
         assert!(res != None, "symbol not found");
@@ -106,5 +268,63 @@ mod tests {
         assert!(res != None, "symbol not found");
         assert_eq!(res.unwrap().name(), "cu1908");
         assert!(syms.get_symbol(syms.end_idx()) == None);
+        assert!(res.unwrap().check_qty(7).is_ok());
+        // default spec kept, added symbol w/o limits
+        let sym = Symbol::default();
+        assert_eq!((sym.price_step(), sym.lot_size(), sym.vol_max()),
+                   (10, 5, 2000));
+        assert_eq!(sym.check_qty(7), Err(Reject::QtyLot));
+    }
+
+    #[test]
+    #[should_panic(expected = "zero price_step")]
+    fn symbol_zero_step() {
+        let _ = Symbol::new("cu1906").with_spec(0, 0, 1, 1, 1000);
+    }
+
+    #[test]
+    fn symbols_csv() {
+        let csv = "name,digits,price_step,lot_size,vol_min,vol_max\n\
+                   # copper\n\
+                   cu1906,0,10,5,5,1000\n\
+                   al1906, 0, 5, 1, 1, 500\n";
+        let syms = Symbols::read_csv(csv.as_bytes()).unwrap();
+        assert_eq!(syms.end_idx(), 3);
+        let sym = syms.get_symbol(syms.get_idx("al1906").unwrap()).unwrap();
+        assert_eq!((sym.idx(), sym.price_step(), sym.vol_max()), (2, 5, 500));
+        let sym = syms.get_symbol(1).unwrap();
+        assert_eq!(sym.check_price(43510), Ok(()));
+        assert_eq!(sym.check_price(43515), Err(Reject::PriceTick));
+        assert_eq!(sym.check_price(-43510), Ok(()));
+        assert_eq!(sym.check_qty(0), Err(Reject::QtyMin));
+        assert_eq!(sym.check_qty(1005), Err(Reject::QtyMax));
+        assert_eq!(sym.check_qty(12), Err(Reject::QtyLot));
+        assert_eq!(sym.check_qty(1000), Ok(()));
+        assert!(Symbols::read_csv("cu1906,0,10,5\n".as_bytes()).is_err());
+        assert!(Symbols::read_csv("cu1906,0,0,5,5,10\n".as_bytes()).is_err());
+        assert!(Symbols::read_csv("cu1906,0,10,5,50,10\n".as_bytes())
+                    .is_err());
+        assert!(Symbols::read_csv("a,0,1,1,1,1\na,0,1,1,1,1\n".as_bytes())
+                    .is_err());
+    }
+
+    #[test]
+    fn symbols_limits() {
+        let csv = "cu1906,0,10,5,5,1000,41000,45000\ncu1908,0,10,5,5,1000\n";
+        let mut syms = Symbols::read_csv(csv.as_bytes()).unwrap();
+        let sym = syms.get_symbol(1).unwrap();
+        assert_eq!(sym.limits(), (41000, 45000));
+        assert_eq!(sym.check_price(45000), Ok(()));
+        assert_eq!(sym.check_price(45010), Err(Reject::PriceLimit));
+        assert_eq!(sym.check_price(40990), Err(Reject::PriceLimit));
+        assert_eq!(syms.get_symbol(2).unwrap().limits(), (i32::MIN, i32::MAX));
+        assert!(syms.set_limits(1, 40000, 46000).is_ok());
+        assert_eq!(syms.set_limits(1, 46000, 40000),
+                   Err(EngineError::InvalidLimits));
+        assert_eq!(syms.set_limits(3, 40000, 46000),
+                   Err(EngineError::UnknownSymbol));
+        assert_eq!(syms.get_symbol(1).unwrap().check_price(45010), Ok(()));
+        assert!(Symbols::read_csv("cu1906,0,10,5,5,1000,41000\n".as_bytes())
+                    .is_err());
     }
 }
@@ -23,6 +23,8 @@ mod x86 {
     static mut DUR_TEST: u64 = 0;
     static mut TICKS_TEST: u64 = 1;
     static mut TICKS_OVERHEAD: u64 = 1_000_000;
+    // cpuid once, costly in VMs
+    static mut HAS_RDTSCP: bool = false;
     use std::fmt;
     use std::time::{Instant, Duration};
     use super::duration_as_ns;
@@ -48,7 +50,8 @@ mod x86 {
     }
 
     fn calibration() {
-        if ! check_rdtscp() { return }
+        unsafe { HAS_RDTSCP = check_rdtscp(); }
+        if unsafe { ! HAS_RDTSCP } { return }
         // calibrication overhead
         for _i in 0 .. 100 {
             let start = rdtscp();
@@ -80,9 +83,11 @@ mod x86 {
         format!("rdtscp ticks {:.3} per ns", ticks_us)
     }
 
+    // falls back to clock w/o rdtscp
     pub struct MeasureTsc {
         name: &'static str,
         start: u64,
+        clock: Option<Instant>,
         duration: u64,
     }
     impl MeasureTsc {
@@ -90,16 +95,26 @@ mod x86 {
             INIT.call_once(|| {
                 calibration();
             });
-            assert!(check_rdtscp(), "rdtscp NOT SUPPORTED!");
+            if unsafe { ! HAS_RDTSCP } {
+                return Self { name, start: 0, clock: Some(Instant::now()),
+                              duration: 0 }
+            }
             Self {
                 name,
                 start: rdtscp(),
+                clock: None,
                 duration: 0,
             }
         }
         pub fn stop(&mut self) {
+            if let Some(clock) = self.clock {
+                self.duration = duration_as_ns(&clock.elapsed());
+                return
+            }
             self.duration = unsafe {
-                (rdtscp() - self.start - TICKS_OVERHEAD)
+                // start may be stamped on other core
+                rdtscp().saturating_sub(self.start)
+                    .saturating_sub(TICKS_OVERHEAD)
                     .saturating_mul(DUR_TEST)
                     / TICKS_TEST
             }
@@ -249,8 +264,8 @@ mod tests {
         if super::x86::check_rdtscp() {
             println!("tsc status: {}", tsc_status());
         } else {
+            // clock fallback
             println!("NO rdtscp SUPPORT");
-            return
         }
         let mut measure = MeasureTsc::start("test");
         sleep(Duration::from_secs(1));